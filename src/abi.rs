@@ -0,0 +1,1146 @@
+// Solidity ABI: types, human-readable signatures, encoding and decoding.
+
+use std::fmt;
+
+use primitive_types::{H160, H256, U256};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::hexutil::{hex, parse_h160, parse_hex, parse_u256};
+use crate::machine::{keccak256, ExecutionResult, LogEntry};
+use crate::trie::u256_bytes;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AbiError {
+    #[error("invalid type: {0}")]
+    Type(String),
+    #[error("invalid signature: {0}")]
+    Signature(String),
+    #[error("invalid {ty} value: {value}")]
+    Value { ty: String, value: String },
+    #[error("expected {expected} arguments, got {got}")]
+    ArgCount { expected: usize, got: usize },
+    #[error("cannot decode {0}")]
+    Decode(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbiType {
+    Uint(usize),
+    Int(usize),
+    Address,
+    Bool,
+    FixedBytes(usize),
+    Bytes,
+    String,
+    Array(Box<AbiType>),
+    FixedArray(Box<AbiType>, usize),
+    Tuple(Vec<AbiType>),
+}
+
+impl AbiType {
+    // Canonical type names ("uint256", "(address,bytes)[2]"); `uint`/`int`
+    // mean 256 bits and tuple members may carry names.
+    pub fn parse(s: &str) -> Result<Self, AbiError> {
+        let s = s.trim();
+        let bad = || AbiError::Type(s.to_string());
+        if let Some(rest) = s.strip_suffix(']') {
+            let open = rest.rfind('[').ok_or_else(bad)?;
+            let inner = Box::new(Self::parse(&rest[..open])?);
+            return match &rest[open + 1..] {
+                "" => Ok(AbiType::Array(inner)),
+                n => Ok(AbiType::FixedArray(inner, n.parse().map_err(|_| bad())?)),
+            };
+        }
+        let s = s.strip_prefix("tuple").unwrap_or(s);
+        if let Some(inner) = s.strip_prefix('(').and_then(|r| r.strip_suffix(')')) {
+            let params = split_top(inner)
+                .into_iter()
+                .map(|p| Param::parse(p).map(|p| p.ty))
+                .collect::<Result<_, _>>()?;
+            return Ok(AbiType::Tuple(params));
+        }
+        let sized = |prefix: &str, max: usize, step: usize| -> Option<usize> {
+            let n: usize = s.strip_prefix(prefix)?.parse().ok()?;
+            (n > 0 && n <= max && n.is_multiple_of(step)).then_some(n)
+        };
+        Ok(match s {
+            "address" => AbiType::Address,
+            "bool" => AbiType::Bool,
+            "string" => AbiType::String,
+            "bytes" => AbiType::Bytes,
+            "uint" => AbiType::Uint(256),
+            "int" => AbiType::Int(256),
+            _ if s.starts_with("uint") => AbiType::Uint(sized("uint", 256, 8).ok_or_else(bad)?),
+            _ if s.starts_with("int") => AbiType::Int(sized("int", 256, 8).ok_or_else(bad)?),
+            _ if s.starts_with("bytes") => {
+                AbiType::FixedBytes(sized("bytes", 32, 1).ok_or_else(bad)?)
+            }
+            _ => return Err(bad()),
+        })
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        match self {
+            AbiType::Bytes | AbiType::String | AbiType::Array(_) => true,
+            AbiType::FixedArray(inner, _) => inner.is_dynamic(),
+            AbiType::Tuple(types) => types.iter().any(|t| t.is_dynamic()),
+            _ => false,
+        }
+    }
+
+    // Bytes the type takes in the head of an enclosing tuple
+    fn head_size(&self) -> usize {
+        match self {
+            _ if self.is_dynamic() => 32,
+            AbiType::FixedArray(inner, n) => inner.head_size() * n,
+            AbiType::Tuple(types) => types.iter().map(|t| t.head_size()).sum(),
+            _ => 32,
+        }
+    }
+}
+
+impl fmt::Display for AbiType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbiType::Uint(n) => write!(f, "uint{n}"),
+            AbiType::Int(n) => write!(f, "int{n}"),
+            AbiType::Address => write!(f, "address"),
+            AbiType::Bool => write!(f, "bool"),
+            AbiType::FixedBytes(n) => write!(f, "bytes{n}"),
+            AbiType::Bytes => write!(f, "bytes"),
+            AbiType::String => write!(f, "string"),
+            AbiType::Array(inner) => write!(f, "{inner}[]"),
+            AbiType::FixedArray(inner, n) => write!(f, "{inner}[{n}]"),
+            AbiType::Tuple(types) => write!(f, "({})", join(types, ",")),
+        }
+    }
+}
+
+// One function, error or event parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub ty: AbiType,
+    pub indexed: bool,
+}
+
+impl Param {
+    // "uint256", "address indexed from", "(uint256 a, bytes b)[] memory items"
+    pub fn parse(s: &str) -> Result<Self, AbiError> {
+        let s = s.trim();
+        // The type ends at the first space outside parentheses
+        let mut depth = 0usize;
+        let end = s
+            .char_indices()
+            .find(|(_, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                depth == 0 && c.is_whitespace()
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(s.len());
+        let mut param = Param {
+            name: String::new(),
+            ty: AbiType::parse(&s[..end])?,
+            indexed: false,
+        };
+        for word in s[end..].split_whitespace() {
+            match word {
+                "indexed" => param.indexed = true,
+                "memory" | "calldata" | "storage" | "payable" => {}
+                name => param.name = name.to_string(),
+            }
+        }
+        Ok(param)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub inputs: Vec<Param>,
+    pub outputs: Vec<Param>,
+}
+
+impl Signature {
+    // "transfer(address,uint256)" or the Solidity-style
+    // "function transfer(address to, uint256 amount) external returns (bool)"
+    pub fn parse(s: &str) -> Result<Self, AbiError> {
+        let bad = || AbiError::Signature(s.to_string());
+        let t = s.trim();
+        let t = ["function ", "error ", "event "]
+            .iter()
+            .find_map(|p| t.strip_prefix(p))
+            .unwrap_or(t)
+            .trim();
+        let open = t.find('(').ok_or_else(bad)?;
+        let name = t[..open].trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        {
+            return Err(bad());
+        }
+        let close = matching_paren(t, open).ok_or_else(bad)?;
+        let inputs = parse_params(&t[open + 1..close])?;
+        let rest = t[close + 1..].trim();
+        let outputs = match rest.find("returns") {
+            Some(i) => {
+                let r = rest[i + "returns".len()..].trim();
+                let close = r
+                    .starts_with('(')
+                    .then(|| matching_paren(r, 0))
+                    .flatten()
+                    .ok_or_else(bad)?;
+                parse_params(&r[1..close])?
+            }
+            None => Vec::new(),
+        };
+        Ok(Signature {
+            name: name.to_string(),
+            inputs,
+            outputs,
+        })
+    }
+
+    // "transfer(address,uint256)"
+    pub fn canonical(&self) -> String {
+        let types: Vec<String> = self.inputs.iter().map(|p| p.ty.to_string()).collect();
+        format!("{}({})", self.name, types.join(","))
+    }
+
+    pub fn selector(&self) -> [u8; 4] {
+        let hash = keccak256(self.canonical().as_bytes());
+        [hash[0], hash[1], hash[2], hash[3]]
+    }
+
+    pub fn input_types(&self) -> Vec<AbiType> {
+        self.inputs.iter().map(|p| p.ty.clone()).collect()
+    }
+
+    pub fn output_types(&self) -> Vec<AbiType> {
+        self.outputs.iter().map(|p| p.ty.clone()).collect()
+    }
+
+    // Calldata from textual arguments, one per input
+    pub fn encode_call(&self, args: &[String]) -> Result<Vec<u8>, AbiError> {
+        if args.len() != self.inputs.len() {
+            return Err(AbiError::ArgCount {
+                expected: self.inputs.len(),
+                got: args.len(),
+            });
+        }
+        let values = self
+            .inputs
+            .iter()
+            .zip(args)
+            .map(|(p, a)| AbiValue::parse(&p.ty, a))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut out = self.selector().to_vec();
+        out.extend(encode(&values));
+        Ok(out)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbiValue {
+    Uint(U256, usize),
+    // Two's complement
+    Int(U256, usize),
+    Address(H160),
+    Bool(bool),
+    FixedBytes(Vec<u8>),
+    Bytes(Vec<u8>),
+    String(String),
+    Array(AbiType, Vec<AbiValue>),
+    FixedArray(Vec<AbiValue>),
+    Tuple(Vec<AbiValue>),
+}
+
+impl AbiValue {
+    // Textual argument: decimal or 0x numbers (ints may be negative), true/false,
+    // hex bytes, strings (optionally quoted), [a,b] arrays and (a,b) tuples
+    pub fn parse(ty: &AbiType, s: &str) -> Result<Self, AbiError> {
+        let s = s.trim();
+        let bad = || AbiError::Value {
+            ty: ty.to_string(),
+            value: s.to_string(),
+        };
+        Ok(match ty {
+            AbiType::Uint(bits) => {
+                let v = parse_u256(s).ok_or_else(bad)?;
+                if *bits < 256 && v.bits() > *bits {
+                    return Err(bad());
+                }
+                AbiValue::Uint(v, *bits)
+            }
+            AbiType::Int(bits) => {
+                let (neg, digits) = match s.strip_prefix('-') {
+                    Some(d) => (true, d),
+                    None => (false, s),
+                };
+                let mag = parse_u256(digits).ok_or_else(bad)?;
+                let limit = U256::one() << (bits - 1);
+                if (neg && mag > limit) || (!neg && mag >= limit) {
+                    return Err(bad());
+                }
+                let v = if neg {
+                    (!mag).overflowing_add(U256::one()).0
+                } else {
+                    mag
+                };
+                AbiValue::Int(v, *bits)
+            }
+            AbiType::Address => AbiValue::Address(parse_h160(s).ok_or_else(bad)?),
+            AbiType::Bool => match s {
+                "true" => AbiValue::Bool(true),
+                "false" => AbiValue::Bool(false),
+                _ => return Err(bad()),
+            },
+            AbiType::FixedBytes(n) => {
+                let b = parse_hex(s).filter(|b| b.len() == *n).ok_or_else(bad)?;
+                AbiValue::FixedBytes(b)
+            }
+            AbiType::Bytes => AbiValue::Bytes(parse_hex(s).ok_or_else(bad)?),
+            AbiType::String => {
+                let unquoted = s.strip_prefix('"').and_then(|r| r.strip_suffix('"'));
+                AbiValue::String(unquoted.unwrap_or(s).to_string())
+            }
+            AbiType::Array(inner) | AbiType::FixedArray(inner, _) => {
+                let body = s
+                    .strip_prefix('[')
+                    .and_then(|r| r.strip_suffix(']'))
+                    .ok_or_else(bad)?;
+                let items = split_top(body)
+                    .into_iter()
+                    .map(|item| AbiValue::parse(inner, item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match ty {
+                    AbiType::FixedArray(_, n) if items.len() != *n => return Err(bad()),
+                    AbiType::FixedArray(..) => AbiValue::FixedArray(items),
+                    _ => AbiValue::Array((**inner).clone(), items),
+                }
+            }
+            AbiType::Tuple(types) => {
+                let body = s
+                    .strip_prefix('(')
+                    .and_then(|r| r.strip_suffix(')'))
+                    .ok_or_else(bad)?;
+                let items = split_top(body);
+                if items.len() != types.len() {
+                    return Err(bad());
+                }
+                let values = types
+                    .iter()
+                    .zip(items)
+                    .map(|(t, item)| AbiValue::parse(t, item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                AbiValue::Tuple(values)
+            }
+        })
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            AbiValue::Bytes(_) | AbiValue::String(_) | AbiValue::Array(..) => true,
+            AbiValue::FixedArray(items) | AbiValue::Tuple(items) => {
+                items.iter().any(|v| v.is_dynamic())
+            }
+            _ => false,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            AbiValue::Uint(..) | AbiValue::Int(..) => self.to_string().into(),
+            AbiValue::Address(a) => format!("0x{}", hex(a.as_bytes())).into(),
+            AbiValue::Bool(b) => json!(b),
+            AbiValue::FixedBytes(b) | AbiValue::Bytes(b) => format!("0x{}", hex(b)).into(),
+            AbiValue::String(s) => s.clone().into(),
+            AbiValue::Array(_, items) | AbiValue::FixedArray(items) | AbiValue::Tuple(items) => {
+                items.iter().map(|v| v.to_json()).collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for AbiValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbiValue::Uint(v, _) => write!(f, "{v}"),
+            AbiValue::Int(v, _) if v.bit(255) => {
+                write!(f, "-{}", (!*v).overflowing_add(U256::one()).0)
+            }
+            AbiValue::Int(v, _) => write!(f, "{v}"),
+            AbiValue::Address(a) => write!(f, "0x{}", hex(a.as_bytes())),
+            AbiValue::Bool(b) => write!(f, "{b}"),
+            AbiValue::FixedBytes(b) | AbiValue::Bytes(b) => write!(f, "0x{}", hex(b)),
+            AbiValue::String(s) => write!(f, "{s:?}"),
+            AbiValue::Array(_, items) | AbiValue::FixedArray(items) => {
+                write!(f, "[{}]", join(items, ", "))
+            }
+            AbiValue::Tuple(items) => write!(f, "({})", join(items, ", ")),
+        }
+    }
+}
+
+// Functions, custom errors and events of a contract ABI
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Abi {
+    pub functions: Vec<Signature>,
+    pub errors: Vec<Signature>,
+    pub events: Vec<Event>,
+}
+
+impl Abi {
+    // A JSON ABI array, or an artifact object with an "abi" field (Hardhat, Foundry)
+    pub fn from_json(v: &Value) -> Result<Self, AbiError> {
+        let items = v
+            .as_array()
+            .or_else(|| v.get("abi").and_then(|a| a.as_array()))
+            .ok_or_else(|| AbiError::Signature("expected a JSON ABI array".into()))?;
+        let mut abi = Abi::default();
+        for item in items {
+            let kind = item
+                .get("type")
+                .and_then(|t| t.as_str())
+                .unwrap_or("function");
+            let signature = Signature {
+                name: item["name"].as_str().unwrap_or_default().to_string(),
+                inputs: json_params(&item["inputs"])?,
+                outputs: json_params(&item["outputs"])?,
+            };
+            match kind {
+                "function" => abi.functions.push(signature),
+                "error" => abi.errors.push(signature),
+                "event" => abi.events.push(Event {
+                    signature,
+                    anonymous: item["anonymous"].as_bool().unwrap_or(false),
+                }),
+                _ => {}
+            }
+        }
+        Ok(abi)
+    }
+
+    pub fn extend(&mut self, other: Abi) {
+        self.functions.extend(other.functions);
+        self.errors.extend(other.errors);
+        self.events.extend(other.events);
+    }
+}
+
+fn json_params(v: &Value) -> Result<Vec<Param>, AbiError> {
+    let Some(items) = v.as_array() else {
+        return Ok(Vec::new());
+    };
+    items
+        .iter()
+        .map(|p| {
+            Ok(Param {
+                name: p["name"].as_str().unwrap_or_default().to_string(),
+                ty: json_type(p)?,
+                indexed: p["indexed"].as_bool().unwrap_or(false),
+            })
+        })
+        .collect()
+}
+
+// "tuple", "tuple[]", ... take their members from "components"
+fn json_type(p: &Value) -> Result<AbiType, AbiError> {
+    let ty = p["type"].as_str().unwrap_or_default();
+    match ty.strip_prefix("tuple") {
+        Some(suffix) => {
+            let members: Vec<String> = json_params(&p["components"])?
+                .iter()
+                .map(|m| m.ty.to_string())
+                .collect();
+            AbiType::parse(&format!("({}){suffix}", members.join(",")))
+        }
+        None => AbiType::parse(ty),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub signature: Signature,
+    // Anonymous events have no topic0, so they can only be matched by shape
+    pub anonymous: bool,
+}
+
+impl Event {
+    // "event Transfer(address indexed from, address indexed to, uint256 value)",
+    // optionally followed by "anonymous"
+    pub fn parse(s: &str) -> Result<Self, AbiError> {
+        let t = s.trim();
+        let (t, anonymous) = match t.strip_suffix("anonymous") {
+            Some(rest) if rest.trim_end().ends_with(')') => (rest, true),
+            _ => (t, false),
+        };
+        Ok(Event {
+            signature: Signature::parse(t)?,
+            anonymous,
+        })
+    }
+
+    // topic0 of the event's logs
+    pub fn topic(&self) -> H256 {
+        keccak256(self.signature.canonical().as_bytes())
+    }
+
+    // Decodes `topics` and `data` if they fit this event
+    pub fn decode(&self, topics: &[H256], data: &[u8]) -> Option<Vec<AbiValue>> {
+        let topics = match self.anonymous {
+            true => topics,
+            false => topics.strip_prefix(&[self.topic()])?,
+        };
+        let inputs = &self.signature.inputs;
+        if topics.len() != inputs.iter().filter(|p| p.indexed).count() {
+            return None;
+        }
+        let body: Vec<AbiType> = inputs
+            .iter()
+            .filter(|p| !p.indexed)
+            .map(|p| p.ty.clone())
+            .collect();
+        let mut body = decode(&body, data).ok()?.into_iter();
+        let mut topics = topics.iter();
+        inputs
+            .iter()
+            .map(|p| match p.indexed {
+                false => body.next(),
+                true => {
+                    let topic = topics.next()?.as_bytes();
+                    match p.ty {
+                        // Indexed strings, bytes, arrays and tuples are stored as their hash
+                        AbiType::String
+                        | AbiType::Bytes
+                        | AbiType::Array(_)
+                        | AbiType::FixedArray(..)
+                        | AbiType::Tuple(_) => Some(AbiValue::FixedBytes(topic.to_vec())),
+                        _ => decode(std::slice::from_ref(&p.ty), topic).ok()?.pop(),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+// A log matched to one of the ABI's events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedLog {
+    pub address: H160,
+    pub event: Event,
+    // One per event input, in declaration order
+    pub values: Vec<AbiValue>,
+}
+
+impl DecodedLog {
+    pub fn to_json(&self) -> Value {
+        let args: Vec<Value> = self
+            .event
+            .signature
+            .inputs
+            .iter()
+            .zip(&self.values)
+            .map(|(p, v)| {
+                json!({
+                    "name": p.name,
+                    "type": p.ty.to_string(),
+                    "indexed": p.indexed,
+                    "value": v.to_json(),
+                })
+            })
+            .collect();
+        json!({
+            "address": format!("0x{}", hex(self.address.as_bytes())),
+            "event": self.event.signature.name,
+            "signature": self.event.signature.canonical(),
+            "args": args,
+        })
+    }
+}
+
+impl fmt::Display for DecodedLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let args: Vec<String> = self
+            .event
+            .signature
+            .inputs
+            .iter()
+            .zip(&self.values)
+            .map(|(p, v)| match p.name.as_str() {
+                "" => v.to_string(),
+                name => format!("{name}: {v}"),
+            })
+            .collect();
+        write!(f, "{}({})", self.event.signature.name, args.join(", "))
+    }
+}
+
+// Matches `log` against `events` by topic0, then against anonymous events by
+// their shape; the first event the topics and data decode under wins
+pub fn decode_log(log: &LogEntry, events: &[Event]) -> Option<DecodedLog> {
+    let topics: Vec<H256> = log.topics.iter().map(|t| H256(u256_bytes(*t))).collect();
+    let named = events.iter().filter(|e| !e.anonymous);
+    let anonymous = events.iter().filter(|e| e.anonymous);
+    named.chain(anonymous).find_map(|event| {
+        Some(DecodedLog {
+            address: log.address,
+            event: event.clone(),
+            values: event.decode(&topics, &log.data)?,
+        })
+    })
+}
+
+// Decoded revert data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevertReason {
+    // require(cond, "message") / revert("message")
+    Error(String),
+    // assert, arithmetic checks, bounds checks, ...
+    Panic(U256),
+    // A custom error from the given ABIs
+    Custom {
+        error: Signature,
+        values: Vec<AbiValue>,
+    },
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevertReason::Error(msg) => write!(f, "{msg}"),
+            RevertReason::Panic(code) => match panic_message(*code) {
+                Some(msg) => write!(f, "panic 0x{code:02x}: {msg}"),
+                None => write!(f, "panic 0x{code:02x}"),
+            },
+            RevertReason::Custom { error, values } => {
+                let args: Vec<String> = error
+                    .inputs
+                    .iter()
+                    .zip(values)
+                    .map(|(p, v)| match p.name.as_str() {
+                        "" => v.to_string(),
+                        name => format!("{name}: {v}"),
+                    })
+                    .collect();
+                write!(f, "{}({})", error.name, args.join(", "))
+            }
+        }
+    }
+}
+
+// Solidity's panic codes
+pub fn panic_message(code: U256) -> Option<&'static str> {
+    if code > U256::from(0xff) {
+        return None;
+    }
+    Some(match code.as_u32() {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "incorrectly encoded storage byte array",
+        0x31 => "pop() on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to a zero-initialized internal function",
+        _ => return None,
+    })
+}
+
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+// Error(string), Panic(uint256), or one of `errors` matched by selector
+pub fn decode_revert(output: &[u8], errors: &[Signature]) -> Option<RevertReason> {
+    let (selector, data) = (output.get(..4)?, &output[4..]);
+    if selector == ERROR_SELECTOR {
+        return match decode(&[AbiType::String], data).ok()?.pop()? {
+            AbiValue::String(msg) => Some(RevertReason::Error(msg)),
+            _ => None,
+        };
+    }
+    if selector == PANIC_SELECTOR {
+        return match decode(&[AbiType::Uint(256)], data).ok()?.pop()? {
+            AbiValue::Uint(code, _) => Some(RevertReason::Panic(code)),
+            _ => None,
+        };
+    }
+    errors
+        .iter()
+        .filter(|e| e.selector() == selector)
+        .find_map(|e| {
+            let values = decode(&e.input_types(), data).ok()?;
+            Some(RevertReason::Custom {
+                error: e.clone(),
+                values,
+            })
+        })
+}
+
+// Revert reason of an execution result, for `result.revert_reason(&errors)`
+pub trait DecodeRevert {
+    fn revert_reason(&self, errors: &[Signature]) -> Option<RevertReason>;
+}
+
+impl DecodeRevert for ExecutionResult {
+    fn revert_reason(&self, errors: &[Signature]) -> Option<RevertReason> {
+        match self {
+            ExecutionResult::Revert { output, .. } => decode_revert(output, errors),
+            _ => None,
+        }
+    }
+}
+
+// Encode `values` as a tuple, e.g. function arguments
+pub fn encode(values: &[AbiValue]) -> Vec<u8> {
+    let head_len: usize = values
+        .iter()
+        .map(|v| {
+            if v.is_dynamic() {
+                32
+            } else {
+                encode_value(v).len()
+            }
+        })
+        .sum();
+    let mut head = Vec::new();
+    let mut tail = Vec::new();
+    for v in values {
+        let enc = encode_value(v);
+        if v.is_dynamic() {
+            head.extend_from_slice(&u256_bytes(U256::from(head_len + tail.len())));
+            tail.extend(enc);
+        } else {
+            head.extend(enc);
+        }
+    }
+    head.extend(tail);
+    head
+}
+
+fn encode_value(v: &AbiValue) -> Vec<u8> {
+    match v {
+        AbiValue::Uint(x, _) | AbiValue::Int(x, _) => u256_bytes(*x).to_vec(),
+        AbiValue::Address(a) => {
+            let mut w = vec![0u8; 12];
+            w.extend_from_slice(a.as_bytes());
+            w
+        }
+        AbiValue::Bool(b) => u256_bytes(U256::from(*b as u8)).to_vec(),
+        AbiValue::FixedBytes(b) => padded(b),
+        AbiValue::Bytes(b) => {
+            let mut out = u256_bytes(U256::from(b.len())).to_vec();
+            out.extend(padded(b));
+            out
+        }
+        AbiValue::String(s) => encode_value(&AbiValue::Bytes(s.as_bytes().to_vec())),
+        AbiValue::Array(_, items) => {
+            let mut out = u256_bytes(U256::from(items.len())).to_vec();
+            out.extend(encode(items));
+            out
+        }
+        AbiValue::FixedArray(items) | AbiValue::Tuple(items) => encode(items),
+    }
+}
+
+fn padded(b: &[u8]) -> Vec<u8> {
+    let mut out = b.to_vec();
+    out.resize(b.len().div_ceil(32) * 32, 0);
+    out
+}
+
+// Decode `data` as a tuple of `types`, e.g. return data
+pub fn decode(types: &[AbiType], data: &[u8]) -> Result<Vec<AbiValue>, AbiError> {
+    decode_tuple(types, data, 0)
+}
+
+fn decode_tuple(types: &[AbiType], data: &[u8], base: usize) -> Result<Vec<AbiValue>, AbiError> {
+    let mut at = base;
+    let mut out = Vec::with_capacity(types.len());
+    for ty in types {
+        if ty.is_dynamic() {
+            let offset = read_usize(data, at, ty)?;
+            let start = base.checked_add(offset).ok_or_else(|| short(ty))?;
+            out.push(decode_value(ty, data, start)?);
+            at += 32;
+        } else {
+            out.push(decode_value(ty, data, at)?);
+            at += ty.head_size();
+        }
+    }
+    Ok(out)
+}
+
+fn decode_value(ty: &AbiType, data: &[u8], at: usize) -> Result<AbiValue, AbiError> {
+    let invalid = || AbiError::Decode(format!("{ty}: invalid value"));
+    Ok(match ty {
+        AbiType::Uint(bits) => {
+            let v = read_word(data, at, ty)?;
+            if *bits < 256 && v.bits() > *bits {
+                return Err(invalid());
+            }
+            AbiValue::Uint(v, *bits)
+        }
+        AbiType::Int(bits) => {
+            let v = read_word(data, at, ty)?;
+            if *bits < 256 && sign_extend(v, *bits) != v {
+                return Err(invalid());
+            }
+            AbiValue::Int(v, *bits)
+        }
+        AbiType::Address => {
+            let v = read_word(data, at, ty)?;
+            if v.bits() > 160 {
+                return Err(invalid());
+            }
+            AbiValue::Address(H160::from_slice(&u256_bytes(v)[12..]))
+        }
+        AbiType::Bool => {
+            let v = read_word(data, at, ty)?;
+            if v > U256::one() {
+                return Err(invalid());
+            }
+            AbiValue::Bool(!v.is_zero())
+        }
+        AbiType::FixedBytes(n) => {
+            let w = data.get(at..at + 32).ok_or_else(|| short(ty))?;
+            AbiValue::FixedBytes(w[..*n].to_vec())
+        }
+        AbiType::Bytes | AbiType::String => {
+            let len = read_usize(data, at, ty)?;
+            let start = at + 32;
+            let bytes = start
+                .checked_add(len)
+                .and_then(|end| data.get(start..end))
+                .ok_or_else(|| short(ty))?
+                .to_vec();
+            if *ty == AbiType::Bytes {
+                AbiValue::Bytes(bytes)
+            } else {
+                AbiValue::String(String::from_utf8(bytes).map_err(|_| invalid())?)
+            }
+        }
+        AbiType::Array(inner) => {
+            let len = read_usize(data, at, ty)?;
+            // Every element takes at least one word
+            if len > data.len() / 32 {
+                return Err(short(ty));
+            }
+            let items = decode_tuple(&vec![(**inner).clone(); len], data, at + 32)?;
+            AbiValue::Array((**inner).clone(), items)
+        }
+        AbiType::FixedArray(inner, n) => {
+            if *n > data.len() / 32 {
+                return Err(short(ty));
+            }
+            AbiValue::FixedArray(decode_tuple(&vec![(**inner).clone(); *n], data, at)?)
+        }
+        AbiType::Tuple(types) => AbiValue::Tuple(decode_tuple(types, data, at)?),
+    })
+}
+
+fn short(ty: &AbiType) -> AbiError {
+    AbiError::Decode(format!("{ty}: data too short"))
+}
+
+fn read_word(data: &[u8], at: usize, ty: &AbiType) -> Result<U256, AbiError> {
+    let end = at.checked_add(32).ok_or_else(|| short(ty))?;
+    let w = data.get(at..end).ok_or_else(|| short(ty))?;
+    Ok(U256::from_big_endian(w))
+}
+
+// Offsets and lengths
+fn read_usize(data: &[u8], at: usize, ty: &AbiType) -> Result<usize, AbiError> {
+    let v = read_word(data, at, ty)?;
+    if v > U256::from(data.len()) {
+        return Err(short(ty));
+    }
+    Ok(v.as_usize())
+}
+
+fn sign_extend(v: U256, bits: usize) -> U256 {
+    let mask = (U256::one() << bits) - 1;
+    let low = v & mask;
+    if low.bit(bits - 1) {
+        low | !mask
+    } else {
+        low
+    }
+}
+
+// Split on commas that are not inside (), [] or quotes
+fn split_top(s: &str) -> Vec<&str> {
+    if s.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut parts = Vec::new();
+    let (mut depth, mut quoted, mut start) = (0usize, false, 0usize);
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '[' if !quoted => depth += 1,
+            ')' | ']' if !quoted => depth = depth.saturating_sub(1),
+            ',' if !quoted && depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts
+}
+
+fn parse_params(s: &str) -> Result<Vec<Param>, AbiError> {
+    split_top(s).into_iter().map(Param::parse).collect()
+}
+
+// Index of the ')' closing the '(' at `open`
+fn matching_paren(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn join<T: fmt::Display>(items: &[T], sep: &str) -> String {
+    items
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{Account, Evm, EvmConfig, World};
+
+    #[test]
+    fn parses_signatures_and_selectors() {
+        let sig = Signature::parse("transfer(address,uint256)").unwrap();
+        assert_eq!(sig.selector(), [0xa9, 0x05, 0x9c, 0xbb]);
+        let sig = Signature::parse(
+            "function transfer(address to, uint amount) external returns (bool success)",
+        )
+        .unwrap();
+        assert_eq!(sig.canonical(), "transfer(address,uint256)");
+        assert_eq!(sig.inputs[0].name, "to");
+        assert_eq!(sig.output_types(), [AbiType::Bool]);
+        let sig = Signature::parse("f((uint256 a, bytes b)[2] memory xs, int8)").unwrap();
+        assert_eq!(sig.canonical(), "f((uint256,bytes)[2],int8)");
+        assert!(AbiType::parse("uint7").is_err());
+        assert!(AbiType::parse("bytes33").is_err());
+    }
+
+    #[test]
+    fn encodes_dynamic_types_like_solidity() {
+        // The example from the Solidity ABI spec: f(uint256,uint32[],bytes10,bytes)
+        let sig = Signature::parse("f(uint256,uint32[],bytes10,bytes)").unwrap();
+        let args: Vec<String> = [
+            "0x123",
+            "[0x456,0x789]",
+            "0x31323334353637383930",
+            "0x48656c6c6f2c20776f726c6421", // "Hello, world!"
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let data = sig.encode_call(&args).unwrap();
+        let expected = concat!(
+            "8be65246",
+            "0000000000000000000000000000000000000000000000000000000000000123",
+            "0000000000000000000000000000000000000000000000000000000000000080",
+            "3132333435363738393000000000000000000000000000000000000000000000",
+            "00000000000000000000000000000000000000000000000000000000000000e0",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000456",
+            "0000000000000000000000000000000000000000000000000000000000000789",
+            "000000000000000000000000000000000000000000000000000000000000000d",
+            "48656c6c6f2c20776f726c642100000000000000000000000000000000000000",
+        );
+        assert_eq!(hex(&data), expected);
+        let decoded = decode(&sig.input_types(), &data[4..]).unwrap();
+        assert_eq!(decoded[0].to_string(), "291");
+        assert_eq!(decoded[1].to_string(), "[1110, 1929]");
+        assert_eq!(decoded[3], AbiValue::Bytes(b"Hello, world!".to_vec()));
+    }
+
+    #[test]
+    fn decodes_revert_reasons() {
+        let mut data = ERROR_SELECTOR.to_vec();
+        data.extend(encode(&[AbiValue::String("nope".into())]));
+        let reason = decode_revert(&data, &[]).unwrap();
+        assert_eq!(reason, RevertReason::Error("nope".into()));
+        assert!(decode_revert(&data[..40], &[]).is_none());
+
+        let mut data = PANIC_SELECTOR.to_vec();
+        data.extend(encode(&[AbiValue::Uint(U256::from(0x11), 256)]));
+        assert_eq!(
+            decode_revert(&data, &[]).unwrap().to_string(),
+            "panic 0x11: arithmetic overflow or underflow"
+        );
+
+        let abi = Abi::from_json(&json!([
+            {"type": "function", "name": "f", "inputs": [], "outputs": []},
+            {"type": "error", "name": "Low", "inputs": [
+                {"name": "have", "type": "uint256"},
+                {"name": "want", "type": "tuple", "components": [{"name": "x", "type": "uint8"}]}
+            ]}
+        ]))
+        .unwrap();
+        assert_eq!(abi.errors[0].canonical(), "Low(uint256,(uint8))");
+        let mut data = abi.errors[0].selector().to_vec();
+        data.extend(encode(&[
+            AbiValue::Uint(U256::from(1), 256),
+            AbiValue::Tuple(vec![AbiValue::Uint(U256::from(2), 8)]),
+        ]));
+        assert_eq!(
+            decode_revert(&data, &abi.errors).unwrap().to_string(),
+            "Low(have: 1, want: (2))"
+        );
+        assert!(decode_revert(&data, &[]).is_none());
+    }
+
+    #[test]
+    fn decodes_logs_by_topic_and_shape() {
+        let transfer =
+            Event::parse("event Transfer(address indexed from, address indexed to, uint256 value)")
+                .unwrap();
+        assert_eq!(
+            hex(transfer.topic().as_bytes()),
+            "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+        let word = |b: u8| U256::from(b);
+        let log = LogEntry {
+            address: H160::repeat_byte(0xcc),
+            topics: vec![
+                U256::from_big_endian(transfer.topic().as_bytes()),
+                word(0xaa),
+                word(0xbb),
+            ],
+            data: u256_bytes(U256::from(100)).to_vec(),
+        };
+        let abi = Abi::from_json(&json!([
+            {"type": "event", "name": "Note", "anonymous": true, "inputs": [
+                {"name": "tag", "type": "string", "indexed": true},
+                {"name": "n", "type": "uint8", "indexed": false}
+            ]}
+        ]))
+        .unwrap();
+        let mut events = abi.events.clone();
+        events.push(transfer);
+        let decoded = decode_log(&log, &events).unwrap();
+        assert_eq!(
+            decoded.to_string(),
+            format!(
+                "Transfer(from: 0x{}, to: 0x{}, value: 100)",
+                "0".repeat(38) + "aa",
+                "0".repeat(38) + "bb"
+            )
+        );
+        assert_eq!(decoded.to_json()["args"][2]["value"], "100");
+        assert_eq!(decoded.to_json()["args"][0]["indexed"], true);
+
+        // One topic and a uint8 body only fits the anonymous Note
+        let note = LogEntry {
+            topics: vec![word(0x11)],
+            data: u256_bytes(U256::from(7)).to_vec(),
+            ..log.clone()
+        };
+        let decoded = decode_log(&note, &events).unwrap();
+        assert_eq!(decoded.event.signature.name, "Note");
+        assert_eq!(decoded.values[1], AbiValue::Uint(U256::from(7), 8));
+        assert_eq!(
+            decoded.values[0],
+            AbiValue::FixedBytes(u256_bytes(word(0x11)).to_vec())
+        );
+        let wrong_shape = LogEntry {
+            topics: log.topics[..2].to_vec(),
+            ..log
+        };
+        assert!(decode_log(&wrong_shape, &events).is_none());
+    }
+
+    #[test]
+    fn round_trips_nested_values() {
+        let ty = AbiType::parse("(int8,string[],(bool,address))").unwrap();
+        let text = format!("(-5,[\"a,b\",\"\"],(true,0x{}))", "11".repeat(20));
+        let value = AbiValue::parse(&ty, &text).unwrap();
+        let data = encode(std::slice::from_ref(&value));
+        let back = decode(&[ty], &data).unwrap();
+        assert_eq!(back[0], value);
+        assert_eq!(
+            value.to_string(),
+            format!("(-5, [\"a,b\", \"\"], (true, 0x{}))", "11".repeat(20))
+        );
+        assert_eq!(value.to_json()[0], "-5");
+        assert!(AbiValue::parse(&AbiType::Int(8), "128").is_err());
+        assert!(AbiValue::parse(&AbiType::Int(8), "-128").is_ok());
+        // Truncated and out-of-range data is rejected
+        assert!(decode(&[AbiType::Bytes], &data[..40]).is_err());
+        assert!(decode(&[AbiType::Uint(8)], &u256_bytes(U256::from(256))).is_err());
+    }
+
+    #[test]
+    fn decodes_revert_bubbled_up_from_a_call() {
+        let inner = H160::repeat_byte(0xbb);
+        let mut world = World::default();
+        // MSTORE(0, 0x4e487b71); MSTORE(0x20, 0x11); REVERT(0x1c, 0x24)
+        let code = vec![
+            0x63, 0x4e, 0x48, 0x7b, 0x71, 0x60, 0x00, 0x52, 0x60, 0x11, 0x60, 0x20, 0x52, 0x60,
+            0x24, 0x60, 0x1c, 0xfd,
+        ];
+        world.accounts.insert(
+            inner,
+            Account {
+                code,
+                ..Account::default()
+            },
+        );
+        // CALL(gas, inner, 0, 0, 0, 0, 0); POP; copy the returndata and revert with it
+        let mut code = vec![
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73,
+        ];
+        code.extend_from_slice(inner.as_bytes());
+        code.extend_from_slice(&[0x5a, 0xf1, 0x50, 0x3d, 0x60, 0x00, 0x60, 0x00, 0x3e]);
+        code.extend_from_slice(&[0x3d, 0x60, 0x00, 0xfd]);
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                world: Some(world),
+                ..EvmConfig::default()
+            },
+        );
+        let result = evm.run();
+        assert_eq!(
+            result.revert_reason(&[]),
+            Some(RevertReason::Panic(U256::from(0x11)))
+        );
+        assert!(ExecutionResult::Revert {
+            gas_used: 0,
+            output: vec![0x2a],
+        }
+        .revert_reason(&[])
+        .is_none());
+    }
+}
@@ -0,0 +1,389 @@
+// Static checks over the control-flow graph, for telling whether code is well
+// formed before running it.
+//
+// Stack heights are exact along each path the CFG explored from the entry, so
+// an underflow or overflow found for a height the block is entered with will
+// happen whenever execution takes that path. When some reachable jump has an
+// unknown target, any JUMPDEST may be reached through it, so unreachable code
+// and unused JUMPDESTs are only reported as far as that still allows.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::cfg::{BasicBlock, Cfg, EdgeKind};
+use crate::machine::STACK_LIMIT;
+use crate::opcodes::JUMPDEST;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Finding {
+    // Entering the block with `height` items, the instruction at `pc` needs `needed`
+    StackUnderflow {
+        pc: usize,
+        height: usize,
+        needed: usize,
+    },
+    StackOverflow {
+        pc: usize,
+        height: usize,
+    },
+    // Blocks no path from the entry reaches, as [start, end)
+    Unreachable {
+        start: usize,
+        end: usize,
+    },
+    // A JUMPDEST no jump targets
+    UnusedJumpdest {
+        pc: usize,
+    },
+    JumpIntoPushData {
+        pc: usize,
+        target: usize,
+    },
+    // A constant jump to something other than a JUMPDEST or PUSH data
+    InvalidJump {
+        pc: usize,
+        target: usize,
+    },
+    // A reachable jump whose target is not a constant
+    UnresolvedJump {
+        pc: usize,
+    },
+}
+
+impl Finding {
+    pub fn pc(&self) -> usize {
+        match *self {
+            Finding::StackUnderflow { pc, .. }
+            | Finding::StackOverflow { pc, .. }
+            | Finding::UnusedJumpdest { pc }
+            | Finding::JumpIntoPushData { pc, .. }
+            | Finding::InvalidJump { pc, .. }
+            | Finding::UnresolvedJump { pc } => pc,
+            Finding::Unreachable { start, .. } => start,
+        }
+    }
+
+    // Whether execution fails when it gets here, as opposed to a code smell
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            Finding::StackUnderflow { .. }
+                | Finding::StackOverflow { .. }
+                | Finding::JumpIntoPushData { .. }
+                | Finding::InvalidJump { .. }
+        )
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Finding::StackUnderflow { .. } => "stackUnderflow",
+            Finding::StackOverflow { .. } => "stackOverflow",
+            Finding::Unreachable { .. } => "unreachable",
+            Finding::UnusedJumpdest { .. } => "unusedJumpdest",
+            Finding::JumpIntoPushData { .. } => "jumpIntoPushData",
+            Finding::InvalidJump { .. } => "invalidJump",
+            Finding::UnresolvedJump { .. } => "unresolvedJump",
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut v = json!({"kind": self.kind(), "pc": self.pc()});
+        match *self {
+            Finding::StackUnderflow { height, needed, .. } => {
+                v["height"] = height.into();
+                v["needed"] = needed.into();
+            }
+            Finding::StackOverflow { height, .. } => v["height"] = height.into(),
+            Finding::Unreachable { end, .. } => v["end"] = end.into(),
+            Finding::JumpIntoPushData { target, .. } | Finding::InvalidJump { target, .. } => {
+                v["target"] = target.into()
+            }
+            Finding::UnusedJumpdest { .. } | Finding::UnresolvedJump { .. } => {}
+        }
+        v
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}: ", self.pc())?;
+        match *self {
+            Finding::StackUnderflow { height, needed, .. } => write!(
+                f,
+                "stack underflow: needs {needed} items, has {height} on some path"
+            ),
+            Finding::StackOverflow { height, .. } => {
+                write!(f, "stack overflow: {height} items on some path")
+            }
+            Finding::Unreachable { end, .. } => write!(f, "unreachable code up to {end:04x}"),
+            Finding::UnusedJumpdest { .. } => write!(f, "JUMPDEST is never a jump target"),
+            Finding::JumpIntoPushData { target, .. } => {
+                write!(f, "jump into PUSH data at {target:04x}")
+            }
+            Finding::InvalidJump { target, .. } => {
+                write!(f, "jump to {target:04x}, which is not a JUMPDEST")
+            }
+            Finding::UnresolvedJump { .. } => write!(f, "jump target is not a constant"),
+        }
+    }
+}
+
+// Stack use of one basic block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStack {
+    pub start: usize,
+    // Heights the block is entered with; empty when it is unreachable
+    pub heights: Vec<usize>,
+    // Items the block needs on entry to not underflow
+    pub needed: usize,
+    // Height change from entry to exit
+    pub delta: isize,
+    // Highest the stack gets above the entry height
+    pub max_growth: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub cfg: Cfg,
+    pub blocks: Vec<BlockStack>,
+    // Sorted by pc
+    pub findings: Vec<Finding>,
+}
+
+impl Analysis {
+    pub fn run(code: &[u8]) -> Analysis {
+        Analysis::from_cfg(Cfg::build(code))
+    }
+
+    pub fn from_cfg(cfg: Cfg) -> Analysis {
+        let mut findings = BTreeSet::new();
+        let blocks: Vec<BlockStack> = cfg.blocks.iter().map(|b| block_stack(&cfg, b)).collect();
+
+        for (block, stack) in cfg.blocks.iter().zip(&blocks) {
+            for &height in &stack.heights {
+                if let Some(f) = simulate(block, height) {
+                    findings.insert(f);
+                }
+            }
+        }
+
+        // Only one report per instruction, for the lowest height
+        let mut seen = HashSet::new();
+        let mut findings: Vec<Finding> = findings
+            .into_iter()
+            .filter(|f| seen.insert((f.kind(), f.pc())))
+            .collect();
+
+        let push_data: Vec<(usize, usize)> = cfg
+            .blocks
+            .iter()
+            .flat_map(|b| &b.instructions)
+            .filter(|i| !i.immediate.is_empty())
+            .map(|i| (i.offset + 1, i.next()))
+            .collect();
+        for &(pc, target) in &cfg.invalid_jumps {
+            if push_data.iter().any(|&(s, e)| (s..e).contains(&target)) {
+                findings.push(Finding::JumpIntoPushData { pc, target });
+            } else {
+                findings.push(Finding::InvalidJump { pc, target });
+            }
+        }
+
+        let unresolved: Vec<usize> = cfg
+            .unresolved
+            .iter()
+            .copied()
+            .filter(|pc| {
+                cfg.visited
+                    .iter()
+                    .any(|&s| cfg.block(s).unwrap().last().offset == *pc)
+            })
+            .collect();
+        findings.extend(unresolved.iter().map(|&pc| Finding::UnresolvedJump { pc }));
+
+        // With unknown targets around, every JUMPDEST might be reached
+        let mut reachable = cfg.visited.clone();
+        if !unresolved.is_empty() {
+            let mut work: Vec<usize> = cfg
+                .blocks
+                .iter()
+                .filter(|b| b.instructions[0].opcode == JUMPDEST)
+                .map(|b| b.start)
+                .collect();
+            while let Some(start) = work.pop() {
+                if reachable.insert(start) {
+                    work.extend(cfg.successors(start).map(|e| e.to));
+                }
+            }
+        }
+        let mut run: Option<(usize, usize)> = None;
+        for block in &cfg.blocks {
+            if reachable.contains(&block.start) {
+                findings.extend(
+                    run.take()
+                        .map(|(start, end)| Finding::Unreachable { start, end }),
+                );
+            } else {
+                let start = run.map_or(block.start, |(s, _)| s);
+                run = Some((start, block.end()));
+            }
+        }
+        findings.extend(run.map(|(start, end)| Finding::Unreachable { start, end }));
+
+        if unresolved.is_empty() {
+            for block in &cfg.blocks {
+                let first = &block.instructions[0];
+                let targeted = cfg
+                    .predecessors(block.start)
+                    .any(|e| e.kind != EdgeKind::Fallthrough && cfg.visited.contains(&e.from));
+                if first.opcode == JUMPDEST && cfg.visited.contains(&block.start) && !targeted {
+                    findings.push(Finding::UnusedJumpdest { pc: first.offset });
+                }
+            }
+        }
+
+        findings.sort_by_key(|f| f.pc());
+        Analysis {
+            cfg,
+            blocks,
+            findings,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let blocks: Vec<Value> = self
+            .blocks
+            .iter()
+            .map(|b| {
+                json!({
+                    "start": b.start,
+                    "heights": b.heights,
+                    "needed": b.needed,
+                    "delta": b.delta,
+                    "maxGrowth": b.max_growth,
+                })
+            })
+            .collect();
+        let findings: Vec<Value> = self.findings.iter().map(|f| f.to_json()).collect();
+        json!({"blocks": blocks, "findings": findings})
+    }
+}
+
+fn block_stack(cfg: &Cfg, block: &BasicBlock) -> BlockStack {
+    let (mut needed, mut delta, mut max_growth) = (0usize, 0isize, 0usize);
+    for ins in &block.instructions {
+        let (pops, pushes) = ins.stack_effect();
+        needed = needed.max((pops as isize - delta).max(0) as usize);
+        delta += pushes as isize - pops as isize;
+        max_growth = max_growth.max(delta.max(0) as usize);
+    }
+    BlockStack {
+        start: block.start,
+        heights: cfg
+            .heights
+            .get(&block.start)
+            .map(|h| h.iter().copied().collect())
+            .unwrap_or_default(),
+        needed,
+        delta,
+        max_growth,
+    }
+}
+
+// The first stack fault running `block` from `height` items
+fn simulate(block: &BasicBlock, mut height: usize) -> Option<Finding> {
+    for ins in &block.instructions {
+        let (pops, pushes) = ins.stack_effect();
+        if height < pops {
+            return Some(Finding::StackUnderflow {
+                pc: ins.offset,
+                height,
+                needed: pops,
+            });
+        }
+        height = height - pops + pushes;
+        if height > STACK_LIMIT {
+            return Some(Finding::StackOverflow {
+                pc: ins.offset,
+                height,
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn computes_block_stack_use() {
+        let code = assemble(
+            "
+            PUSH1 1 PUSH1 2 PUSH @add JUMP
+            JUMPDEST @add ADD DUP1 POP STOP
+            ",
+        )
+        .unwrap();
+        let analysis = Analysis::run(&code);
+        assert_eq!(
+            analysis.blocks[1],
+            BlockStack {
+                start: 7,
+                heights: vec![2],
+                needed: 2,
+                delta: -1,
+                max_growth: 0,
+            }
+        );
+        assert_eq!(analysis.blocks[0].max_growth, 3);
+        assert!(analysis.findings.is_empty(), "{:?}", analysis.findings);
+    }
+
+    #[test]
+    fn flags_malformed_code() {
+        let code = assemble(
+            "
+            PUSH1 0 CALLDATALOAD PUSH @under JUMPI   ; 0000
+            JUMPDEST                                 ; 0006: only fallen into
+            PUSH1 0x0b JUMP                          ; 0007: into PUSH1 0x0e's data
+            PUSH1 0x0e JUMP                          ; 000a: dead
+            JUMPDEST @under ADD STOP                 ; 000d
+            ",
+        )
+        .unwrap();
+        let analysis = Analysis::run(&code);
+        assert_eq!(
+            analysis.findings,
+            vec![
+                Finding::UnusedJumpdest { pc: 6 },
+                Finding::JumpIntoPushData {
+                    pc: 9,
+                    target: 0x0b
+                },
+                Finding::Unreachable {
+                    start: 0x0a,
+                    end: 0x0d
+                },
+                Finding::InvalidJump {
+                    pc: 0x0c,
+                    target: 0x0e
+                },
+                Finding::StackUnderflow {
+                    pc: 0x0e,
+                    height: 0,
+                    needed: 2
+                },
+            ]
+        );
+        assert_eq!(
+            analysis.findings[4].to_string(),
+            "000e: stack underflow: needs 2 items, has 0 on some path"
+        );
+        assert!(!analysis.findings[0].is_error());
+        assert!(analysis.findings[4].is_error());
+    }
+}
@@ -0,0 +1,292 @@
+// Assembler for mnemonic text, the inverse of `disasm::listing`.
+//
+//     PUSH1 0x03          ; comments start with ; or //
+//     JUMPDEST @loop      ; defines `loop` at this JUMPDEST
+//     PUSH1 1
+//     SUB
+//     DUP1
+//     PUSH @loop          ; smallest PUSH that fits the label's offset
+//     JUMPI
+//
+// `PUSH` without a width picks the smallest one for its value; `PUSHn` takes
+// hex or decimal literals up to n bytes. A bare hex literal such as `0xfe` is
+// emitted as raw bytes, and `0000:` offset prefixes from the disassembler are
+// skipped, so disassembly output assembles back to the same code.
+
+use std::collections::HashMap;
+
+use primitive_types::U256;
+use thiserror::Error;
+
+use crate::hexutil::parse_hex;
+use crate::opcodes::{opcode_by_name, JUMPDEST, PUSH1};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AsmError {
+    #[error("line {line}: unknown instruction `{token}`")]
+    UnknownInstruction { line: usize, token: String },
+    #[error("line {line}: {mnemonic} needs an operand")]
+    MissingOperand { line: usize, mnemonic: String },
+    #[error("line {line}: invalid literal `{token}`")]
+    InvalidLiteral { line: usize, token: String },
+    #[error("line {line}: `{token}` does not fit in {width} bytes")]
+    TooWide {
+        line: usize,
+        token: String,
+        width: usize,
+    },
+    #[error("line {line}: label @{name} defined twice")]
+    DuplicateLabel { line: usize, name: String },
+    #[error("line {line}: undefined label @{name}")]
+    UndefinedLabel { line: usize, name: String },
+}
+
+#[derive(Debug)]
+enum Item {
+    Bytes(Vec<u8>),
+    // PUSH of a label's offset; `width` is fixed for PUSHn and grows for PUSH
+    Label {
+        name: String,
+        width: usize,
+        fixed: bool,
+        line: usize,
+    },
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match self {
+            Item::Bytes(b) => b.len(),
+            Item::Label { width, .. } => 1 + width,
+        }
+    }
+}
+
+pub fn assemble(src: &str) -> Result<Vec<u8>, AsmError> {
+    let mut items = Vec::new();
+    // Label name -> index of the item it points at
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    for (i, raw) in src.lines().enumerate() {
+        let line = i + 1;
+        let text = raw.split(';').next().unwrap_or("");
+        let text = text.split("//").next().unwrap_or("");
+        let mut tokens = text.split_whitespace().peekable();
+        while let Some(token) = tokens.next() {
+            if is_offset(token) {
+                continue;
+            }
+            let upper = token.to_ascii_uppercase();
+            if upper == "PUSH" || push_width(&upper).is_some() {
+                let width = push_width(&upper);
+                let operand = tokens.next().ok_or_else(|| AsmError::MissingOperand {
+                    line,
+                    mnemonic: upper.clone(),
+                })?;
+                items.push(push(line, width, operand)?);
+            } else if let Some(op) = opcode_by_name(token) {
+                items.push(Item::Bytes(vec![op]));
+                if op == JUMPDEST {
+                    if let Some(name) = tokens.next_if(|t| t.starts_with('@')) {
+                        let name = name[1..].to_string();
+                        if labels.insert(name.clone(), items.len() - 1).is_some() {
+                            return Err(AsmError::DuplicateLabel { line, name });
+                        }
+                    }
+                }
+            } else if let Some(bytes) = token.starts_with("0x").then(|| parse_hex(token)).flatten()
+            {
+                items.push(Item::Bytes(bytes));
+            } else {
+                return Err(AsmError::UnknownInstruction {
+                    line,
+                    token: token.to_string(),
+                });
+            }
+        }
+    }
+    for item in &items {
+        if let Item::Label { name, line, .. } = item {
+            if !labels.contains_key(name) {
+                return Err(AsmError::UndefinedLabel {
+                    line: *line,
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    // Widen label pushes until every offset fits; widths only grow, so this ends
+    let offsets = loop {
+        let mut offsets = Vec::with_capacity(items.len());
+        let mut pc = 0;
+        for item in &items {
+            offsets.push(pc);
+            pc += item.len();
+        }
+        let mut changed = false;
+        for item in &mut items {
+            if let Item::Label {
+                name, width, fixed, ..
+            } = item
+            {
+                let needed = byte_len(U256::from(offsets[labels[name.as_str()]]));
+                if !*fixed && needed > *width {
+                    *width = needed;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break offsets;
+        }
+    };
+
+    let mut code = Vec::new();
+    for item in &items {
+        match item {
+            Item::Bytes(b) => code.extend_from_slice(b),
+            Item::Label {
+                name, width, line, ..
+            } => {
+                let target = U256::from(offsets[labels[name.as_str()]]);
+                if byte_len(target) > *width {
+                    return Err(AsmError::TooWide {
+                        line: *line,
+                        token: format!("@{name}"),
+                        width: *width,
+                    });
+                }
+                code.push(PUSH1 + *width as u8 - 1);
+                code.extend_from_slice(&be_bytes(target, *width));
+            }
+        }
+    }
+    Ok(code)
+}
+
+// "PUSH1".."PUSH32"
+fn push_width(upper: &str) -> Option<usize> {
+    let n: usize = upper.strip_prefix("PUSH")?.parse().ok()?;
+    (1..=32).contains(&n).then_some(n)
+}
+
+// "0000:" as printed by the disassembler
+fn is_offset(token: &str) -> bool {
+    token
+        .strip_suffix(':')
+        .is_some_and(|t| !t.is_empty() && t.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn push(line: usize, width: Option<usize>, operand: &str) -> Result<Item, AsmError> {
+    if let Some(name) = operand.strip_prefix('@') {
+        return Ok(Item::Label {
+            name: name.to_string(),
+            width: width.unwrap_or(1),
+            fixed: width.is_some(),
+            line,
+        });
+    }
+    let invalid = || AsmError::InvalidLiteral {
+        line,
+        token: operand.to_string(),
+    };
+    let value = match operand.strip_prefix("0x") {
+        Some(digits) if digits.len() <= 64 => {
+            U256::from_str_radix(digits, 16).map_err(|_| invalid())?
+        }
+        Some(_) => return Err(invalid()),
+        None => U256::from_dec_str(operand).map_err(|_| invalid())?,
+    };
+    let width = width.unwrap_or_else(|| byte_len(value));
+    if byte_len(value) > width {
+        return Err(AsmError::TooWide {
+            line,
+            token: operand.to_string(),
+            width,
+        });
+    }
+    let mut bytes = vec![PUSH1 + width as u8 - 1];
+    bytes.extend_from_slice(&be_bytes(value, width));
+    Ok(Item::Bytes(bytes))
+}
+
+// Bytes needed to hold `v`, at least one
+fn byte_len(v: U256) -> usize {
+    v.bits().div_ceil(8).max(1)
+}
+
+fn be_bytes(v: U256, width: usize) -> Vec<u8> {
+    let mut buf = [0u8; 32];
+    v.to_big_endian(&mut buf);
+    buf[32 - width..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::listing;
+    use crate::machine::{Evm, EvmConfig};
+
+    #[test]
+    fn resolves_labels_to_the_smallest_push() {
+        let src = "
+            PUSH1 0x03          ; counter
+            JUMPDEST @loop
+            PUSH1 1 SUB         // counter - 1
+            DUP1
+            PUSH @loop
+            JUMPI
+            PUSH @end JUMP
+            JUMPDEST @end
+            PUSH2 258 PUSH 0x10000 STOP
+        ";
+        let code = assemble(src).unwrap();
+        assert_eq!(
+            code,
+            vec![
+                0x60, 0x03, 0x5b, 0x60, 0x01, 0x03, 0x80, 0x60, 0x02, 0x57, 0x60, 0x0d, 0x56, 0x5b,
+                0x61, 0x01, 0x02, 0x62, 0x01, 0x00, 0x00, 0x00,
+            ]
+        );
+        let mut evm = Evm::new(code, EvmConfig::default());
+        assert!(evm.run().is_success());
+        assert_eq!(
+            evm.stack,
+            vec![U256::zero(), U256::from(258), U256::from(0x10000)]
+        );
+
+        assert_eq!(
+            assemble("PUSH @nowhere"),
+            Err(AsmError::UndefinedLabel {
+                line: 1,
+                name: "nowhere".into()
+            })
+        );
+        assert!(matches!(
+            assemble("PUSH1 256"),
+            Err(AsmError::TooWide { width: 1, .. })
+        ));
+        assert!(matches!(
+            assemble("\nFOO"),
+            Err(AsmError::UnknownInstruction { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn round_trips_disassembly() {
+        let mut code: Vec<u8> = (0..=255u8).filter(|b| !(0x60..=0x7f).contains(b)).collect();
+        for n in 1..=32u8 {
+            code.push(0x5f + n);
+            code.extend((0..n).map(|i| i.wrapping_mul(37)));
+        }
+        // End on a JUMPDEST so none of it is taken for a data section
+        code.push(0x5b);
+        for effects in [false, true] {
+            let text = listing(&code, effects).join("\n");
+            assert_eq!(assemble(&text).unwrap(), code);
+        }
+        // Data after the last block comes back as raw bytes
+        code.extend_from_slice(&[0x00, 0x5c, 0x60]);
+        assert_eq!(assemble(&listing(&code, false).join("\n")).unwrap(), code);
+    }
+}
@@ -1,6 +1,23 @@
-use clap::{Parser, Subcommand};
-use evm_in_rust::{disasm, Account, Evm, EvmConfig, World};
-use primitive_types::{H160, U256};
+use clap::{Args, Parser, Subcommand};
+use evm_in_rust::abi::{self, Abi, AbiType, AbiValue, DecodeRevert, Signature};
+use evm_in_rust::asm::assemble;
+use evm_in_rust::coverage::{CodeCoverage, Coverage};
+use evm_in_rust::debugger::Debugger;
+use evm_in_rust::hexutil::{hex, parse_h160, parse_hex, parse_u256};
+use evm_in_rust::machine::keccak256;
+use evm_in_rust::opcodes::opcode_name;
+use evm_in_rust::prestate::{self, PrestateTracer};
+use evm_in_rust::profiler::GasProfiler;
+use evm_in_rust::srcmap::{self, SolcOutput, SourceMap};
+use evm_in_rust::statetest::{self, run_state_tests};
+use evm_in_rust::t8n;
+use evm_in_rust::tracer::{self, Eip3155Tracer};
+use evm_in_rust::trie::EMPTY_ROOT;
+use evm_in_rust::{
+    disasm, find_selectors, Account, Analysis, CallFrame, CallTracer, Cfg, Evm, EvmConfig,
+    ExecutionResult, SignatureDb, SuccessReason, World,
+};
+use primitive_types::H256;
 use std::collections::HashMap;
 
 #[derive(Debug, Parser)]
@@ -10,70 +27,207 @@ struct Cli {
     cmd: Cmd,
 }
 
+// Code, context and world shared by the subcommands that execute code
+#[derive(Debug, Args)]
+struct ExecArgs {
+    /// Hex bytecode (e.g., 0x6001600101), @file or a .easm source
+    code: String,
+    /// Gas limit
+    #[arg(long, default_value_t = 10_000_000)]
+    gas: i128,
+    /// Calldata as hex
+    #[arg(long, default_value = "0x")]
+    calldata: String,
+    /// Build calldata from a function signature, e.g. "transfer(address,uint256)"
+    #[arg(long, conflicts_with = "calldata")]
+    sig: Option<String>,
+    /// Arguments for --sig, one per parameter
+    #[arg(long, num_args = 1.., allow_negative_numbers = true, requires = "sig")]
+    args: Vec<String>,
+    /// World JSON file (accounts map)
+    #[arg(long)]
+    world: Option<String>,
+    /// Context address (0x..)
+    #[arg(long)]
+    address: Option<String>,
+    /// Msg caller (0x..)
+    #[arg(long)]
+    caller: Option<String>,
+    /// Tx origin (0x..)
+    #[arg(long)]
+    origin: Option<String>,
+    /// Call value (0x.. or decimal)
+    #[arg(long, default_value = "0x0")]
+    value: String,
+    /// Gas price (0x.. or decimal)
+    #[arg(long, default_value = "0x0")]
+    gas_price: String,
+    /// Block coinbase (0x..)
+    #[arg(long)]
+    coinbase: Option<String>,
+    /// Block timestamp (unix seconds)
+    #[arg(long)]
+    timestamp: Option<u64>,
+    /// Block number
+    #[arg(long)]
+    number: Option<u64>,
+    /// Block gas limit (0x.. or decimal)
+    #[arg(long)]
+    block_gas_limit: Option<String>,
+    /// Chain id (0x.. or decimal)
+    #[arg(long)]
+    chainid: Option<String>,
+    /// Basefee (0x.. or decimal)
+    #[arg(long)]
+    basefee: Option<String>,
+}
+
+// solc output used to map pcs back to Solidity source
+#[derive(Debug, Args)]
+struct SourceArgs {
+    /// solc --combined-json bin-runtime,srcmap-runtime output, or standard-JSON output
+    #[arg(long)]
+    solc: Option<String>,
+    /// Directory the compiler's source paths are relative to
+    #[arg(long, default_value = ".")]
+    source_root: String,
+}
+
+// Contract ABIs used to decode custom errors and events
+#[derive(Debug, Args)]
+struct AbiArgs {
+    /// JSON ABI file, or an artifact with an "abi" field (repeatable)
+    #[arg(long = "abi")]
+    abis: Vec<String>,
+}
+
 #[derive(Debug, Subcommand)]
 #[allow(clippy::large_enum_variant)]
 enum Cmd {
     /// Run EVM bytecode
     Run {
-        /// Hex bytecode (e.g., 0x6001600101) or @file
-        code: String,
-        /// Gas limit
-        #[arg(long, default_value_t = 10_000_000)]
-        gas: i128,
-        /// Calldata as hex
-        #[arg(long, default_value = "0x")]
-        calldata: String,
+        #[command(flatten)]
+        exec: ExecArgs,
+        #[command(flatten)]
+        sources: SourceArgs,
+        #[command(flatten)]
+        abis: AbiArgs,
         /// Print full stack
         #[arg(long)]
         dump_stack: bool,
-        /// World JSON file (accounts map)
+        /// Dump final world JSON to stdout or file path
         #[arg(long)]
-        world: Option<String>,
-        /// Context address (0x..)
+        dump_world: Option<Option<String>>,
+        /// Decode the return data as these types, e.g. "(uint256,bool)"
+        #[arg(long, conflicts_with_all = ["call_trace", "prestate"])]
+        ret: Option<String>,
+        /// Print the logs as JSON, decoded with the --abi events where they match
+        #[arg(long, conflicts_with_all = ["call_trace", "prestate"])]
+        logs_json: bool,
+        /// Print the call tree as callTracer JSON instead of the final state
         #[arg(long)]
-        address: Option<String>,
-        /// Msg caller (0x..)
+        call_trace: bool,
+        /// Include logs in the call tree (with --call-trace)
+        #[arg(long, requires = "call_trace")]
+        with_log: bool,
+        /// Print the pre-state of touched accounts as prestateTracer JSON
+        #[arg(long, conflicts_with = "call_trace")]
+        prestate: bool,
+        /// Print only what changed, as {pre, post} (with --prestate)
+        #[arg(long, requires = "prestate")]
+        diff_mode: bool,
+    },
+    /// Run EVM bytecode and break down the gas used
+    Profile {
+        #[command(flatten)]
+        exec: ExecArgs,
+        /// Rows per table
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Write folded stacks (for flamegraph.pl/inferno) to this file
         #[arg(long)]
-        caller: Option<String>,
-        /// Tx origin (0x..)
+        folded: Option<String>,
+    },
+    /// Run bytecode over many inputs and report instruction/branch coverage
+    Coverage {
+        #[command(flatten)]
+        exec: ExecArgs,
+        /// Calldata of one run (repeatable); defaults to --calldata or --sig/--args
+        #[arg(long = "input")]
+        inputs: Vec<String>,
+        /// File with one calldata hex per line
         #[arg(long)]
-        origin: Option<String>,
-        /// Call value (0x.. or decimal)
-        #[arg(long, default_value = "0x0")]
-        value: String,
-        /// Gas price (0x.. or decimal)
-        #[arg(long, default_value = "0x0")]
-        gas_price: String,
-        /// Block coinbase (0x..)
+        inputs_file: Option<String>,
+        /// solc runtime source map of the code (or @file)
         #[arg(long)]
-        coinbase: Option<String>,
-        /// Block timestamp (unix seconds)
+        srcmap: Option<String>,
+        /// Source file, repeated in the compiler's source list order
+        #[arg(long = "source")]
+        sources: Vec<String>,
+        /// Write LCOV mapped to source lines to this path
+        #[arg(long, requires = "srcmap")]
+        lcov: Option<String>,
+    },
+    /// Step through bytecode interactively, into and out of calls
+    Debug {
+        #[command(flatten)]
+        exec: ExecArgs,
+        #[command(flatten)]
+        abis: AbiArgs,
+    },
+    /// Disassemble bytecode
+    Disasm {
+        /// Hex bytecode, @file or a .easm source
+        code: String,
+        /// Print instructions and the data section as JSON
         #[arg(long)]
-        timestamp: Option<u64>,
-        /// Block number
+        json: bool,
+        /// Show each instruction's stack effect and static gas
         #[arg(long)]
-        number: Option<u64>,
-        /// Block gas limit (0x.. or decimal)
+        effects: bool,
+    },
+    /// Recover the control-flow graph and print it as Graphviz DOT
+    Cfg {
+        /// Hex bytecode, @file or a .easm source
+        code: String,
+        /// Print JSON instead of DOT
         #[arg(long)]
-        block_gas_limit: Option<String>,
-        /// Chain id (0x.. or decimal)
+        json: bool,
+    },
+    /// Check stack heights, reachability and jump targets without running the code
+    Analyze {
+        /// Hex bytecode, @file or a .easm source
+        code: String,
+        /// Print JSON instead of text
         #[arg(long)]
-        chainid: Option<String>,
-        /// Basefee (0x.. or decimal)
+        json: bool,
+    },
+    /// List the function selectors a contract dispatches on, with their entry PCs
+    Selectors {
+        /// Hex bytecode, @file or a .easm source
+        #[arg(required_unless_present = "world", conflicts_with = "world")]
+        code: Option<String>,
+        /// World JSON file holding the contract (with --address)
+        #[arg(long, requires = "address")]
+        world: Option<String>,
+        /// Contract address in --world
+        #[arg(long, requires = "world")]
+        address: Option<String>,
+        /// Signature database: "0x<selector> <signature>" or signature lines, or 4byte-style JSON
         #[arg(long)]
-        basefee: Option<String>,
-        /// Dump final world JSON to stdout or file path
+        db: Option<String>,
+        /// Print JSON instead of text
         #[arg(long)]
-        dump_world: Option<Option<String>>,
+        json: bool,
     },
-    /// Disassemble bytecode
-    Disasm {
-        /// Hex bytecode or @file
-        code: String,
+    /// Assemble mnemonic source into hex bytecode
+    Asm {
+        /// Assembly text, @file or a .easm file
+        source: String,
     },
     /// Step-through trace
     Trace {
-        /// Hex bytecode or @file
+        /// Hex bytecode, @file or a .easm source
         code: String,
         /// Calldata as hex
         #[arg(long, default_value = "0x")]
@@ -93,6 +247,67 @@ enum Cmd {
         /// Msg caller (0x..)
         #[arg(long)]
         caller: Option<String>,
+        /// Emit EIP-3155 JSON lines instead of the text trace
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        sources: SourceArgs,
+        #[command(flatten)]
+        abis: AbiArgs,
+    },
+    /// Print an EIP-1186 account/storage proof against the world's state root
+    Proof {
+        /// World JSON file (accounts map)
+        #[arg(long)]
+        world: String,
+        /// Account address (0x..)
+        #[arg(long)]
+        address: String,
+        /// Storage slot to prove (repeatable)
+        #[arg(long = "slot")]
+        slots: Vec<String>,
+    },
+    /// Run ethereum/tests GeneralStateTests fixtures (a file or a directory)
+    Statetest {
+        /// Fixture JSON file or directory searched recursively
+        path: String,
+        /// Fork to check; only Cancun is implemented, other forks are rejected
+        #[arg(long)]
+        fork: Option<String>,
+    },
+    /// State transition tool compatible with geth's `evm t8n`
+    T8n {
+        /// Pre-state alloc JSON file, or `stdin`
+        #[arg(long = "input.alloc", default_value = "alloc.json")]
+        input_alloc: String,
+        /// Block env JSON file, or `stdin`
+        #[arg(long = "input.env", default_value = "env.json")]
+        input_env: String,
+        /// Transactions JSON file, or `stdin`
+        #[arg(long = "input.txs", default_value = "txs.json")]
+        input_txs: String,
+        /// Directory the output files are written to
+        #[arg(long = "output.basedir", default_value = "")]
+        output_basedir: String,
+        /// Result file name, or `stdout`
+        #[arg(long = "output.result", default_value = "result.json")]
+        output_result: String,
+        /// Post-state alloc file name, or `stdout`
+        #[arg(long = "output.alloc", default_value = "alloc.json")]
+        output_alloc: String,
+        /// Fork rules to apply; only Cancun is implemented
+        #[arg(long = "state.fork", default_value = "Cancun")]
+        fork: String,
+        /// Chain id (0x.. or decimal)
+        #[arg(long = "state.chainid", default_value = "1")]
+        chain_id: String,
+        /// Block reward paid to the coinbase; -1 disables it
+        #[arg(
+            long = "state.reward",
+            default_value = "-1",
+            allow_hyphen_values = true
+        )]
+        reward: String,
     },
 }
 
@@ -100,43 +315,65 @@ fn main() {
     let cli = Cli::parse();
     match cli.cmd {
         Cmd::Run {
-            code,
-            gas,
-            calldata,
+            exec,
+            sources,
+            abis,
             dump_stack,
-            world,
-            address,
-            caller,
-            origin,
-            value,
-            gas_price,
-            coinbase,
-            timestamp,
-            number,
-            block_gas_limit,
-            chainid,
-            basefee,
             dump_world,
+            ret,
+            logs_json,
+            call_trace,
+            with_log,
+            prestate,
+            diff_mode,
         } => run_cmd(
-            &code,
-            gas,
-            &calldata,
+            &exec,
+            &sources,
+            &abis,
             dump_stack,
-            world.as_deref(),
-            address.as_deref(),
-            caller.as_deref(),
-            origin.as_deref(),
-            &value,
-            &gas_price,
-            coinbase.as_deref(),
-            timestamp,
-            number,
-            block_gas_limit.as_deref(),
-            chainid.as_deref(),
-            basefee.as_deref(),
             dump_world,
+            ret.as_deref(),
+            logs_json,
+            if call_trace {
+                Some(RunTracer::Call { with_log })
+            } else if prestate {
+                Some(RunTracer::Prestate { diff_mode })
+            } else {
+                None
+            },
         ),
-        Cmd::Disasm { code } => disasm_cmd(&code),
+        Cmd::Profile { exec, top, folded } => profile_cmd(&exec, top, folded.as_deref()),
+        Cmd::Coverage {
+            exec,
+            inputs,
+            inputs_file,
+            srcmap,
+            sources,
+            lcov,
+        } => coverage_cmd(
+            &exec,
+            inputs,
+            inputs_file.as_deref(),
+            srcmap.as_deref(),
+            &sources,
+            lcov.as_deref(),
+        ),
+        Cmd::Debug { exec, abis } => debug_cmd(&exec, &abis),
+        Cmd::Disasm {
+            code,
+            json,
+            effects,
+        } => disasm_cmd(&code, json, effects),
+        Cmd::Cfg { code, json } => cfg_cmd(&code, json),
+        Cmd::Analyze { code, json } => analyze_cmd(&code, json),
+        Cmd::Selectors {
+            code,
+            world,
+            address,
+            db,
+            json,
+        } => selectors_cmd(code, world, address, db, json),
+        Cmd::Asm { source } => asm_cmd(&source),
         Cmd::Trace {
             code,
             calldata,
@@ -145,6 +382,9 @@ fn main() {
             world,
             address,
             caller,
+            json,
+            sources,
+            abis,
         } => trace_cmd(
             &code,
             &calldata,
@@ -153,112 +393,530 @@ fn main() {
             world.as_deref(),
             address.as_deref(),
             caller.as_deref(),
+            json,
+            &sources,
+            &abis,
+        ),
+        Cmd::Proof {
+            world,
+            address,
+            slots,
+        } => proof_cmd(&world, &address, &slots),
+        Cmd::Statetest { path, fork } => statetest_cmd(&path, fork.as_deref()),
+        Cmd::T8n {
+            input_alloc,
+            input_env,
+            input_txs,
+            output_basedir,
+            output_result,
+            output_alloc,
+            fork,
+            chain_id,
+            reward,
+        } => t8n_cmd(
+            [&input_alloc, &input_env, &input_txs],
+            &output_basedir,
+            &output_result,
+            &output_alloc,
+            &fork,
+            &chain_id,
+            &reward,
         ),
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn run_cmd(
-    code_arg: &str,
-    gas: i128,
-    calldata_hex: &str,
-    dump_stack: bool,
-    world_path: Option<&str>,
-    address_hex: Option<&str>,
-    caller_hex: Option<&str>,
-    origin_hex: Option<&str>,
-    value_str: &str,
-    gas_price_str: &str,
-    coinbase_hex: Option<&str>,
-    ts: Option<u64>,
-    num: Option<u64>,
-    block_gas_limit_str: Option<&str>,
-    chainid_str: Option<&str>,
-    basefee_str: Option<&str>,
-    dump_world: Option<Option<String>>,
-) {
-    let code = read_code_arg(code_arg);
-    let calldata = parse_hex(calldata_hex).unwrap_or_else(|| die("Invalid calldata hex"));
+// Tracer selected for `evm run`; its JSON replaces the usual output
+enum RunTracer {
+    Call { with_log: bool },
+    Prestate { diff_mode: bool },
+}
+
+fn build_evm(exec: &ExecArgs) -> Evm {
+    let code = read_code_arg(&exec.code);
+    let calldata = match &exec.sig {
+        Some(sig) => Signature::parse(sig)
+            .and_then(|sig| sig.encode_call(&exec.args))
+            .unwrap_or_else(|e| die(&format!("Invalid --sig/--args: {e}"))),
+        None => parse_hex(&exec.calldata).unwrap_or_else(|| die("Invalid calldata hex")),
+    };
     let mut cfg = EvmConfig {
-        gas_limit: gas,
+        gas_limit: exec.gas,
         calldata,
         ..EvmConfig::default()
     };
-    cfg.address = address_hex.and_then(parse_h160);
-    cfg.caller = caller_hex.and_then(parse_h160);
-    cfg.origin = origin_hex.and_then(parse_h160);
-    cfg.value = parse_u256(value_str).unwrap_or_else(|| die("Invalid --value"));
-    cfg.gas_price = parse_u256(gas_price_str).unwrap_or_else(|| die("Invalid --gas-price"));
-    if let Some(cb) = coinbase_hex.and_then(parse_h160) {
+    cfg.address = exec.address.as_deref().and_then(parse_h160);
+    cfg.caller = exec.caller.as_deref().and_then(parse_h160);
+    cfg.origin = exec.origin.as_deref().and_then(parse_h160);
+    cfg.value = parse_u256(&exec.value).unwrap_or_else(|| die("Invalid --value"));
+    cfg.gas_price = parse_u256(&exec.gas_price).unwrap_or_else(|| die("Invalid --gas-price"));
+    if let Some(cb) = exec.coinbase.as_deref().and_then(parse_h160) {
         cfg.block.coinbase = cb;
     }
-    if let Some(t) = ts {
+    if let Some(t) = exec.timestamp {
         cfg.block.timestamp = t;
     }
-    if let Some(n) = num {
+    if let Some(n) = exec.number {
         cfg.block.number = n;
     }
-    if let Some(gl) = block_gas_limit_str.and_then(parse_u256) {
+    if let Some(gl) = exec.block_gas_limit.as_deref().and_then(parse_u256) {
         cfg.block.gas_limit = gl;
     }
-    if let Some(cid) = chainid_str.and_then(parse_u256) {
+    if let Some(cid) = exec.chainid.as_deref().and_then(parse_u256) {
         cfg.block.chain_id = cid;
     }
-    if let Some(bf) = basefee_str.and_then(parse_u256) {
+    if let Some(bf) = exec.basefee.as_deref().and_then(parse_u256) {
         cfg.block.basefee = bf;
     }
-    if let Some(path) = world_path {
+    if let Some(path) = &exec.world {
         cfg.world = Some(load_world(path));
     }
-    let mut evm = Evm::new(code, cfg);
-    match evm.run() {
-        Ok(()) => {
-            println!("halted: {}", halt_status(&evm));
-            if !evm.return_data.is_empty() {
-                println!("return: 0x{}", hex(&evm.return_data));
-            }
-            println!("pc: {}", evm.pc);
-            println!("gas left: {}", evm.gas);
-            println!("stack size: {}", evm.stack.len());
-            if let Some(top) = evm.stack.last() {
-                println!("top: 0x{:x}", top);
+    Evm::new(code, cfg)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_cmd(
+    exec: &ExecArgs,
+    sources: &SourceArgs,
+    abis: &AbiArgs,
+    dump_stack: bool,
+    dump_world: Option<Option<String>>,
+    ret: Option<&str>,
+    logs_json: bool,
+    tracer: Option<RunTracer>,
+) {
+    let mut evm = build_evm(exec);
+    if let Some(tracer) = tracer {
+        // The tracers print their own JSON and decode nothing with an ABI
+        if !abis.abis.is_empty() {
+            die("--abi cannot be used with --call-trace or --prestate");
+        }
+        let out = match tracer {
+            RunTracer::Call { with_log } => {
+                let mut tracer = CallTracer::new(CallFrame::root(&evm), with_log);
+                let result = evm.run_with(&mut tracer);
+                tracer.finish(&result).to_json()
             }
-            if dump_stack {
-                for (i, v) in evm.stack.iter().rev().enumerate() {
-                    println!("[{}] 0x{:x}", i, v);
+            RunTracer::Prestate { diff_mode } => {
+                let pre = evm.world.clone().unwrap_or_default();
+                let mut tracer = PrestateTracer::new(&evm);
+                let result = evm.run_with(&mut tracer);
+                if diff_mode {
+                    tracer
+                        .diff(&pre, evm.world.as_ref().unwrap_or(&pre), &result)
+                        .to_json()
+                } else {
+                    prestate::accounts_json(&tracer.prestate(&pre))
                 }
             }
-            if !evm.logs.is_empty() {
-                println!("logs: {}", evm.logs.len());
-            }
-            match dump_world {
-                Some(Some(dw)) => {
-                    let json = world_to_json(evm.world.as_ref());
-                    if let Some(path) = dw.strip_prefix('@') {
-                        std::fs::write(path, json)
-                            .unwrap_or_else(|e| die(&format!("write world: {e}")));
-                    } else {
-                        println!("{}", json);
-                    }
-                }
-                Some(None) => {
-                    let json = world_to_json(evm.world.as_ref());
-                    println!("{}", json);
+        };
+        println!("{}", serde_json::to_string_pretty(&out).unwrap());
+        return;
+    }
+    let abi = load_abis(abis);
+    let result = evm.run();
+    if let ExecutionResult::Halt { reason, .. } = &result {
+        let maps = load_source_maps(sources);
+        let note = source_note(&maps, &evm.code, evm.pc);
+        die(&format!("Execution error: {reason}{note}"));
+    }
+    println!("halted: {}", halt_status(Some(&result)));
+    if !result.output().is_empty() {
+        println!("return: 0x{}", hex(result.output()));
+    }
+    if let Some(reason) = result.revert_reason(&abi.errors) {
+        println!("revert: {reason}");
+    }
+    if let (Some(ret), ExecutionResult::Success { output, .. }) = (ret, &result) {
+        // A bare "uint256,bool" list is read as a tuple
+        let ret = if ret.trim().starts_with('(') {
+            ret.to_string()
+        } else {
+            format!("({ret})")
+        };
+        let ty = AbiType::parse(&ret).unwrap_or_else(|e| die(&format!("Invalid --ret: {e}")));
+        let AbiType::Tuple(types) = ty else {
+            die("Invalid --ret: expected a tuple")
+        };
+        match abi::decode(&types, output) {
+            Ok(values) => println!("decoded: {}", AbiValue::Tuple(values)),
+            Err(e) => println!("decoded: <{e}>"),
+        }
+    }
+    println!("pc: {}", evm.pc);
+    println!("gas left: {}", evm.gas);
+    println!("stack size: {}", evm.stack.len());
+    if let Some(top) = evm.stack.last() {
+        println!("top: 0x{:x}", top);
+    }
+    if dump_stack {
+        for (i, v) in evm.stack.iter().rev().enumerate() {
+            println!("[{}] 0x{:x}", i, v);
+        }
+    }
+    if !result.logs().is_empty() {
+        println!("logs: {}", result.logs().len());
+        for (i, log) in result.logs().iter().enumerate() {
+            let addr = hex(log.address.as_bytes());
+            match abi::decode_log(log, &abi.events) {
+                Some(decoded) => println!("  [{i}] 0x{addr} {decoded}"),
+                None => {
+                    let topics: Vec<String> =
+                        log.topics.iter().map(|t| format!("0x{t:064x}")).collect();
+                    println!(
+                        "  [{i}] 0x{addr} topics=[{}] data=0x{}",
+                        topics.join(", "),
+                        hex(&log.data)
+                    );
                 }
-                None => {}
             }
         }
-        Err(e) => die(&format!("Execution error: {e}")),
+    }
+    if logs_json {
+        let logs: Vec<serde_json::Value> = result
+            .logs()
+            .iter()
+            .map(|log| match abi::decode_log(log, &abi.events) {
+                Some(decoded) => decoded.to_json(),
+                None => serde_json::json!({
+                    "address": format!("0x{}", hex(log.address.as_bytes())),
+                    "topics": log.topics.iter().map(|t| format!("0x{t:064x}")).collect::<Vec<_>>(),
+                    "data": format!("0x{}", hex(&log.data)),
+                }),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&logs).unwrap());
+    }
+    match dump_world {
+        Some(Some(dw)) => {
+            let json = world_to_json(evm.world.as_ref());
+            if let Some(path) = dw.strip_prefix('@') {
+                std::fs::write(path, json).unwrap_or_else(|e| die(&format!("write world: {e}")));
+            } else {
+                println!("{}", json);
+            }
+        }
+        Some(None) => {
+            let json = world_to_json(evm.world.as_ref());
+            println!("{}", json);
+        }
+        None => {}
     }
 }
 
-fn disasm_cmd(code_arg: &str) {
+fn profile_cmd(exec: &ExecArgs, top: usize, folded: Option<&str>) {
+    let mut evm = build_evm(exec);
+    let mut profiler = GasProfiler::new(&evm);
+    let result = evm.run_with(&mut profiler);
+    let total = result.gas_used();
+    let pct = |gas: u64| gas as f64 * 100.0 / total.max(1) as f64;
+    println!("halted: {}", halt_status(Some(&result)));
+    println!("gas used: {total}");
+
+    let mut opcodes: Vec<_> = profiler.opcodes.iter().collect();
+    opcodes.sort_by(|a, b| b.1.gas.cmp(&a.1.gas).then(a.0.cmp(b.0)));
+    println!();
+    println!("{:<14} {:>8} {:>12} {:>7}", "opcode", "count", "gas", "%");
+    for (op, stat) in opcodes.into_iter().take(top) {
+        let name = opcode_name(*op);
+        println!(
+            "{:<14} {:>8} {:>12} {:>6.2}%",
+            name,
+            stat.count,
+            stat.gas,
+            pct(stat.gas)
+        );
+    }
+
+    let mut pcs: Vec<_> = profiler.pcs.iter().collect();
+    pcs.sort_by(|a, b| b.1 .1.gas.cmp(&a.1 .1.gas).then(a.0.cmp(b.0)));
+    println!();
+    println!(
+        "{:<42} {:>6} {:<14} {:>8} {:>12} {:>7}",
+        "code", "pc", "opcode", "count", "gas", "%"
+    );
+    for ((addr, pc), (op, stat)) in pcs.into_iter().take(top) {
+        let code = addr
+            .map(|a| format!("0x{}", hex(a.as_bytes())))
+            .unwrap_or_else(|| "-".into());
+        println!(
+            "{:<42} {:>6} {:<14} {:>8} {:>12} {:>6.2}%",
+            code,
+            pc,
+            opcode_name(*op),
+            stat.count,
+            stat.gas,
+            pct(stat.gas)
+        );
+    }
+
+    // Frames in call order, indented by depth
+    println!();
+    println!("{:<60} {:>12} {:>12} {:>7}", "frame", "self", "total", "%");
+    for frame in &profiler.frames {
+        let label = format!("{}{}", "  ".repeat(frame.depth), frame.label());
+        println!(
+            "{:<60} {:>12} {:>12} {:>6.2}%",
+            label,
+            frame.self_gas,
+            frame.total_gas,
+            pct(frame.total_gas)
+        );
+    }
+
+    if let Some(path) = folded {
+        let mut text = profiler.folded_lines().join("\n");
+        text.push('\n');
+        std::fs::write(path, text).unwrap_or_else(|e| die(&format!("write folded: {e}")));
+    }
+}
+
+fn coverage_cmd(
+    exec: &ExecArgs,
+    mut inputs: Vec<String>,
+    inputs_file: Option<&str>,
+    srcmap: Option<&str>,
+    sources: &[String],
+    lcov: Option<&str>,
+) {
+    if let Some(path) = inputs_file {
+        let text =
+            std::fs::read_to_string(path).unwrap_or_else(|e| die(&format!("read inputs: {e}")));
+        inputs.extend(
+            text.lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(String::from),
+        );
+    }
+    let mut cov = Coverage::default();
+    // Without inputs, a single run with the calldata of --calldata or --sig/--args
+    let runs = inputs.len().max(1);
+    for i in 0..runs {
+        let mut evm = build_evm(exec);
+        if let Some(input) = inputs.get(i) {
+            evm.calldata =
+                parse_hex(input).unwrap_or_else(|| die(&format!("Invalid input hex: {input}")));
+        }
+        evm.run_with(&mut cov);
+    }
+    let code = read_code_arg(&exec.code);
+    let target = cov.get(&code).cloned().unwrap_or_else(|| CodeCoverage {
+        code: code.clone(),
+        ..CodeCoverage::default()
+    });
+    let pct = |hit: usize, total: usize| hit as f64 * 100.0 / total.max(1) as f64;
+    println!("runs: {runs}");
+    // The code under test first, then code reached through calls and creates
+    let others = cov.codes.iter().filter(|(_, c)| c.code != code);
+    for (i, (hash, c)) in std::iter::once((&keccak256(&code), &target))
+        .chain(others)
+        .enumerate()
+    {
+        let s = c.summary();
+        println!(
+            "{}0x{} ({} bytes): instructions {}/{} ({:.2}%), branches {}/{} ({:.2}%)",
+            if i == 0 { "" } else { "  " },
+            hex(hash.as_bytes()),
+            c.code.len(),
+            s.instructions_hit,
+            s.instructions,
+            pct(s.instructions_hit, s.instructions),
+            s.branches_hit,
+            s.branches,
+            pct(s.branches_hit, s.branches),
+        );
+    }
+    let missed: Vec<String> = target
+        .missed()
+        .iter()
+        .map(|pc| format!("{pc:04x}"))
+        .collect();
+    if !missed.is_empty() {
+        println!("missed pcs: {}", missed.join(" "));
+    }
+    if let Some(path) = lcov {
+        let map = read_text_arg(srcmap.unwrap_or_default());
+        let ranges = srcmap::decode(&map).unwrap_or_else(|e| die(&e));
+        let sources: Vec<(String, String)> = sources
+            .iter()
+            .map(|p| {
+                let text = std::fs::read_to_string(p)
+                    .unwrap_or_else(|e| die(&format!("read source {p}: {e}")));
+                (p.clone(), text)
+            })
+            .collect();
+        std::fs::write(path, target.lcov(&ranges, &sources))
+            .unwrap_or_else(|e| die(&format!("write lcov: {e}")));
+    }
+}
+
+fn debug_cmd(exec: &ExecArgs, abis: &AbiArgs) {
+    let mut evm = build_evm(exec);
+    let abi = load_abis(abis);
+    println!("type `help` for commands");
+    let mut dbg = Debugger::new(std::io::stdin().lock(), std::io::stdout());
+    let result = evm.run_with(&mut dbg);
+    if dbg.quit {
+        return;
+    }
+    println!("halted: {}", halt_status(Some(&result)));
+    if !result.output().is_empty() {
+        println!("return: 0x{}", hex(result.output()));
+    }
+    if let Some(reason) = result.revert_reason(&abi.errors) {
+        println!("revert: {reason}");
+    }
+    if let ExecutionResult::Halt { reason, .. } = &result {
+        println!("error: {reason}");
+    }
+    println!("gas used: {}", result.gas_used());
+}
+
+fn disasm_cmd(code_arg: &str, json: bool, effects: bool) {
     let code = read_code_arg(code_arg);
-    for line in disasm::disassemble(&code) {
-        println!("{}", line);
+    if !json {
+        for line in disasm::listing(&code, effects) {
+            println!("{}", line);
+        }
+        return;
     }
+    let instructions: Vec<serde_json::Value> = disasm::disassemble(&code)
+        .iter()
+        .map(|ins| {
+            let mut v = ins.to_json();
+            if effects {
+                let (pops, pushes) = ins.stack_effect();
+                let (gas, dynamic) = ins.gas();
+                v["pops"] = pops.into();
+                v["pushes"] = pushes.into();
+                v["gas"] = gas.into();
+                v["dynamicGas"] = dynamic.into();
+            }
+            v
+        })
+        .collect();
+    let section = |start: Option<usize>, end: usize| {
+        start.filter(|&s| s < end).map_or(
+            serde_json::Value::Null,
+            |s| serde_json::json!({"offset": s, "bytes": format!("0x{}", hex(&code[s..end]))}),
+        )
+    };
+    let meta = disasm::metadata_start(&code);
+    let out = serde_json::json!({
+        "instructions": instructions,
+        "data": section(disasm::data_start(&code), meta.unwrap_or(code.len())),
+        "metadata": section(meta, code.len()),
+    });
+    println!("{}", serde_json::to_string_pretty(&out).unwrap());
+}
+
+fn cfg_cmd(code_arg: &str, json: bool) {
+    let code = read_code_arg(code_arg);
+    let cfg = Cfg::build(&code);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&cfg.to_json()).unwrap());
+    } else {
+        print!("{}", cfg.to_dot());
+    }
+}
+
+// Exits with status 1 when a finding would make execution fail
+fn analyze_cmd(code_arg: &str, json: bool) {
+    let code = read_code_arg(code_arg);
+    let analysis = Analysis::run(&code);
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&analysis.to_json()).unwrap()
+        );
+    } else {
+        println!("blocks: {}", analysis.blocks.len());
+        for (b, block) in analysis.blocks.iter().zip(&analysis.cfg.blocks) {
+            let heights: Vec<String> = b.heights.iter().map(|h| h.to_string()).collect();
+            println!(
+                "  {:04x}-{:04x} entry=[{}] needs={} delta={:+} max=+{}",
+                b.start,
+                block.end(),
+                heights.join(","),
+                b.needed,
+                b.delta,
+                b.max_growth
+            );
+        }
+        println!("findings: {}", analysis.findings.len());
+        for finding in &analysis.findings {
+            println!("  {finding}");
+        }
+    }
+    if analysis.findings.iter().any(|f| f.is_error()) {
+        std::process::exit(1);
+    }
+}
+
+fn selectors_cmd(
+    code_arg: Option<String>,
+    world: Option<String>,
+    address: Option<String>,
+    db: Option<String>,
+    json: bool,
+) {
+    let code = match (code_arg, world, address) {
+        (Some(arg), _, _) => read_code_arg(&arg),
+        (None, Some(path), Some(addr)) => {
+            let addr = parse_h160(&addr).unwrap_or_else(|| die("invalid --address"));
+            load_world(&path)
+                .accounts
+                .remove(&addr)
+                .map(|a| a.code)
+                .unwrap_or_else(|| die("no such account in world"))
+        }
+        _ => die("pass code, or --world with --address"),
+    };
+    let db = db.map_or_else(SignatureDb::default, |path| {
+        let text = std::fs::read_to_string(&path).unwrap_or_else(|e| die(&format!("read db: {e}")));
+        SignatureDb::parse(&text).unwrap_or_else(|e| die(&format!("signature db: {e}")))
+    });
+    let found = find_selectors(&code);
+    if json {
+        let out: Vec<serde_json::Value> = found
+            .iter()
+            .map(|s| {
+                serde_json::json!({
+                    "selector": format!("0x{}", hex(&s.selector)),
+                    "entry": s.entry,
+                    "pc": s.pc,
+                    "signatures": db.lookup(s.selector),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&out).unwrap());
+        return;
+    }
+    println!("selectors: {}", found.len());
+    for s in &found {
+        let mut line = format!("  0x{} entry=0x{:04x}", hex(&s.selector), s.entry);
+        let sigs = db.lookup(s.selector);
+        if !sigs.is_empty() {
+            line.push(' ');
+            line.push_str(&sigs.join(" | "));
+        }
+        println!("{line}");
+    }
+}
+
+fn asm_cmd(source: &str) {
+    let path = source
+        .strip_prefix('@')
+        .or(is_easm(source).then_some(source));
+    let text = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .unwrap_or_else(|e| die(&format!("Failed to read file: {e}"))),
+        None => source.to_string(),
+    };
+    let code = assemble(&text).unwrap_or_else(|e| die(&format!("asm: {e}")));
+    println!("0x{}", hex(&code));
 }
 
+#[allow(clippy::too_many_arguments)]
 fn trace_cmd(
     code_arg: &str,
     calldata_hex: &str,
@@ -267,6 +925,9 @@ fn trace_cmd(
     world_path: Option<&str>,
     address_hex: Option<&str>,
     caller_hex: Option<&str>,
+    json: bool,
+    sources: &SourceArgs,
+    abis: &AbiArgs,
 ) {
     let code = read_code_arg(code_arg);
     let calldata = parse_hex(calldata_hex).unwrap_or_else(|| die("Invalid calldata hex"));
@@ -281,20 +942,34 @@ fn trace_cmd(
         cfg.world = Some(load_world(path));
     }
     let mut evm = Evm::new(code, cfg);
+    if json {
+        return trace_json(&mut evm, max_steps);
+    }
 
+    let maps = load_source_maps(sources);
+    let errors = load_abis(abis).errors;
     let mut steps = 0usize;
     loop {
-        if evm.pc >= evm.code.len() || evm.halted.is_some() || steps >= max_steps {
-            println!("-- halt: {} --", halt_status(&evm));
+        let result = evm.result();
+        if result.is_some() || steps >= max_steps {
+            println!("-- halt: {} --", halt_status(result.as_ref()));
             if !evm.return_data.is_empty() {
                 println!("return: 0x{}", hex(&evm.return_data));
             }
+            if let Some(reason) = result.and_then(|r| r.revert_reason(&errors)) {
+                println!("revert: {reason}");
+            }
             println!("gas left: {}", evm.gas);
             break;
         }
         let op = evm.code[evm.pc];
+        let loc = maps
+            .get(&keccak256(&evm.code))
+            .and_then(|m| m.location(evm.pc))
+            .map(|l| format!("  {l} {}", l.snippet))
+            .unwrap_or_default();
         println!(
-            "pc={:04x} op=0x{:02x} {:8} stack={:2} top={} gas={}",
+            "pc={:04x} op=0x{:02x} {:8} stack={:2} top={} gas={}{}",
             evm.pc,
             op,
             opcode_name(op),
@@ -304,43 +979,259 @@ fn trace_cmd(
                 .map(|v| format!("0x{:x}", v))
                 .unwrap_or_else(|| "-".to_string()),
             evm.gas,
+            loc,
         );
+        let pc = evm.pc;
         if let Err(e) = evm.step() {
-            die(&format!("step error: {e}"));
+            let note = source_note(&maps, &evm.code, pc);
+            die(&format!("step error: {e}{note}"));
+        }
+        steps += 1;
+    }
+}
+
+fn proof_cmd(world_path: &str, address_hex: &str, slots: &[String]) {
+    let world = load_world(world_path);
+    let address = parse_h160(address_hex).unwrap_or_else(|| die("invalid --address"));
+    let slots: Vec<_> = slots
+        .iter()
+        .map(|s| parse_u256(s).unwrap_or_else(|| die("invalid --slot")))
+        .collect();
+    let proof = world.proof(address, &slots);
+    let mut out = proof.to_json();
+    out["stateRoot"] = format!("0x{}", hex(world.state_root().as_bytes())).into();
+    println!("{}", serde_json::to_string_pretty(&out).unwrap());
+}
+
+fn statetest_cmd(path: &str, fork: Option<&str>) {
+    let root = std::path::Path::new(path);
+    let mut files = Vec::new();
+    if root.is_file() {
+        files.push(root.to_path_buf());
+    } else {
+        collect_json_files(root, &mut files);
+        files.sort();
+    }
+    if files.is_empty() {
+        die(&format!("no fixtures found at {path}"));
+    }
+    let mut results = Vec::new();
+    let mut errors = Vec::new();
+    for file in &files {
+        let txt = std::fs::read_to_string(file)
+            .unwrap_or_else(|e| die(&format!("read {}: {e}", file.display())));
+        match run_state_tests(&txt, fork) {
+            Ok(r) => results.extend(r),
+            Err(e @ statetest::StateTestError::UnsupportedFork(_)) => die(&e.to_string()),
+            Err(e) => errors.push(serde_json::json!({
+                "file": file.display().to_string(),
+                "error": e.to_string(),
+            })),
+        }
+    }
+    let (passed, failed) = statetest::summarize(&results);
+    let out = serde_json::json!({
+        "passed": passed,
+        "failed": failed,
+        "errors": errors,
+        "results": results.iter().map(|r| r.to_json()).collect::<Vec<_>>(),
+    });
+    println!("{}", serde_json::to_string_pretty(&out).unwrap());
+    eprintln!(
+        "{passed}/{} tests passed, {} fixture(s) could not be loaded",
+        results.len(),
+        errors.len()
+    );
+    if failed > 0 || !errors.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn collect_json_files(path: &std::path::Path, out: &mut Vec<std::path::PathBuf>) {
+    if path.is_dir() {
+        let entries = std::fs::read_dir(path).unwrap_or_else(|e| die(&format!("read dir: {e}")));
+        for entry in entries.flatten() {
+            collect_json_files(&entry.path(), out);
+        }
+    } else if path.extension().is_some_and(|e| e == "json") {
+        out.push(path.to_path_buf());
+    }
+}
+
+// Exit codes follow geth's t8n: 3 bad configuration, 10 bad input, 11 I/O failure
+fn t8n_fail(code: i32, msg: &str) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(code);
+}
+
+fn t8n_cmd(
+    inputs: [&str; 3],
+    basedir: &str,
+    output_result: &str,
+    output_alloc: &str,
+    fork: &str,
+    chain_id: &str,
+    reward: &str,
+) {
+    // Inputs named `stdin` come from a single {"alloc", "env", "txs"} object
+    let stdin: Option<serde_json::Value> = inputs.contains(&"stdin").then(|| {
+        serde_json::from_reader(std::io::stdin())
+            .unwrap_or_else(|e| t8n_fail(10, &format!("parse stdin: {e}")))
+    });
+    let [alloc, env, txs] = [("alloc", inputs[0]), ("env", inputs[1]), ("txs", inputs[2])].map(
+        |(key, path)| -> serde_json::Value {
+            if path == "stdin" {
+                return stdin.as_ref().unwrap()[key].clone();
+            }
+            let txt = std::fs::read_to_string(path)
+                .unwrap_or_else(|e| t8n_fail(11, &format!("read {path}: {e}")));
+            serde_json::from_str(&txt)
+                .unwrap_or_else(|e| t8n_fail(10, &format!("parse {path}: {e}")))
+        },
+    );
+    let opts = t8n::T8nOptions {
+        fork: fork.to_string(),
+        chain_id: parse_u256(chain_id).unwrap_or_else(|| t8n_fail(3, "invalid --state.chainid")),
+        reward: match reward {
+            "-1" => None,
+            r => Some(parse_u256(r).unwrap_or_else(|| t8n_fail(3, "invalid --state.reward"))),
+        },
+    };
+    let run = || -> Result<_, t8n::T8nError> {
+        let pre = t8n::parse_alloc_json(&alloc)?;
+        let env = t8n::parse_env(&env)?;
+        let txs = t8n::parse_txs(&txs, opts.chain_id)?;
+        t8n::transition(pre, &env, &txs, &opts)
+    };
+    let (post, result) = run().unwrap_or_else(|e| match e {
+        t8n::T8nError::UnsupportedFork(_) => t8n_fail(3, &e.to_string()),
+        _ => t8n_fail(10, &e.to_string()),
+    });
+
+    let mut stdout = serde_json::Map::new();
+    for (key, name, value) in [
+        ("result", output_result, result),
+        ("alloc", output_alloc, t8n::alloc_to_json(&post)),
+    ] {
+        if name == "stdout" {
+            stdout.insert(key.to_string(), value);
+            continue;
+        }
+        let path = std::path::Path::new(basedir).join(name);
+        let txt = serde_json::to_string_pretty(&value).unwrap();
+        std::fs::write(&path, txt)
+            .unwrap_or_else(|e| t8n_fail(11, &format!("write {}: {e}", path.display())));
+    }
+    if !stdout.is_empty() {
+        println!("{}", serde_json::to_string_pretty(&stdout).unwrap());
+    }
+}
+
+fn trace_json(evm: &mut Evm, max_steps: usize) {
+    let mut tracer = Eip3155Tracer::default();
+    let mut steps = 0usize;
+    let result = loop {
+        if let Some(result) = evm.result() {
+            break result;
+        }
+        if steps >= max_steps {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "output": "0x",
+                    "gasUsed": format!("0x{:x}", evm.gas_limit - evm.gas),
+                    "pass": false,
+                    "error": "step limit reached",
+                })
+            );
+            return;
+        }
+        let res = evm.step_with(&mut tracer);
+        // Nested frames finish within the outer step, so every line is complete here
+        for step in std::mem::take(&mut tracer.steps) {
+            println!("{}", step.to_json());
+        }
+        if let Err(reason) = res {
+            break ExecutionResult::Halt {
+                reason,
+                gas_used: evm.gas_limit.max(0) as u64,
+            };
         }
         steps += 1;
+    };
+    let mut end = tracer::summary(&result);
+    if let Some(world) = &evm.world {
+        end["stateRoot"] = format!("0x{}", hex(world.state_root().as_bytes())).into();
     }
+    println!("{}", end);
 }
 
 fn read_code_arg(arg: &str) -> Vec<u8> {
-    if let Some(rest) = arg.strip_prefix('@') {
+    let path = arg.strip_prefix('@').unwrap_or(arg);
+    if is_easm(path) {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| die(&format!("Failed to read file: {e}")));
+        assemble(&text).unwrap_or_else(|e| die(&format!("asm: {e}")))
+    } else if let Some(rest) = arg.strip_prefix('@') {
         std::fs::read(rest).unwrap_or_else(|e| die(&format!("Failed to read file: {e}")))
     } else {
         parse_hex(arg).unwrap_or_else(|| die("Invalid code hex"))
     }
 }
 
-fn parse_hex(s: &str) -> Option<Vec<u8>> {
-    let s = s.trim();
-    let s = s.strip_prefix("0x").unwrap_or(s);
-    if s.is_empty() {
-        return Some(Vec::new());
-    }
-    if s.len() % 2 != 0 {
-        return None;
+fn is_easm(path: &str) -> bool {
+    path.ends_with(".easm")
+}
+
+fn load_source_maps(args: &SourceArgs) -> HashMap<H256, SourceMap> {
+    let Some(path) = &args.solc else {
+        return HashMap::new();
+    };
+    let txt =
+        std::fs::read_to_string(path).unwrap_or_else(|e| die(&format!("read solc output: {e}")));
+    let v: serde_json::Value =
+        serde_json::from_str(&txt).unwrap_or_else(|e| die(&format!("parse solc output: {e}")));
+    let out = SolcOutput::parse(&v).unwrap_or_else(|e| die(&format!("solc output: {e}")));
+    let root = std::path::Path::new(&args.source_root);
+    let sources: Vec<(String, String)> = out
+        .source_list
+        .iter()
+        .map(|name| {
+            let text = std::fs::read_to_string(root.join(name))
+                .unwrap_or_else(|e| die(&format!("read source {name}: {e}")));
+            (name.clone(), text)
+        })
+        .collect();
+    out.source_maps(&sources).unwrap_or_else(|e| die(&e))
+}
+
+fn load_abis(args: &AbiArgs) -> Abi {
+    let mut abi = Abi::default();
+    for path in &args.abis {
+        let txt =
+            std::fs::read_to_string(path).unwrap_or_else(|e| die(&format!("read abi {path}: {e}")));
+        let v: serde_json::Value =
+            serde_json::from_str(&txt).unwrap_or_else(|e| die(&format!("parse abi {path}: {e}")));
+        abi.extend(Abi::from_json(&v).unwrap_or_else(|e| die(&format!("abi {path}: {e}"))));
     }
-    (0..s.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
-        .collect()
+    abi
+}
+
+// "--> file:line:col" plus the source snippet for `pc` in `code`, if mapped
+fn source_note(maps: &HashMap<H256, SourceMap>, code: &[u8], pc: usize) -> String {
+    maps.get(&keccak256(code))
+        .and_then(|m| m.location(pc))
+        .map(|l| format!("\n  --> {l}\n   | {}", l.snippet))
+        .unwrap_or_default()
 }
 
-fn hex(bytes: &[u8]) -> String {
-    let mut s = String::with_capacity(bytes.len() * 2);
-    for b in bytes {
-        s.push_str(&format!("{:02x}", b));
+// Literal text, or the contents of a file given as @path
+fn read_text_arg(arg: &str) -> String {
+    match arg.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path)
+            .unwrap_or_else(|e| die(&format!("Failed to read file: {e}"))),
+        None => arg.to_string(),
     }
-    s
 }
 
 fn die(msg: &str) -> ! {
@@ -348,69 +1239,16 @@ fn die(msg: &str) -> ! {
     std::process::exit(1);
 }
 
-fn halt_status(evm: &Evm) -> &'static str {
-    match &evm.halted {
-        Some(h) => match h {
-            evm_in_rust::machine::Halt::Stop => "STOP",
-            evm_in_rust::machine::Halt::Return => "RETURN",
-            evm_in_rust::machine::Halt::Revert => "REVERT",
+fn halt_status(result: Option<&ExecutionResult>) -> &'static str {
+    match result {
+        Some(ExecutionResult::Success { reason, .. }) => match reason {
+            SuccessReason::Stop => "STOP",
+            SuccessReason::Return => "RETURN",
+            SuccessReason::EndOfCode => "EOF",
         },
-        None => {
-            if evm.pc >= evm.code.len() {
-                "EOF"
-            } else {
-                "RUNNING"
-            }
-        }
-    }
-}
-
-fn opcode_name(op: u8) -> &'static str {
-    use evm_in_rust::opcodes::*;
-    match op {
-        STOP => "STOP",
-        ADD => "ADD",
-        MUL => "MUL",
-        SUB => "SUB",
-        DIV => "DIV",
-        LT => "LT",
-        GT => "GT",
-        EQ => "EQ",
-        ISZERO => "ISZERO",
-        AND => "AND",
-        OR => "OR",
-        XOR => "XOR",
-        NOT => "NOT",
-        SHA3 => "SHA3",
-        POP => "POP",
-        MLOAD => "MLOAD",
-        MSTORE => "MSTORE",
-        MSTORE8 => "MSTORE8",
-        SLOAD => "SLOAD",
-        SSTORE => "SSTORE",
-        JUMP => "JUMP",
-        JUMPI => "JUMPI",
-        JUMPDEST => "JUMPDEST",
-        PUSH0 => "PUSH0",
-        PC => "PC",
-        MSIZE => "MSIZE",
-        GAS => "GAS",
-        CALLDATALOAD => "CALLDATALOAD",
-        CALLDATASIZE => "CALLDATASIZE",
-        CALLDATACOPY => "CALLDATACOPY",
-        CODESIZE => "CODESIZE",
-        CODECOPY => "CODECOPY",
-        RETURN => "RETURN",
-        REVERT => "REVERT",
-        LOG0 => "LOG0",
-        LOG1 => "LOG1",
-        LOG2 => "LOG2",
-        LOG3 => "LOG3",
-        LOG4 => "LOG4",
-        x if (PUSH1..=PUSH32).contains(&x) => "PUSHn",
-        x if (DUP1..=DUP16).contains(&x) => "DUPn",
-        x if (SWAP1..=SWAP16).contains(&x) => "SWAPn",
-        _ => "?",
+        Some(ExecutionResult::Revert { .. }) => "REVERT",
+        Some(ExecutionResult::Halt { .. }) => "HALT",
+        None => "RUNNING",
     }
 }
 
@@ -434,36 +1272,12 @@ fn world_to_json(world: Option<&World>) -> String {
             );
         }
     }
-    serde_json::to_string_pretty(&json!({"accounts": Value::Object(accounts)})).unwrap()
-}
-
-fn parse_h160(s: &str) -> Option<H160> {
-    let b = parse_hex(s)?;
-    if b.len() != 20 {
-        return None;
-    }
-    Some(H160::from_slice(&b))
-}
-
-fn parse_u256(s: &str) -> Option<U256> {
-    let s = s.trim();
-    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
-        // Accept odd-length hex (e.g., 0x0) by padding a leading zero nibble
-        let padded = if hex.len() % 2 == 1 {
-            format!("0x0{}", hex)
-        } else {
-            format!("0x{}", hex)
-        };
-        let b = parse_hex(&padded)?;
-        let mut buf = [0u8; 32];
-        if b.len() > 32 {
-            return None;
-        }
-        buf[32 - b.len()..].copy_from_slice(&b);
-        Some(U256::from_big_endian(&buf))
-    } else {
-        s.parse::<u128>().ok().map(U256::from)
-    }
+    let state_root = world.map(|w| w.state_root()).unwrap_or(EMPTY_ROOT);
+    serde_json::to_string_pretty(&json!({
+        "stateRoot": format!("0x{}", hex(state_root.as_bytes())),
+        "accounts": Value::Object(accounts),
+    }))
+    .unwrap()
 }
 
 fn load_world(path: &str) -> World {
@@ -477,6 +1291,13 @@ fn load_world(path: &str) -> World {
         for (k, val) in accs {
             let addr = parse_h160(k).unwrap_or_else(|| die("invalid account key"));
             let mut a = Account::default();
+            if let Some(nonce) = val.get("nonce").and_then(|x| match x {
+                serde_json::Value::Number(n) => n.as_u64(),
+                serde_json::Value::String(s) => parse_u256(s).map(|v| v.low_u64()),
+                _ => None,
+            }) {
+                a.nonce = nonce;
+            }
             if let Some(bal) = val
                 .get("balance")
                 .and_then(|x| x.as_str())
@@ -1,17 +1,7 @@
-use evm_in_rust::{Evm, EvmConfig};
+use evm_in_rust::hexutil::parse_hex;
+use evm_in_rust::{Evm, EvmConfig, ExecutionResult};
 use std::env;
 
-fn parse_hex(s: &str) -> Option<Vec<u8>> {
-    let s = s.strip_prefix("0x").unwrap_or(s);
-    if s.len() % 2 != 0 {
-        return None;
-    }
-    (0..s.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
-        .collect()
-}
-
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
@@ -35,7 +25,11 @@ fn main() {
     };
     let mut evm = Evm::new(code, cfg);
     match evm.run() {
-        Ok(()) => {
+        ExecutionResult::Halt { reason, .. } => {
+            eprintln!("Execution error: {reason}");
+            std::process::exit(2);
+        }
+        _ => {
             println!("pc: {}", evm.pc);
             println!("gas left: {}", evm.gas);
             println!("stack size: {}", evm.stack.len());
@@ -43,9 +37,5 @@ fn main() {
                 println!("top: 0x{:x}", top);
             }
         }
-        Err(e) => {
-            eprintln!("Execution error: {e}");
-            std::process::exit(2);
-        }
     }
 }
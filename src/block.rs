@@ -0,0 +1,351 @@
+use primitive_types::{H160, H256, U256};
+use thiserror::Error;
+
+use crate::machine::{keccak256, BlockEnv, Evm, LogEntry, World};
+use crate::rlp;
+use crate::transaction::{Transaction, TxError};
+use crate::trie::{ordered_trie_root, u256_bytes};
+
+// 2048-bit logs bloom (yellow paper M3:2048)
+#[derive(Clone, PartialEq, Eq)]
+pub struct Bloom(pub [u8; 256]);
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom([0u8; 256])
+    }
+}
+
+impl std::fmt::Debug for Bloom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bloom(0x")?;
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Bloom {
+    pub fn accrue(&mut self, input: &[u8]) {
+        let hash = keccak256(input);
+        for i in 0..3 {
+            let bit = (((hash[2 * i] as usize) << 8) | hash[2 * i + 1] as usize) & 2047;
+            self.0[255 - bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    pub fn accrue_log(&mut self, log: &LogEntry) {
+        self.accrue(log.address.as_bytes());
+        for topic in &log.topics {
+            let mut buf = [0u8; 32];
+            topic.to_big_endian(&mut buf);
+            self.accrue(&buf);
+        }
+    }
+
+    pub fn accrue_bloom(&mut self, other: &Bloom) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a |= b;
+        }
+    }
+
+    // May return false positives, never false negatives
+    pub fn contains_input(&self, input: &[u8]) -> bool {
+        let mut single = Bloom::default();
+        single.accrue(input);
+        single
+            .0
+            .iter()
+            .zip(self.0.iter())
+            .all(|(bit, ours)| bit & ours == *bit)
+    }
+
+    pub fn from_logs(logs: &[LogEntry]) -> Self {
+        let mut bloom = Bloom::default();
+        for log in logs {
+            bloom.accrue_log(log);
+        }
+        bloom
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub tx_type: u8,
+    pub success: bool,
+    pub gas_used: u64,
+    pub cumulative_gas_used: u64,
+    pub logs: Vec<LogEntry>,
+    pub logs_bloom: Bloom,
+    pub contract_address: Option<H160>,
+}
+
+impl Receipt {
+    // EIP-2718 envelope around rlp([status, cumulativeGasUsed, logsBloom, logs])
+    pub fn encode(&self) -> Vec<u8> {
+        let payload = rlp::encode_list(&[
+            rlp::encode_u64(self.success as u64),
+            rlp::encode_u64(self.cumulative_gas_used),
+            rlp::encode_bytes(&self.logs_bloom.0),
+            encode_logs(&self.logs),
+        ]);
+        if self.tx_type == 0 {
+            return payload;
+        }
+        let mut out = vec![self.tx_type];
+        out.extend(payload);
+        out
+    }
+}
+
+impl LogEntry {
+    // rlp([address, [topics...], data])
+    pub fn rlp_encode(&self) -> Vec<u8> {
+        let topics: Vec<Vec<u8>> = self
+            .topics
+            .iter()
+            .map(|t| rlp::encode_bytes(&u256_bytes(*t)))
+            .collect();
+        rlp::encode_list(&[
+            rlp::encode_bytes(self.address.as_bytes()),
+            rlp::encode_list(&topics),
+            rlp::encode_bytes(&self.data),
+        ])
+    }
+}
+
+pub fn encode_logs(logs: &[LogEntry]) -> Vec<u8> {
+    rlp::encode_list(&logs.iter().map(|l| l.rlp_encode()).collect::<Vec<_>>())
+}
+
+// keccak256(rlp(logs)), the "logs hash" reported by state tests and t8n
+pub fn logs_hash(logs: &[LogEntry]) -> H256 {
+    keccak256(&encode_logs(logs))
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BlockError {
+    #[error("transaction {index}: gas limit {gas_limit} exceeds remaining block gas {available}")]
+    GasLimitExceeded {
+        index: usize,
+        gas_limit: u64,
+        available: U256,
+    },
+    #[error("transaction {index}: {source}")]
+    InvalidTransaction {
+        index: usize,
+        #[source]
+        source: TxError,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockResult {
+    pub receipts: Vec<Receipt>,
+    pub gas_used: u64,
+    pub logs_bloom: Bloom,
+    pub transactions_root: H256,
+    pub receipts_root: H256,
+}
+
+// Applies transactions one at a time on top of `world`; an invalid transaction
+// is reported without touching state so callers can skip it and continue.
+#[derive(Debug, Clone)]
+pub struct BlockExecutor {
+    pub world: World,
+    pub block: BlockEnv,
+    pub transactions: Vec<Transaction>,
+    pub receipts: Vec<Receipt>,
+    pub gas_used: u64,
+}
+
+impl BlockExecutor {
+    pub fn new(world: World, block: BlockEnv) -> Self {
+        Self {
+            world,
+            block,
+            transactions: Vec::new(),
+            receipts: Vec::new(),
+            gas_used: 0,
+        }
+    }
+
+    pub fn execute_transaction(&mut self, tx: &Transaction) -> Result<&Receipt, BlockError> {
+        let index = self.receipts.len();
+        let available = self
+            .block
+            .gas_limit
+            .saturating_sub(U256::from(self.gas_used));
+        if U256::from(tx.gas_limit) > available {
+            return Err(BlockError::GasLimitExceeded {
+                index,
+                gas_limit: tx.gas_limit,
+                available,
+            });
+        }
+        let res = Evm::transact(&mut self.world, &self.block, tx)
+            .map_err(|source| BlockError::InvalidTransaction { index, source })?;
+        self.gas_used += res.gas_used();
+        let logs = res.logs().to_vec();
+        self.receipts.push(Receipt {
+            tx_type: tx.tx_type,
+            success: res.is_success(),
+            gas_used: res.gas_used(),
+            cumulative_gas_used: self.gas_used,
+            logs_bloom: Bloom::from_logs(&logs),
+            logs,
+            contract_address: res.created_address,
+        });
+        self.transactions.push(tx.clone());
+        Ok(&self.receipts[index])
+    }
+
+    pub fn finish(self) -> (World, BlockResult) {
+        let mut logs_bloom = Bloom::default();
+        for r in &self.receipts {
+            logs_bloom.accrue_bloom(&r.logs_bloom);
+        }
+        let transactions_root = ordered_trie_root(
+            &self
+                .transactions
+                .iter()
+                .map(|tx| tx.encode())
+                .collect::<Vec<_>>(),
+        );
+        let receipts_root =
+            ordered_trie_root(&self.receipts.iter().map(|r| r.encode()).collect::<Vec<_>>());
+        (
+            self.world,
+            BlockResult {
+                receipts: self.receipts,
+                gas_used: self.gas_used,
+                logs_bloom,
+                transactions_root,
+                receipts_root,
+            },
+        )
+    }
+}
+
+// Apply all transactions in order. Any invalid transaction invalidates the whole
+// block and leaves `world` unchanged.
+pub fn execute_block(
+    world: &mut World,
+    block: &BlockEnv,
+    txs: &[Transaction],
+) -> Result<BlockResult, BlockError> {
+    let mut executor = BlockExecutor::new(world.clone(), block.clone());
+    for tx in txs {
+        executor.execute_transaction(tx)?;
+    }
+    let (post, result) = executor.finish();
+    *world = post;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Account;
+    use crate::transaction::TxKind;
+
+    fn setup() -> (World, BlockEnv, H160, H160) {
+        let sender = H160::from_low_u64_be(0xaa);
+        let logger = H160::from_low_u64_be(0xbb);
+        let mut world = World::default();
+        world.accounts.insert(
+            sender,
+            Account {
+                balance: U256::from(10_000_000u64),
+                ..Account::default()
+            },
+        );
+        // PUSH1 0x42 (topic); PUSH1 0; PUSH1 0; LOG1; STOP
+        world.accounts.insert(
+            logger,
+            Account {
+                code: vec![0x60, 0x42, 0x60, 0x00, 0x60, 0x00, 0xa1, 0x00],
+                ..Account::default()
+            },
+        );
+        let block = BlockEnv {
+            coinbase: H160::from_low_u64_be(0xcc),
+            gas_limit: U256::from(100_000),
+            basefee: U256::from(10),
+            ..BlockEnv::default()
+        };
+        (world, block, sender, logger)
+    }
+
+    #[test]
+    fn receipts_accumulate_gas_and_blooms() {
+        let (mut world, block, sender, logger) = setup();
+        let txs: Vec<Transaction> = (0..2)
+            .map(|nonce| Transaction {
+                tx_type: 2,
+                caller: sender,
+                nonce,
+                gas_limit: 40_000,
+                gas_price: U256::from(12),
+                max_priority_fee_per_gas: Some(U256::from(1)),
+                to: TxKind::Call(logger),
+                ..Transaction::default()
+            })
+            .collect();
+        let result = execute_block(&mut world, &block, &txs).unwrap();
+        assert_eq!(result.receipts.len(), 2);
+        let first = &result.receipts[0];
+        assert_eq!(first.tx_type, 2);
+        assert!(first.success);
+        assert_eq!(first.logs.len(), 1);
+        assert_eq!(first.logs[0].address, logger);
+        assert_eq!(result.receipts[1].cumulative_gas_used, 2 * first.gas_used);
+        assert_eq!(result.gas_used, 2 * first.gas_used);
+        assert!(result.logs_bloom.contains_input(logger.as_bytes()));
+        let mut topic = [0u8; 32];
+        topic[31] = 0x42;
+        assert!(first.logs_bloom.contains_input(&topic));
+        // Only the priority fee reaches the coinbase; the basefee is burned.
+        assert_eq!(
+            world.accounts[&block.coinbase].balance,
+            U256::from(result.gas_used)
+        );
+    }
+
+    #[test]
+    fn empty_block_has_empty_roots() {
+        let (mut world, block, _, _) = setup();
+        let result = execute_block(&mut world, &block, &[]).unwrap();
+        assert_eq!(result.transactions_root, crate::trie::EMPTY_ROOT);
+        assert_eq!(result.receipts_root, crate::trie::EMPTY_ROOT);
+    }
+
+    #[test]
+    fn block_gas_limit_is_enforced() {
+        let (mut world, block, sender, logger) = setup();
+        let txs: Vec<Transaction> = (0..2)
+            .map(|nonce| Transaction {
+                caller: sender,
+                nonce,
+                gas_limit: 40_000,
+                gas_price: U256::from(10),
+                to: TxKind::Call(logger),
+                ..Transaction::default()
+            })
+            .collect();
+        let mut tight = block.clone();
+        tight.gas_limit = U256::from(60_000);
+        // The first call uses 21_017 gas, leaving less than the second one's limit.
+        let err = execute_block(&mut world, &tight, &txs).unwrap_err();
+        assert_eq!(
+            err,
+            BlockError::GasLimitExceeded {
+                index: 1,
+                gas_limit: 40_000,
+                available: U256::from(60_000 - 21_017),
+            }
+        );
+        assert_eq!(world.accounts[&sender].nonce, 0);
+    }
+}
@@ -0,0 +1,305 @@
+// Call tree tracer producing geth's `callTracer` JSON.
+
+use primitive_types::{H160, U256};
+use serde_json::{json, Value};
+
+use crate::abi::{decode_revert, RevertReason};
+use crate::hexutil::hex;
+use crate::inspector::{
+    CallInputs, CallScheme, CreateInputs, CreateOutcome, CreateScheme, Inspector,
+};
+use crate::machine::{Evm, ExecutionResult, LogEntry};
+use crate::trie::u256_bytes;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallLog {
+    pub address: H160,
+    pub topics: Vec<U256>,
+    pub data: Vec<u8>,
+    // Number of sub-calls made by the frame before this log was emitted
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallFrame {
+    // CALL, STATICCALL, DELEGATECALL, CALLCODE, CREATE or CREATE2
+    pub kind: &'static str,
+    pub from: H160,
+    pub to: Option<H160>,
+    pub value: Option<U256>,
+    pub gas: u64,
+    pub gas_used: u64,
+    pub input: Vec<u8>,
+    pub output: Vec<u8>,
+    pub error: Option<String>,
+    pub revert_reason: Option<String>,
+    pub calls: Vec<CallFrame>,
+    pub logs: Vec<CallLog>,
+}
+
+impl CallFrame {
+    // The outermost frame of `evm`, which inspectors never see being entered
+    pub fn root(evm: &Evm) -> Self {
+        Self {
+            kind: "CALL",
+            from: evm.caller.unwrap_or_default(),
+            to: Some(evm.address.unwrap_or_default()),
+            value: Some(evm.callvalue),
+            gas: evm.gas_limit.max(0) as u64,
+            gas_used: 0,
+            input: evm.calldata.clone(),
+            output: Vec::new(),
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+            logs: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, result: &ExecutionResult) {
+        self.gas_used = result.gas_used();
+        self.output = result.output().to_vec();
+        match result {
+            ExecutionResult::Success { .. } => {}
+            ExecutionResult::Revert { output, .. } => {
+                self.error = Some("execution reverted".to_string());
+                // Only `Error(string)` reverts get a `revertReason`
+                self.revert_reason = match decode_revert(output, &[]) {
+                    Some(RevertReason::Error(msg)) => Some(msg),
+                    _ => None,
+                };
+            }
+            ExecutionResult::Halt { reason, .. } => self.error = Some(reason.to_string()),
+        }
+        if !result.is_success() {
+            self.clear_logs();
+        }
+    }
+
+    // Logs of a failed frame (and everything under it) never make it on chain
+    fn clear_logs(&mut self) {
+        self.logs.clear();
+        for call in &mut self.calls {
+            call.clear_logs();
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut v = json!({
+            "type": self.kind,
+            "from": format!("0x{}", hex(self.from.as_bytes())),
+            "gas": format!("0x{:x}", self.gas),
+            "gasUsed": format!("0x{:x}", self.gas_used),
+            "input": format!("0x{}", hex(&self.input)),
+        });
+        if let Some(to) = self.to {
+            v["to"] = format!("0x{}", hex(to.as_bytes())).into();
+        }
+        if let Some(value) = self.value {
+            v["value"] = format!("0x{:x}", value).into();
+        }
+        if !self.output.is_empty() {
+            v["output"] = format!("0x{}", hex(&self.output)).into();
+        }
+        if let Some(err) = &self.error {
+            v["error"] = err.clone().into();
+        }
+        if let Some(reason) = &self.revert_reason {
+            v["revertReason"] = reason.clone().into();
+        }
+        if !self.logs.is_empty() {
+            v["logs"] = self
+                .logs
+                .iter()
+                .map(|l| {
+                    json!({
+                        "address": format!("0x{}", hex(l.address.as_bytes())),
+                        "topics": l.topics.iter().map(|t| format!("0x{}", hex(&u256_bytes(*t)))).collect::<Vec<_>>(),
+                        "data": format!("0x{}", hex(&l.data)),
+                        "position": format!("0x{:x}", l.position),
+                    })
+                })
+                .collect();
+        }
+        if !self.calls.is_empty() {
+            v["calls"] = self.calls.iter().map(|c| c.to_json()).collect();
+        }
+        v
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CallTracer {
+    pub with_log: bool,
+    // Frames entered but not yet finished; the first one is the root
+    stack: Vec<CallFrame>,
+}
+
+impl CallTracer {
+    pub fn new(root: CallFrame, with_log: bool) -> Self {
+        Self {
+            with_log,
+            stack: vec![root],
+        }
+    }
+
+    // Complete the root frame with the outcome of the whole execution
+    pub fn finish(mut self, result: &ExecutionResult) -> CallFrame {
+        let mut root = self.stack.swap_remove(0);
+        root.apply(result);
+        root
+    }
+
+    fn enter(&mut self, frame: CallFrame) {
+        self.stack.push(frame);
+    }
+
+    fn exit(&mut self, result: &ExecutionResult, to: Option<H160>) {
+        if self.stack.len() < 2 {
+            return;
+        }
+        let mut frame = self.stack.pop().unwrap();
+        if to.is_some() {
+            frame.to = to;
+        }
+        frame.apply(result);
+        self.stack.last_mut().unwrap().calls.push(frame);
+    }
+}
+
+impl Inspector for CallTracer {
+    fn call(&mut self, _evm: &Evm, inputs: &CallInputs) -> Option<ExecutionResult> {
+        let (kind, value) = match inputs.scheme {
+            CallScheme::Call => ("CALL", Some(inputs.value)),
+            CallScheme::CallCode => ("CALLCODE", Some(inputs.value)),
+            CallScheme::StaticCall => ("STATICCALL", None),
+            CallScheme::DelegateCall => ("DELEGATECALL", None),
+        };
+        // For DELEGATECALL/CALLCODE, `to` is the code that runs, as in geth
+        self.enter(CallFrame {
+            kind,
+            from: match inputs.scheme {
+                CallScheme::DelegateCall => inputs.target,
+                _ => inputs.caller,
+            },
+            to: Some(inputs.code_address),
+            value,
+            gas: inputs.gas_limit,
+            gas_used: 0,
+            input: inputs.input.clone(),
+            output: Vec::new(),
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+            logs: Vec::new(),
+        });
+        None
+    }
+
+    fn call_end(&mut self, _evm: &Evm, _inputs: &CallInputs, result: &mut ExecutionResult) {
+        self.exit(result, None);
+    }
+
+    fn create(&mut self, _evm: &Evm, inputs: &CreateInputs) -> Option<CreateOutcome> {
+        self.enter(CallFrame {
+            kind: match inputs.scheme {
+                CreateScheme::Create => "CREATE",
+                CreateScheme::Create2 { .. } => "CREATE2",
+            },
+            from: inputs.caller,
+            to: None,
+            value: Some(inputs.value),
+            gas: inputs.gas_limit,
+            gas_used: 0,
+            input: inputs.init_code.clone(),
+            output: Vec::new(),
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+            logs: Vec::new(),
+        });
+        None
+    }
+
+    fn create_end(&mut self, _evm: &Evm, _inputs: &CreateInputs, outcome: &mut CreateOutcome) {
+        self.exit(&outcome.result, outcome.address);
+    }
+
+    fn log(&mut self, _evm: &Evm, log: &LogEntry) {
+        if !self.with_log {
+            return;
+        }
+        if let Some(frame) = self.stack.last_mut() {
+            frame.logs.push(CallLog {
+                address: log.address,
+                topics: log.topics.clone(),
+                data: log.data.clone(),
+                position: frame.calls.len(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{Account, EvmConfig, World};
+
+    #[test]
+    fn builds_nested_call_tree() {
+        let inner = H160::repeat_byte(0xcc);
+        let outer = H160::repeat_byte(0xbb);
+        let mut world = World::default();
+        // inner: LOG0 (empty); REVERT(0, 0)
+        world.accounts.insert(
+            inner,
+            Account {
+                code: vec![0x60, 0x00, 0x60, 0x00, 0xa0, 0x60, 0x00, 0x60, 0x00, 0xfd],
+                ..Account::default()
+            },
+        );
+        // outer: LOG0; CALL inner with no args; STOP
+        let mut code = vec![0x60, 0x00, 0x60, 0x00, 0xa0];
+        code.extend_from_slice(&[
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73,
+        ]);
+        code.extend_from_slice(inner.as_bytes());
+        code.extend_from_slice(&[0x61, 0xff, 0xff, 0xf1, 0x00]);
+        world.accounts.insert(
+            outer,
+            Account {
+                code: code.clone(),
+                ..Account::default()
+            },
+        );
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                gas_limit: 100_000,
+                address: Some(outer),
+                caller: Some(H160::repeat_byte(0xaa)),
+                world: Some(world),
+                ..EvmConfig::default()
+            },
+        );
+        let mut tracer = CallTracer::new(CallFrame::root(&evm), true);
+        let result = evm.run_with(&mut tracer);
+        let root = tracer.finish(&result);
+        assert_eq!(root.gas, 100_000);
+        assert_eq!(root.gas_used, result.gas_used());
+        assert_eq!(root.logs.len(), 1);
+        assert_eq!(root.calls.len(), 1);
+        let call = &root.calls[0];
+        assert_eq!(call.kind, "CALL");
+        assert_eq!(call.from, outer);
+        assert_eq!(call.to, Some(inner));
+        assert_eq!(call.error.as_deref(), Some("execution reverted"));
+        // The reverted frame's log is dropped
+        assert!(call.logs.is_empty());
+        // Parent gas includes what the child used
+        assert!(root.gas_used > call.gas_used && call.gas_used > 0);
+        let json = root.to_json();
+        assert_eq!(json["calls"][0]["type"], "CALL");
+        assert_eq!(json["logs"][0]["position"], "0x0");
+    }
+}
@@ -0,0 +1,396 @@
+// Control-flow graph recovery for bytecode without source.
+//
+// Code is split into basic blocks at JUMPDESTs and after every jump or
+// terminating instruction. Jump targets are found by running each block on an
+// abstract stack that only tracks PUSHed constants, following every path from
+// the entry with its own stack. A return address pushed by the caller of a
+// Solidity internal function is carried through DUPs and SWAPs to the
+// function's final JUMP, so each call site gets its own return edge.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use serde_json::{json, Value};
+
+use crate::disasm::{data_start, disassemble, Instruction};
+use crate::machine::{scan_jumpdests, STACK_LIMIT};
+use crate::opcodes::*;
+
+// Distinct entry stacks explored per block before giving up on new ones
+const MAX_STATES: usize = 512;
+// Unknown items dead code is explored with
+const DEAD_STACK: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EdgeKind {
+    // Into the next block: no jump, or a JUMPI not taken
+    Fallthrough,
+    Jump,
+    // A JUMPI taken
+    Branch,
+}
+
+impl EdgeKind {
+    fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Jump => "jump",
+            EdgeKind::Branch => "branch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Edge {
+    // Block start offsets
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub instructions: Vec<Instruction>,
+}
+
+impl BasicBlock {
+    // Offset just past the block
+    pub fn end(&self) -> usize {
+        self.instructions.last().map_or(self.start, |i| i.next())
+    }
+
+    pub fn last(&self) -> &Instruction {
+        self.instructions.last().expect("blocks are never empty")
+    }
+
+    // Whether control can run past the last instruction into the next block
+    pub fn falls_through(&self) -> bool {
+        !matches!(
+            self.last().opcode,
+            JUMP | STOP | RETURN | REVERT | INVALID | SELFDESTRUCT
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    // Sorted by start offset; the first one is the entry
+    pub blocks: Vec<BasicBlock>,
+    pub edges: BTreeSet<Edge>,
+    // JUMPDEST offsets, as the interpreter sees them
+    pub jumpdests: HashSet<usize>,
+    // PCs of jumps whose target was not a known constant on some path
+    pub unresolved: BTreeSet<usize>,
+    // Constant jumps to offsets that are not JUMPDESTs, as (pc, target)
+    pub invalid_jumps: BTreeSet<(usize, usize)>,
+    // Blocks explored from the entry, as opposed to only as dead code
+    pub visited: BTreeSet<usize>,
+    // Stack heights each visited block is entered with, one per distinct path
+    // state explored
+    pub heights: BTreeMap<usize, BTreeSet<usize>>,
+}
+
+// A PUSHed constant that could be a code offset, or anything else
+type Val = Option<usize>;
+
+impl Cfg {
+    pub fn build(code: &[u8]) -> Cfg {
+        let end = data_start(code).unwrap_or(code.len());
+        let jumpdests = scan_jumpdests(&code[..end]);
+        let mut cfg = Cfg {
+            blocks: split_blocks(disassemble(code), &jumpdests),
+            jumpdests,
+            ..Cfg::default()
+        };
+        if cfg.blocks.is_empty() {
+            return cfg;
+        }
+        let index: HashMap<usize, usize> = cfg
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (b.start, i))
+            .collect();
+        let mut seen: Vec<HashSet<Vec<Val>>> = vec![HashSet::new(); cfg.blocks.len()];
+        cfg.explore(&index, &mut seen, vec![(0, Vec::new())]);
+        for (block, states) in cfg.blocks.iter().zip(&seen) {
+            if !states.is_empty() {
+                cfg.visited.insert(block.start);
+                cfg.heights
+                    .insert(block.start, states.iter().map(|s| s.len()).collect());
+            }
+        }
+        // Dead code still gets its own constant jumps resolved, starting from
+        // a stack of unknowns since nothing says what it would hold
+        for i in 0..cfg.blocks.len() {
+            if seen[i].is_empty() {
+                cfg.explore(&index, &mut seen, vec![(i, vec![None; DEAD_STACK])]);
+            }
+        }
+        cfg
+    }
+
+    pub fn block(&self, start: usize) -> Option<&BasicBlock> {
+        let i = self.blocks.binary_search_by_key(&start, |b| b.start).ok()?;
+        Some(&self.blocks[i])
+    }
+
+    pub fn successors(&self, start: usize) -> impl Iterator<Item = &Edge> + '_ {
+        self.edges.iter().filter(move |e| e.from == start)
+    }
+
+    pub fn predecessors(&self, start: usize) -> impl Iterator<Item = &Edge> + '_ {
+        self.edges.iter().filter(move |e| e.to == start)
+    }
+
+    fn explore(
+        &mut self,
+        index: &HashMap<usize, usize>,
+        seen: &mut [HashSet<Vec<Val>>],
+        mut work: Vec<(usize, Vec<Val>)>,
+    ) {
+        while let Some((i, stack)) = work.pop() {
+            if seen[i].len() >= MAX_STATES || !seen[i].insert(stack.clone()) {
+                continue;
+            }
+            let block = &self.blocks[i];
+            let mut stack = stack;
+            let (body, last) = block.instructions.split_at(block.instructions.len() - 1);
+            if body.iter().any(|ins| !exec(&mut stack, ins)) {
+                continue;
+            }
+            let last = &last[0];
+            let mut targets = Vec::new();
+            match last.opcode {
+                JUMP | JUMPI => {
+                    let (pops, _) = stack_effect(last.opcode);
+                    if stack.len() < pops {
+                        continue;
+                    }
+                    let target = stack.pop().flatten();
+                    let kind = if last.opcode == JUMP {
+                        EdgeKind::Jump
+                    } else {
+                        stack.pop();
+                        EdgeKind::Branch
+                    };
+                    match target {
+                        Some(t) if self.jumpdests.contains(&t) => targets.push((t, kind)),
+                        Some(t) => {
+                            self.invalid_jumps.insert((last.offset, t));
+                        }
+                        None => {
+                            self.unresolved.insert(last.offset);
+                        }
+                    }
+                }
+                _ => {
+                    if !exec(&mut stack, last) {
+                        continue;
+                    }
+                }
+            }
+            if block.falls_through() && i + 1 < self.blocks.len() {
+                targets.push((self.blocks[i + 1].start, EdgeKind::Fallthrough));
+            }
+            for (to, kind) in targets {
+                self.edges.insert(Edge {
+                    from: block.start,
+                    to,
+                    kind,
+                });
+                // Dead code may jump into live code, which is explored already
+                if !self.visited.contains(&to) {
+                    work.push((index[&to], stack.clone()));
+                }
+            }
+        }
+    }
+
+    // Graphviz digraph with one box per block listing its instructions
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph cfg {\n  node [shape=box fontname=monospace];\n");
+        for block in &self.blocks {
+            let mut label = String::new();
+            for ins in &block.instructions {
+                let _ = write!(label, "{:04x}: {}\\l", ins.offset, ins);
+            }
+            let mut attrs = String::new();
+            if !self.visited.contains(&block.start) {
+                attrs.push_str(" style=dashed");
+            }
+            if self.unresolved.contains(&block.last().offset) {
+                attrs.push_str(" color=red");
+            }
+            let _ = writeln!(out, "  b{:04x} [label=\"{label}\"{attrs}];", block.start);
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Fallthrough => "",
+                EdgeKind::Jump => " [color=blue]",
+                EdgeKind::Branch => " [color=darkgreen label=\"taken\"]",
+            };
+            let _ = writeln!(out, "  b{:04x} -> b{:04x}{style};", edge.from, edge.to);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_json(&self) -> Value {
+        let blocks: Vec<Value> = self
+            .blocks
+            .iter()
+            .map(|b| {
+                json!({
+                    "start": b.start,
+                    "end": b.end(),
+                    "reachable": self.visited.contains(&b.start),
+                    "instructions": b.instructions.iter().map(|i| i.to_json()).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        let edges: Vec<Value> = self
+            .edges
+            .iter()
+            .map(|e| json!({"from": e.from, "to": e.to, "kind": e.kind.name()}))
+            .collect();
+        let invalid: Vec<Value> = self
+            .invalid_jumps
+            .iter()
+            .map(|(pc, target)| json!({"pc": pc, "target": target}))
+            .collect();
+        json!({
+            "blocks": blocks,
+            "edges": edges,
+            "unresolved": self.unresolved,
+            "invalidJumps": invalid,
+        })
+    }
+}
+
+fn split_blocks(instructions: Vec<Instruction>, jumpdests: &HashSet<usize>) -> Vec<BasicBlock> {
+    let mut blocks: Vec<BasicBlock> = Vec::new();
+    let mut ends_block = true;
+    for ins in instructions {
+        if ends_block || jumpdests.contains(&ins.offset) {
+            blocks.push(BasicBlock {
+                start: ins.offset,
+                instructions: Vec::new(),
+            });
+        }
+        ends_block = matches!(
+            ins.opcode,
+            JUMP | JUMPI | STOP | RETURN | REVERT | INVALID | SELFDESTRUCT
+        );
+        if let Some(block) = blocks.last_mut() {
+            block.instructions.push(ins);
+        }
+    }
+    blocks
+}
+
+// Applies one non-jump instruction; false if the stack underflows or
+// overflows, which ends the path
+fn exec(stack: &mut Vec<Val>, ins: &Instruction) -> bool {
+    let op = ins.opcode;
+    let (pops, pushes) = stack_effect(op);
+    let top = stack.len();
+    if top < pops {
+        return false;
+    }
+    match op {
+        PUSH0..=PUSH32 => {
+            let bytes = &ins.immediate;
+            let skip = bytes.iter().take_while(|b| **b == 0).count();
+            let value = (bytes.len() - skip <= 4).then(|| {
+                bytes[skip..]
+                    .iter()
+                    .fold(0usize, |acc, b| (acc << 8) | *b as usize)
+            });
+            stack.push(value);
+        }
+        DUP1..=DUP16 => stack.push(stack[top - pops]),
+        SWAP1..=SWAP16 => stack.swap(top - 1, top - pops),
+        _ => {
+            stack.truncate(top - pops);
+            stack.extend(std::iter::repeat_n(None, pushes));
+        }
+    }
+    stack.len() <= STACK_LIMIT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn edge(from: usize, to: usize, kind: EdgeKind) -> Edge {
+        Edge { from, to, kind }
+    }
+
+    #[test]
+    fn splits_blocks_and_resolves_constant_jumps() {
+        let code = assemble(
+            "
+            PUSH1 0 CALLDATALOAD PUSH @yes JUMPI    ; 0000
+            PUSH1 1 CALLDATALOAD JUMP               ; 0006: target from calldata
+            JUMPDEST @yes PUSH1 0x0c                ; 000a
+            JUMPDEST JUMP                           ; 000d: 0x0c is PUSH data
+            ",
+        )
+        .unwrap();
+        let cfg = Cfg::build(&code);
+        let starts: Vec<usize> = cfg.blocks.iter().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0x00, 0x06, 0x0a, 0x0d]);
+        assert_eq!(
+            cfg.edges.iter().copied().collect::<Vec<_>>(),
+            vec![
+                edge(0x00, 0x06, EdgeKind::Fallthrough),
+                edge(0x00, 0x0a, EdgeKind::Branch),
+                edge(0x0a, 0x0d, EdgeKind::Fallthrough),
+            ]
+        );
+        assert_eq!(cfg.unresolved, BTreeSet::from([0x09]));
+        assert_eq!(cfg.invalid_jumps, BTreeSet::from([(0x0e, 0x0c)]));
+        let dot = cfg.to_dot();
+        assert!(dot.contains("b0000 -> b000a [color=darkgreen label=\"taken\"];"));
+        assert!(dot.contains(
+            "b0006 [label=\"0006: PUSH1 0x01\\l0008: CALLDATALOAD\\l0009: JUMP\\l\" color=red];"
+        ));
+        assert_eq!(cfg.to_json()["edges"][1]["kind"], "branch");
+    }
+
+    #[test]
+    fn follows_return_addresses_through_internal_calls() {
+        let code = assemble(
+            "
+            PUSH @ret1 PUSH1 7 PUSH @f JUMP     ; f(7)
+            JUMPDEST @ret1 POP
+            PUSH @ret2 PUSH1 9 PUSH @f JUMP     ; f(9)
+            JUMPDEST @ret2 POP STOP
+            JUMPDEST @f PUSH1 1 ADD SWAP1 JUMP  ; return x + 1
+            JUMPDEST PUSH @f JUMP               ; dead
+            ",
+        )
+        .unwrap();
+        let cfg = Cfg::build(&code);
+        let (ret1, ret2, f, dead) = (0x07, 0x10, 0x13, 0x19);
+        let jumps: Vec<(usize, usize)> = cfg
+            .edges
+            .iter()
+            .filter(|e| e.kind == EdgeKind::Jump)
+            .map(|e| (e.from, e.to))
+            .collect();
+        assert_eq!(
+            jumps,
+            vec![(0, f), (ret1, f), (f, ret1), (f, ret2), (dead, f)]
+        );
+        assert!(cfg.unresolved.is_empty());
+        assert!(!cfg.visited.contains(&dead));
+        assert!(cfg.visited.contains(&ret2));
+        // f is entered with [ret, x] from both call sites
+        assert_eq!(cfg.heights[&f], BTreeSet::from([2]));
+        assert_eq!(cfg.heights[&ret2], BTreeSet::from([1]));
+    }
+}
@@ -0,0 +1,224 @@
+// Instruction and JUMPI branch coverage, accumulated over any number of runs
+// and keyed by code hash and pc.
+
+use std::collections::BTreeMap;
+
+use primitive_types::H256;
+
+use crate::disasm::instruction_starts;
+use crate::inspector::{CallInputs, CreateInputs, CreateOutcome, Inspector};
+use crate::machine::{keccak256, Evm, EvmError, ExecutionResult};
+use crate::opcodes::JUMPI;
+use crate::srcmap::{line_of, pc_index, SourceRange};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeCoverage {
+    pub code: Vec<u8>,
+    // Times each instruction was executed
+    pub hits: BTreeMap<usize, u64>,
+    // Per JUMPI: [times it fell through, times it jumped]
+    pub branches: BTreeMap<usize, [u64; 2]>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoverageSummary {
+    pub instructions: usize,
+    pub instructions_hit: usize,
+    pub branches: usize,
+    pub branches_hit: usize,
+}
+
+impl CodeCoverage {
+    pub fn summary(&self) -> CoverageSummary {
+        let starts = instruction_starts(&self.code);
+        let jumpis = starts.iter().filter(|pc| self.code[**pc] == JUMPI).count();
+        CoverageSummary {
+            instructions: starts.len(),
+            instructions_hit: self.hits.len(),
+            branches: jumpis * 2,
+            branches_hit: self
+                .branches
+                .values()
+                .map(|b| b.iter().filter(|n| **n > 0).count())
+                .sum(),
+        }
+    }
+
+    // Instructions that never ran
+    pub fn missed(&self) -> Vec<usize> {
+        instruction_starts(&self.code)
+            .into_iter()
+            .filter(|pc| !self.hits.contains_key(pc))
+            .collect()
+    }
+
+    // LCOV tracefile for this code, given its solc source map and the sources
+    // as (name, text) in the order of the compiler's source list. A line's
+    // count is the highest count of the instructions mapped to it.
+    pub fn lcov(&self, ranges: &[SourceRange], sources: &[(String, String)]) -> String {
+        let index = pc_index(&self.code, ranges);
+        let mut lines: Vec<BTreeMap<usize, u64>> = vec![BTreeMap::new(); sources.len()];
+        let mut branches: Vec<Vec<(usize, usize, [u64; 2])>> = vec![Vec::new(); sources.len()];
+        let mut pcs: Vec<_> = index.into_iter().collect();
+        pcs.sort_by_key(|(pc, _)| *pc);
+        for (pc, range) in pcs {
+            let Some((_, text)) = usize::try_from(range.file)
+                .ok()
+                .and_then(|f| sources.get(f))
+            else {
+                continue;
+            };
+            let file = range.file as usize;
+            let line = line_of(text, range.offset);
+            let hits = self.hits.get(&pc).copied().unwrap_or(0);
+            let count = lines[file].entry(line).or_insert(0);
+            *count = (*count).max(hits);
+            if self.code[pc] == JUMPI {
+                let taken = self.branches.get(&pc).copied().unwrap_or([0, 0]);
+                branches[file].push((line, pc, taken));
+            }
+        }
+        let mut out = String::new();
+        for (file, (name, _)) in sources.iter().enumerate() {
+            out.push_str(&format!("SF:{name}\n"));
+            for (line, pc, taken) in &branches[file] {
+                let ran = self.hits.contains_key(pc);
+                for (i, n) in taken.iter().enumerate() {
+                    let n = if ran { n.to_string() } else { "-".to_string() };
+                    out.push_str(&format!("BRDA:{line},{pc},{i},{n}\n"));
+                }
+            }
+            let brh: usize = branches[file]
+                .iter()
+                .map(|(_, _, t)| t.iter().filter(|n| **n > 0).count())
+                .sum();
+            out.push_str(&format!("BRF:{}\nBRH:{brh}\n", branches[file].len() * 2));
+            for (line, hits) in &lines[file] {
+                out.push_str(&format!("DA:{line},{hits}\n"));
+            }
+            let lh = lines[file].values().filter(|h| **h > 0).count();
+            out.push_str(&format!("LF:{}\nLH:{lh}\n", lines[file].len()));
+            out.push_str("end_of_record\n");
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    pub codes: BTreeMap<H256, CodeCoverage>,
+    // Code hash of each running frame, by depth
+    frames: Vec<H256>,
+}
+
+impl Coverage {
+    pub fn get(&self, code: &[u8]) -> Option<&CodeCoverage> {
+        self.codes.get(&keccak256(code))
+    }
+}
+
+impl Inspector for Coverage {
+    fn step(&mut self, evm: &Evm) {
+        // A new frame starts with its first step
+        if self.frames.len() <= evm.depth {
+            let hash = keccak256(&evm.code);
+            self.codes.entry(hash).or_insert_with(|| CodeCoverage {
+                code: evm.code.clone(),
+                ..CodeCoverage::default()
+            });
+            self.frames.push(hash);
+        }
+        let cov = self.codes.get_mut(&self.frames[evm.depth]).unwrap();
+        *cov.hits.entry(evm.pc).or_default() += 1;
+        if evm.code.get(evm.pc) == Some(&JUMPI) && evm.stack.len() >= 2 {
+            let taken = !evm.stack[evm.stack.len() - 2].is_zero();
+            cov.branches.entry(evm.pc).or_default()[taken as usize] += 1;
+        }
+    }
+
+    fn step_end(&mut self, evm: &Evm, error: Option<&EvmError>) {
+        // The run is over once the outermost frame stops; the next one starts afresh
+        let stopped = error.is_some() || evm.halted.is_some() || evm.pc >= evm.code.len();
+        if evm.depth == 0 && stopped {
+            self.frames.clear();
+        }
+    }
+
+    fn call_end(&mut self, evm: &Evm, _inputs: &CallInputs, _result: &mut ExecutionResult) {
+        self.frames.truncate(evm.depth + 1);
+    }
+
+    fn create_end(&mut self, evm: &Evm, _inputs: &CreateInputs, _outcome: &mut CreateOutcome) {
+        self.frames.truncate(evm.depth + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::EvmConfig;
+    use crate::srcmap::decode;
+
+    // CALLDATASIZE; PUSH1 7; JUMPI; PUSH1 1; STOP; JUMPDEST; PUSH1 2; STOP
+    const CODE: [u8; 12] = [
+        0x36, 0x60, 0x07, 0x57, 0x60, 0x01, 0x00, 0x5b, 0x60, 0x02, 0x00, 0x00,
+    ];
+
+    fn run(cov: &mut Coverage, calldata: Vec<u8>) {
+        let mut evm = Evm::new(
+            CODE.to_vec(),
+            EvmConfig {
+                calldata,
+                ..EvmConfig::default()
+            },
+        );
+        evm.run_with(cov);
+    }
+
+    #[test]
+    fn accumulates_across_runs() {
+        let mut cov = Coverage::default();
+        run(&mut cov, vec![]);
+        let summary = cov.get(&CODE).unwrap().summary();
+        assert_eq!(summary.instructions, 9);
+        assert_eq!((summary.instructions_hit, summary.branches_hit), (5, 1));
+        run(&mut cov, vec![1]);
+        let code = cov.get(&CODE).unwrap();
+        assert_eq!(code.branches[&3], [1, 1]);
+        assert_eq!(code.summary().branches_hit, 2);
+        // Only the trailing STOP never ran
+        assert_eq!(code.missed(), [11]);
+    }
+
+    #[test]
+    fn credits_each_run_to_its_own_code() {
+        let mut cov = Coverage::default();
+        run(&mut cov, vec![]);
+        // PUSH1 1; PUSH1 2; ADD, run to its end
+        let other = vec![0x60, 0x01, 0x60, 0x02, 0x01];
+        Evm::new(other.clone(), EvmConfig::default()).run_with(&mut cov);
+        assert_eq!(cov.codes.len(), 2);
+        assert_eq!(cov.get(&other).unwrap().hits.len(), 3);
+        // The first code only has its own run
+        assert_eq!(cov.get(&CODE).unwrap().hits.values().sum::<u64>(), 5);
+        // A reverting run ends its frame too
+        Evm::new(vec![0x5f, 0x5f, 0xfd], EvmConfig::default()).run_with(&mut cov);
+        run(&mut cov, vec![]);
+        assert_eq!(cov.get(&CODE).unwrap().hits.values().sum::<u64>(), 10);
+    }
+
+    #[test]
+    fn maps_to_lcov_lines() {
+        let mut cov = Coverage::default();
+        run(&mut cov, vec![]);
+        // Instructions 0-3 on line 1, 4-5 on line 2, the rest on line 3
+        let ranges = decode("0:1:0;;;;2:1;;4:1").unwrap();
+        let sources = vec![("A.sol".to_string(), "a\nb\nc\n".to_string())];
+        let lcov = cov.get(&CODE).unwrap().lcov(&ranges, &sources);
+        assert!(lcov.starts_with("SF:A.sol\n"));
+        assert!(lcov.contains("BRDA:1,3,0,1\nBRDA:1,3,1,0\n"));
+        assert!(lcov.contains("DA:1,1\nDA:2,1\nDA:3,0\n"));
+        assert!(lcov.contains("LF:3\nLH:2\n"));
+        assert!(lcov.ends_with("end_of_record\n"));
+    }
+}
@@ -0,0 +1,591 @@
+// Interactive step debugger. It runs as an inspector, so the prompt is shown
+// from inside the `step` hook and follows execution into child frames. Every
+// step is recorded, so earlier states can be viewed without re-executing.
+
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use primitive_types::{H160, U256};
+
+use crate::hexutil::{hex, parse_u256};
+use crate::inspector::{CallInputs, CreateInputs, CreateOutcome, Inspector};
+use crate::machine::{Evm, EvmError, ExecutionResult};
+use crate::opcodes::{opcode_by_name, opcode_name, PUSH1, PUSH32};
+use crate::recording::{Recorder, Recording, StepState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    Pc(usize),
+    Opcode(u8),
+    // Any instruction at this call depth
+    Depth(usize),
+}
+
+impl Breakpoint {
+    // "pc 0x1a", "op SSTORE" or "depth 1"
+    pub fn parse(kind: &str, value: &str) -> Result<Self, String> {
+        let number = || {
+            parse_u256(value)
+                .filter(|v| *v <= U256::from(usize::MAX))
+                .map(|v| v.as_usize())
+                .ok_or_else(|| format!("invalid number: {value}"))
+        };
+        match kind {
+            "pc" => Ok(Breakpoint::Pc(number()?)),
+            "op" => opcode_by_name(value)
+                .map(Breakpoint::Opcode)
+                .ok_or_else(|| format!("unknown opcode: {value}")),
+            "depth" => Ok(Breakpoint::Depth(number()?)),
+            _ => Err(format!("unknown breakpoint kind: {kind}")),
+        }
+    }
+
+    pub fn matches(&self, evm: &Evm) -> bool {
+        match self {
+            Breakpoint::Pc(pc) => evm.pc == *pc,
+            Breakpoint::Opcode(op) => evm.code.get(evm.pc) == Some(op),
+            Breakpoint::Depth(depth) => evm.depth == *depth,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Pc(pc) => write!(f, "pc 0x{pc:x}"),
+            Breakpoint::Opcode(op) => write!(f, "op {}", opcode_name(*op)),
+            Breakpoint::Depth(depth) => write!(f, "depth {depth}"),
+        }
+    }
+}
+
+// When to show the prompt next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    // The next instruction, in whatever frame it runs
+    Step,
+    // The next instruction at this depth or above, stepping over calls
+    Next(usize),
+    // The next instruction above this depth
+    Out(usize),
+    // Only at breakpoints
+    Continue,
+    // Until this step index is reached
+    To(usize),
+}
+
+const HELP: &str = "\
+step | s               run one instruction, into calls
+next | n               run one instruction, over calls
+out | o                run until the current frame returns
+continue | c           run until a breakpoint
+back | rs [n]          view the state n steps back (step moves forward again)
+goto <n>               view step n, running forward to it if needed
+lastwrite <slot>       view the last SSTORE to slot of the current address
+break | b <kind> <v>   break at `pc N`, `op NAME` or `depth N`
+delete | d <n>         remove breakpoint n
+breaks                 list breakpoints
+where | w              show the current instruction
+stack                  show the stack, top first
+memory | mem [off] [n] dump memory
+storage [slot]         show storage of the current address
+returndata | rd        show the last call's return data
+logs                   show logs emitted by this frame
+quit | q               stop debugging and exit
+(empty line repeats the last command)";
+
+pub struct Debugger<R, W> {
+    input: R,
+    out: W,
+    pub breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    last: String,
+    // Set by `quit` or end of input; the debugger no longer stops
+    pub quit: bool,
+    recorder: Recorder,
+    // Earlier step being looked at instead of the live one
+    view: Option<usize>,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(input: R, out: W) -> Self {
+        Self {
+            input,
+            out,
+            breakpoints: Vec::new(),
+            mode: Mode::Step,
+            last: String::new(),
+            quit: false,
+            recorder: Recorder::default(),
+            view: None,
+        }
+    }
+
+    // Every step executed so far
+    pub fn recording(&self) -> &Recording {
+        &self.recorder.recording
+    }
+
+    fn live(&self) -> usize {
+        self.recorder.current().unwrap_or(0)
+    }
+
+    fn should_stop(&self, evm: &Evm) -> bool {
+        match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) if evm.depth <= depth => true,
+            Mode::Out(depth) if evm.depth < depth => true,
+            Mode::To(step) if self.live() >= step => true,
+            _ => false,
+        }
+    }
+
+    // Moves the view to `step`, or back to the live step
+    fn show(&mut self, evm: &Evm, step: usize) -> String {
+        let live = self.live();
+        if step >= live {
+            self.view = None;
+            return format!("{} (step {live})", location(evm));
+        }
+        self.view = Some(step);
+        let state = self.recording().state_at(step).unwrap();
+        let code = &self.recording().frames[state.frame].code;
+        format!(
+            "{} (step {step} of {live})",
+            describe(
+                state.depth,
+                state.address,
+                code,
+                state.pc,
+                state.gas as i128
+            )
+        )
+    }
+
+    // Reads commands until one resumes execution
+    fn prompt(&mut self, evm: &Evm) {
+        loop {
+            let _ = write!(self.out, "(evm) ");
+            let _ = self.out.flush();
+            let mut line = String::new();
+            if self.input.read_line(&mut line).unwrap_or(0) == 0 {
+                self.quit = true;
+                return;
+            }
+            let line = match line.trim() {
+                "" => self.last.clone(),
+                l => l.to_string(),
+            };
+            self.last = line.clone();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((cmd, args)) = words.split_first() else {
+                continue;
+            };
+            let resume = matches!(*cmd, "next" | "n" | "out" | "o" | "continue" | "c");
+            if resume || (matches!(*cmd, "step" | "s") && self.view.is_none()) {
+                self.view = None;
+            }
+            match *cmd {
+                "step" | "s" if self.view.is_some() => {
+                    let text = self.show(evm, self.view.unwrap() + 1);
+                    let _ = writeln!(self.out, "{text}");
+                }
+                "step" | "s" => return self.mode = Mode::Step,
+                "next" | "n" => return self.mode = Mode::Next(evm.depth),
+                "out" | "o" => return self.mode = Mode::Out(evm.depth),
+                "continue" | "c" => return self.mode = Mode::Continue,
+                "goto" if number(args, 0, 0).is_ok_and(|n| n > self.live()) => {
+                    self.view = None;
+                    return self.mode = Mode::To(number(args, 0, 0).unwrap());
+                }
+                "quit" | "q" => return self.quit = true,
+                _ => {
+                    let text = self.command(evm, cmd, args).unwrap_or_else(|e| e);
+                    let _ = writeln!(self.out, "{text}");
+                }
+            }
+        }
+    }
+
+    // Commands that only print; returns the text or an error message
+    fn command(&mut self, evm: &Evm, cmd: &str, args: &[&str]) -> Result<String, String> {
+        if let Some(step) = self.view {
+            if let Some(res) = self.history_command(step, cmd, args) {
+                return res;
+            }
+        }
+        match cmd {
+            "help" | "h" => Ok(HELP.to_string()),
+            "where" | "w" => Ok(format!("{} (step {})", location(evm), self.live())),
+            "back" | "rs" => {
+                let n = number(args, 0, 1)?;
+                let from = self.view.unwrap_or(self.live());
+                Ok(self.show(evm, from.saturating_sub(n)))
+            }
+            "goto" => {
+                let n = number(args, 0, usize::MAX)?;
+                Ok(self.show(evm, n))
+            }
+            "lastwrite" => {
+                let address = match self.view {
+                    Some(step) => self.recording().state_at(step).unwrap().address,
+                    None => evm.address,
+                };
+                let address = address.ok_or("no current address")?;
+                let arg = args.first().ok_or("usage: lastwrite <slot>")?;
+                let slot = parse_u256(arg).ok_or_else(|| format!("invalid slot: {arg}"))?;
+                let from = self.view.unwrap_or(self.live());
+                let step = self
+                    .recording()
+                    .last_sstore(from, address, slot)
+                    .ok_or_else(|| format!("no SSTORE to 0x{slot:x} before step {from}"))?;
+                let w = self.recording().steps[step].storage.unwrap();
+                let text = self.show(evm, step);
+                Ok(format!(
+                    "SSTORE 0x{slot:x}: 0x{:x} -> 0x{:x}\n{text}",
+                    w.old, w.new
+                ))
+            }
+            "break" | "b" => {
+                let [kind, value] = args else {
+                    return Err("usage: break <pc|op|depth> <value>".into());
+                };
+                let bp = Breakpoint::parse(kind, value)?;
+                self.breakpoints.push(bp);
+                Ok(format!("breakpoint {}: {bp}", self.breakpoints.len() - 1))
+            }
+            "delete" | "d" => {
+                let n = number(args, 0, usize::MAX)?;
+                if n >= self.breakpoints.len() {
+                    return Err(format!("no breakpoint {n}"));
+                }
+                Ok(format!("deleted {}", self.breakpoints.remove(n)))
+            }
+            "breaks" => Ok(match self.breakpoints.is_empty() {
+                true => "no breakpoints".into(),
+                false => lines(
+                    self.breakpoints
+                        .iter()
+                        .enumerate()
+                        .map(|(i, b)| format!("{i}: {b}")),
+                ),
+            }),
+            "stack" => Ok(stack_lines(&evm.stack)),
+            "memory" | "mem" => {
+                let offset = number(args, 0, 0)?;
+                let len = number(args, 1, evm.memory.len().saturating_sub(offset))?;
+                Ok(dump(&evm.memory, offset, len))
+            }
+            "storage" => {
+                let Some(address) = evm.address else {
+                    return Err("no current address".into());
+                };
+                let account = evm.world.as_ref().and_then(|w| w.accounts.get(&address));
+                let slot = |k: &U256| {
+                    account
+                        .and_then(|a| a.storage.get(k))
+                        .copied()
+                        .unwrap_or_default()
+                };
+                if let Some(arg) = args.first() {
+                    let key = parse_u256(arg).ok_or_else(|| format!("invalid slot: {arg}"))?;
+                    return Ok(format!("0x{key:x}: 0x{:x}", slot(&key)));
+                }
+                let mut keys: Vec<&U256> = account
+                    .map(|a| a.storage.keys().collect())
+                    .unwrap_or_default();
+                keys.sort();
+                Ok(match keys.is_empty() {
+                    true => "empty storage".into(),
+                    false => lines(
+                        keys.into_iter()
+                            .map(|k| format!("0x{k:x}: 0x{:x}", slot(k))),
+                    ),
+                })
+            }
+            "returndata" | "rd" => Ok(format!("0x{}", hex(&evm.last_return_data))),
+            "logs" => Ok(match evm.logs.is_empty() {
+                true => "no logs".into(),
+                false => lines(evm.logs.iter().enumerate().map(|(i, log)| {
+                    let topics: Vec<String> =
+                        log.topics.iter().map(|t| format!("0x{t:x}")).collect();
+                    format!(
+                        "[{i}] 0x{} topics=[{}] data=0x{}",
+                        hex(log.address.as_bytes()),
+                        topics.join(", "),
+                        hex(&log.data)
+                    )
+                })),
+            }),
+            _ => Err(format!("unknown command: {cmd} (try help)")),
+        }
+    }
+
+    // Inspection of a recorded step; None for commands that don't depend on it
+    fn history_command(
+        &self,
+        step: usize,
+        cmd: &str,
+        args: &[&str],
+    ) -> Option<Result<String, String>> {
+        let rec = self.recording();
+        let state: StepState = rec.state_at(step)?;
+        let code = &rec.frames[state.frame].code;
+        let here = describe(
+            state.depth,
+            state.address,
+            code,
+            state.pc,
+            state.gas as i128,
+        );
+        Some(match cmd {
+            "where" | "w" => Ok(format!("{here} (step {step} of {})", self.live())),
+            "stack" => Ok(stack_lines(&state.stack)),
+            "memory" | "mem" => {
+                let offset = match number(args, 0, 0) {
+                    Ok(n) => n,
+                    Err(e) => return Some(Err(e)),
+                };
+                number(args, 1, state.memory.len().saturating_sub(offset))
+                    .map(|len| dump(&state.memory, offset, len))
+            }
+            "storage" => {
+                let address = state.address?;
+                let mut slots: Vec<U256> = match args.first() {
+                    Some(arg) => match parse_u256(arg) {
+                        Some(slot) => vec![slot],
+                        None => return Some(Err(format!("invalid slot: {arg}"))),
+                    },
+                    None => rec
+                        .steps
+                        .iter()
+                        .filter_map(|s| s.storage)
+                        .filter(|w| w.address == address)
+                        .map(|w| w.slot)
+                        .collect(),
+                };
+                slots.sort();
+                slots.dedup();
+                match slots.is_empty() {
+                    true => Ok("no recorded storage writes".into()),
+                    false => {
+                        Ok(lines(slots.into_iter().map(
+                            |k| match rec.storage_at(step, address, k) {
+                                Some(v) => format!("0x{k:x}: 0x{v:x}"),
+                                None => format!("0x{k:x}: unknown (never written)"),
+                            },
+                        )))
+                    }
+                }
+            }
+            "returndata" | "rd" | "logs" => {
+                Err("not recorded; `goto` the live step to see it".into())
+            }
+            _ => return None,
+        })
+    }
+
+    fn returned(&mut self, evm: &Evm, result: &ExecutionResult) {
+        if self.quit || self.mode == Mode::Continue {
+            return;
+        }
+        let status = match result {
+            ExecutionResult::Success { .. } => "success",
+            ExecutionResult::Revert { .. } => "revert",
+            ExecutionResult::Halt { .. } => "halt",
+        };
+        let _ = writeln!(
+            self.out,
+            "<- {status} to depth {} output=0x{}",
+            evm.depth,
+            hex(result.output())
+        );
+    }
+}
+
+impl<R: BufRead, W: Write> Inspector for Debugger<R, W> {
+    fn step(&mut self, evm: &Evm) {
+        self.recorder.step(evm);
+        if self.quit {
+            return;
+        }
+        let hit = self.breakpoints.iter().position(|b| b.matches(evm));
+        if !self.should_stop(evm) && hit.is_none() {
+            return;
+        }
+        if let (Some(i), false) = (hit, self.should_stop(evm)) {
+            let _ = writeln!(self.out, "breakpoint {i}: {}", self.breakpoints[i]);
+        }
+        let _ = writeln!(self.out, "{} (step {})", location(evm), self.live());
+        self.prompt(evm);
+    }
+
+    fn step_end(&mut self, evm: &Evm, error: Option<&EvmError>) {
+        self.recorder.step_end(evm, error);
+    }
+
+    fn call_end(&mut self, evm: &Evm, inputs: &CallInputs, result: &mut ExecutionResult) {
+        self.recorder.call_end(evm, inputs, result);
+        self.returned(evm, result);
+    }
+
+    fn create_end(&mut self, evm: &Evm, inputs: &CreateInputs, outcome: &mut CreateOutcome) {
+        self.recorder.create_end(evm, inputs, outcome);
+        self.returned(evm, &outcome.result);
+    }
+}
+
+// "[depth] address pc: instruction gas=N"
+pub fn location(evm: &Evm) -> String {
+    describe(evm.depth, evm.address, &evm.code, evm.pc, evm.gas)
+}
+
+fn describe(depth: usize, address: Option<H160>, code: &[u8], pc: usize, gas: i128) -> String {
+    let address = address
+        .map(|a| format!("0x{}", hex(a.as_bytes())))
+        .unwrap_or_else(|| "-".into());
+    let op = code.get(pc).copied().unwrap_or(0);
+    let mut instr = opcode_name(op).to_string();
+    if (PUSH1..=PUSH32).contains(&op) {
+        let n = (op - PUSH1 + 1) as usize;
+        let end = (pc + 1 + n).min(code.len());
+        instr.push_str(&format!(" 0x{}", hex(&code[pc + 1..end])));
+    }
+    format!("[{depth}] {address} {pc:04x}: {instr} gas={gas}")
+}
+
+fn stack_lines(stack: &[U256]) -> String {
+    match stack.is_empty() {
+        true => "empty stack".into(),
+        false => lines(
+            stack
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(i, v)| format!("[{i}] 0x{v:x}")),
+        ),
+    }
+}
+
+// Argument `i` as a number, or `default` if it is missing
+fn number(args: &[&str], i: usize, default: usize) -> Result<usize, String> {
+    match args.get(i) {
+        Some(a) => parse_u256(a)
+            .filter(|v| *v <= U256::from(u32::MAX))
+            .map(|v| v.as_usize())
+            .ok_or_else(|| format!("invalid number: {a}")),
+        None => Ok(default),
+    }
+}
+
+fn lines(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join("\n")
+}
+
+// 32 bytes per row; bytes past the end of memory read as zero, but at most one
+// row of them is shown so `mem 0 0xffffffff` does not allocate gigabytes
+fn dump(memory: &[u8], offset: usize, len: usize) -> String {
+    if len == 0 {
+        return "empty memory".into();
+    }
+    let len = len.min((memory.len() + 32).saturating_sub(offset));
+    if len == 0 {
+        return format!(
+            "0x{offset:04x} is past the end of memory ({} bytes)",
+            memory.len()
+        );
+    }
+    let bytes: Vec<u8> = (offset..offset + len)
+        .map(|i| memory.get(i).copied().unwrap_or(0))
+        .collect();
+    lines(
+        bytes
+            .chunks(32)
+            .enumerate()
+            .map(|(i, row)| format!("0x{:04x}: {}", offset + i * 32, hex(row))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::World;
+    use crate::testutil::{call_callee, callee_world, evm};
+
+    fn session(code: Vec<u8>, world: Option<World>, input: &str) -> (String, ExecutionResult) {
+        let mut evm = evm(code, world);
+        let mut out = Vec::new();
+        let mut dbg = Debugger::new(input.as_bytes(), &mut out);
+        let result = evm.run_with(&mut dbg);
+        (String::from_utf8(out).unwrap(), result)
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_inspects_state() {
+        // SSTORE(1, 0x2a); MSTORE(0, 7); STOP
+        let code = vec![
+            0x60, 0x2a, 0x60, 0x01, 0x55, 0x60, 0x07, 0x60, 0x00, 0x52, 0x00,
+        ];
+        let input = "b op SSTORE\nb pc 0xa\nc\nstack\nc\nstorage\nmem 0 32\nbogus\nc\n";
+        let (out, result) = session(code, Some(World::default()), input);
+        assert!(result.is_success());
+        assert!(out.contains("breakpoint 0: op SSTORE\n[0] 0xaaaa"));
+        assert!(out.contains("[0] 0x1\n[1] 0x2a\n"));
+        assert!(out.contains("breakpoint 1: pc 0xa\n"));
+        assert!(out.contains("0x1: 0x2a\n"));
+        assert!(out.contains(&format!("0x0000: {}07\n", "00".repeat(31))));
+        assert!(out.contains("unknown command: bogus"));
+    }
+
+    #[test]
+    fn clamps_memory_dumps_to_the_memory_size() {
+        let out = dump(&[1; 4], 0, 0xffff_ffff);
+        assert_eq!(
+            out,
+            format!(
+                "0x0000: 01010101{}\n0x0020: {}",
+                "00".repeat(28),
+                "00".repeat(4)
+            )
+        );
+        assert_eq!(
+            dump(&[], 0x1000, 8),
+            "0x1000 is past the end of memory (0 bytes)"
+        );
+    }
+
+    #[test]
+    fn steps_into_and_out_of_calls() {
+        let world = callee_world("PUSH1 1 PUSH1 2 STOP");
+        let code = call_callee(0, "STOP");
+        let bb = "bb".repeat(20);
+        // Break in the callee, step once, then run out of it
+        let input = "b depth 1\nc\nd 0\ns\no\nn\n";
+        let (out, result) = session(code, Some(world), input);
+        assert!(result.is_success());
+        assert!(out.contains(&format!(
+            "breakpoint 0: depth 1\n[1] 0x{bb} 0000: PUSH1 0x01"
+        )));
+        assert!(out.contains(&format!("[1] 0x{bb} 0002: PUSH1 0x02")));
+        assert!(out.contains("<- success to depth 0 output=0x\n[0] 0xaaaa"));
+        assert!(out.contains(" 0021: STOP"));
+    }
+
+    #[test]
+    fn travels_back_through_recorded_steps() {
+        // SSTORE(1, 0x2a); SSTORE(1, 0x2b); STOP
+        let code = vec![
+            0x60, 0x2a, 0x60, 0x01, 0x55, 0x60, 0x2b, 0x60, 0x01, 0x55, 0x00,
+        ];
+        let input = "goto 6\nlastwrite 1\nstorage\nstack\nback\nback 9\ns\ngoto 6\nc\n";
+        let (out, result) = session(code, Some(World::default()), input);
+        assert!(result.is_success());
+        assert!(out.contains("0009: SSTORE gas=9979988 (step 5 of 6)\n"));
+        assert!(out.contains("SSTORE 0x1: 0x2a -> 0x2b\n"));
+        assert!(out.contains("(evm) 0x1: 0x2a\n"));
+        assert!(out.contains("(evm) [0] 0x1\n[1] 0x2b\n"));
+        assert!(out.contains("0007: PUSH1 0x01 gas=9979991 (step 4 of 6)\n"));
+        assert!(out.contains("0002: PUSH1 0x01 gas=9999997 (step 1 of 6)\n"));
+        assert!(out.contains("000a: STOP gas=9977088 (step 6)\n"));
+    }
+}
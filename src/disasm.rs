@@ -1,304 +1,240 @@
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::hexutil::hex;
 use crate::opcodes::*;
 
-pub fn disassemble(code: &[u8]) -> Vec<String> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub offset: usize,
+    pub opcode: u8,
+    // "INVALID" for undefined opcodes
+    pub mnemonic: &'static str,
+    // PUSH data; shorter than the PUSH width when the code ends inside it
+    pub immediate: Vec<u8>,
+    pub truncated: bool,
+}
+
+impl Instruction {
+    // Offset of the next instruction
+    pub fn next(&self) -> usize {
+        self.offset + 1 + self.immediate.len()
+    }
+
+    // (items popped, items pushed)
+    pub fn stack_effect(&self) -> (usize, usize) {
+        stack_effect(self.opcode)
+    }
+
+    // Static gas and whether more is charged at runtime
+    pub fn gas(&self) -> (u64, bool) {
+        static_gas(self.opcode)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "offset": self.offset,
+            "opcode": self.opcode,
+            "mnemonic": self.mnemonic,
+            "immediate": format!("0x{}", hex(&self.immediate)),
+            "truncated": self.truncated,
+        })
+    }
+
+    // The instruction followed by its stack effect and gas as a comment, e.g.
+    // "MSTORE                   ; 2 -> 0, gas 3+"
+    pub fn annotated(&self) -> String {
+        let (pops, pushes) = self.stack_effect();
+        let (gas, dynamic) = self.gas();
+        let plus = if dynamic { "+" } else { "" };
+        format!(
+            "{:<24} ; {pops} -> {pushes}, gas {gas}{plus}",
+            self.to_string()
+        )
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if opcode_by_name(self.mnemonic) != Some(self.opcode) {
+            // Undefined opcode: print the byte so the listing still assembles
+            write!(f, "0x{:02x}", self.opcode)
+        } else if (PUSH1..=PUSH32).contains(&self.opcode) {
+            write!(f, "{} 0x{}", self.mnemonic, hex(&self.immediate))?;
+            if self.truncated {
+                write!(f, " ; truncated")?;
+            }
+            Ok(())
+        } else {
+            f.write_str(self.mnemonic)
+        }
+    }
+}
+
+// Decodes the executable part of `code`, stopping where `data_start` finds
+// the trailing data section.
+pub fn disassemble(code: &[u8]) -> Vec<Instruction> {
+    let end = data_start(code).unwrap_or(code.len());
+    decode(&code[..end])
+}
+
+fn decode(code: &[u8]) -> Vec<Instruction> {
     let mut out = Vec::new();
     let mut pc = 0usize;
     while pc < code.len() {
         let op = code[pc];
-        let mut line = format!("{:04x}: ", pc);
-        match op {
-            STOP => {
-                line.push_str("STOP");
-                pc += 1;
-            }
-            ADD => {
-                line.push_str("ADD");
-                pc += 1;
-            }
-            MUL => {
-                line.push_str("MUL");
-                pc += 1;
-            }
-            SUB => {
-                line.push_str("SUB");
-                pc += 1;
-            }
-            DIV => {
-                line.push_str("DIV");
-                pc += 1;
-            }
-            LT => {
-                line.push_str("LT");
-                pc += 1;
-            }
-            GT => {
-                line.push_str("GT");
-                pc += 1;
-            }
-            EQ => {
-                line.push_str("EQ");
-                pc += 1;
-            }
-            ISZERO => {
-                line.push_str("ISZERO");
-                pc += 1;
-            }
-            AND => {
-                line.push_str("AND");
-                pc += 1;
-            }
-            OR => {
-                line.push_str("OR");
-                pc += 1;
-            }
-            XOR => {
-                line.push_str("XOR");
-                pc += 1;
-            }
-            NOT => {
-                line.push_str("NOT");
-                pc += 1;
-            }
-            SHA3 => {
-                line.push_str("SHA3");
-                pc += 1;
-            }
-            ADDRESS => {
-                line.push_str("ADDRESS");
-                pc += 1;
-            }
-            BALANCE => {
-                line.push_str("BALANCE");
-                pc += 1;
-            }
-            ORIGIN => {
-                line.push_str("ORIGIN");
-                pc += 1;
-            }
-            CALLER => {
-                line.push_str("CALLER");
-                pc += 1;
-            }
-            CALLVALUE => {
-                line.push_str("CALLVALUE");
-                pc += 1;
-            }
-            POP => {
-                line.push_str("POP");
-                pc += 1;
-            }
-            MLOAD => {
-                line.push_str("MLOAD");
-                pc += 1;
-            }
-            MSTORE => {
-                line.push_str("MSTORE");
-                pc += 1;
-            }
-            MSTORE8 => {
-                line.push_str("MSTORE8");
-                pc += 1;
-            }
-            SLOAD => {
-                line.push_str("SLOAD");
-                pc += 1;
-            }
-            SSTORE => {
-                line.push_str("SSTORE");
-                pc += 1;
-            }
-            JUMP => {
-                line.push_str("JUMP");
-                pc += 1;
-            }
-            JUMPI => {
-                line.push_str("JUMPI");
-                pc += 1;
-            }
-            JUMPDEST => {
-                line.push_str("JUMPDEST");
-                pc += 1;
-            }
-            PUSH0 => {
-                line.push_str("PUSH0");
-                pc += 1;
-            }
-            x if (PUSH1..=PUSH32).contains(&x) => {
-                let n = (x - PUSH1 + 1) as usize;
-                let start = pc + 1;
-                let end = (start + n).min(code.len());
-                let imm = &code[start..end];
-                line.push_str(&format!("PUSH{} 0x{}", n, hex(imm)));
-                pc = start + n;
-            }
-            x if (DUP1..=DUP16).contains(&x) => {
-                let n = x - DUP1 + 1;
-                line.push_str(&format!("DUP{}", n));
-                pc += 1;
-            }
-            x if (SWAP1..=SWAP16).contains(&x) => {
-                let n = x - SWAP1 + 1;
-                line.push_str(&format!("SWAP{}", n));
-                pc += 1;
-            }
-            PC => {
-                line.push_str("PC");
-                pc += 1;
-            }
-            MSIZE => {
-                line.push_str("MSIZE");
-                pc += 1;
-            }
-            GAS => {
-                line.push_str("GAS");
-                pc += 1;
-            }
-            CALLDATALOAD => {
-                line.push_str("CALLDATALOAD");
-                pc += 1;
-            }
-            CALLDATASIZE => {
-                line.push_str("CALLDATASIZE");
-                pc += 1;
-            }
-            CALLDATACOPY => {
-                line.push_str("CALLDATACOPY");
-                pc += 1;
-            }
-            CODESIZE => {
-                line.push_str("CODESIZE");
-                pc += 1;
-            }
-            CODECOPY => {
-                line.push_str("CODECOPY");
-                pc += 1;
-            }
-            GASPRICE => {
-                line.push_str("GASPRICE");
-                pc += 1;
-            }
-            EXTCODESIZE => {
-                line.push_str("EXTCODESIZE");
-                pc += 1;
-            }
-            EXTCODECOPY => {
-                line.push_str("EXTCODECOPY");
-                pc += 1;
-            }
-            RETURNDATASIZE => {
-                line.push_str("RETURNDATASIZE");
-                pc += 1;
-            }
-            RETURNDATACOPY => {
-                line.push_str("RETURNDATACOPY");
-                pc += 1;
-            }
-            EXTCODEHASH => {
-                line.push_str("EXTCODEHASH");
-                pc += 1;
-            }
-            BLOCKHASH => {
-                line.push_str("BLOCKHASH");
-                pc += 1;
-            }
-            COINBASE => {
-                line.push_str("COINBASE");
-                pc += 1;
-            }
-            TIMESTAMP => {
-                line.push_str("TIMESTAMP");
-                pc += 1;
-            }
-            NUMBER => {
-                line.push_str("NUMBER");
-                pc += 1;
-            }
-            DIFFICULTY_PRAND => {
-                line.push_str("PREVRANDAO");
-                pc += 1;
-            }
-            GASLIMIT_OP => {
-                line.push_str("GASLIMIT");
-                pc += 1;
-            }
-            CHAINID => {
-                line.push_str("CHAINID");
-                pc += 1;
-            }
-            SELFBALANCE => {
-                line.push_str("SELFBALANCE");
-                pc += 1;
-            }
-            BASEFEE => {
-                line.push_str("BASEFEE");
-                pc += 1;
-            }
-            RETURN => {
-                line.push_str("RETURN");
-                pc += 1;
-            }
-            REVERT => {
-                line.push_str("REVERT");
-                pc += 1;
-            }
-            CALL => {
-                line.push_str("CALL");
-                pc += 1;
-            }
-            CALLCODE => {
-                line.push_str("CALLCODE");
-                pc += 1;
-            }
-            STATICCALL => {
-                line.push_str("STATICCALL");
-                pc += 1;
-            }
-            DELEGATECALL => {
-                line.push_str("DELEGATECALL");
-                pc += 1;
-            }
-            CREATE => {
-                line.push_str("CREATE");
-                pc += 1;
-            }
-            CREATE2 => {
-                line.push_str("CREATE2");
-                pc += 1;
-            }
-            LOG0 => {
-                line.push_str("LOG0");
-                pc += 1;
-            }
-            LOG1 => {
-                line.push_str("LOG1");
-                pc += 1;
-            }
-            LOG2 => {
-                line.push_str("LOG2");
-                pc += 1;
-            }
-            LOG3 => {
-                line.push_str("LOG3");
-                pc += 1;
-            }
-            LOG4 => {
-                line.push_str("LOG4");
-                pc += 1;
-            }
-            _ => {
-                line.push_str(&format!("0x{:02x}", op));
-                pc += 1;
-            }
+        let n = if (PUSH1..=PUSH32).contains(&op) {
+            (op - PUSH1 + 1) as usize
+        } else {
+            0
+        };
+        let start = pc + 1;
+        let end = (start + n).min(code.len());
+        out.push(Instruction {
+            offset: pc,
+            opcode: op,
+            mnemonic: opcode_name(op),
+            immediate: code[start..end].to_vec(),
+            truncated: end < start + n,
+        });
+        pc = start + n;
+    }
+    out
+}
+
+// Text listing, one "0000: MNEMONIC" line per instruction, with the data
+// section and solc metadata as raw bytes at the end. `asm::assemble` turns it
+// back into `code`.
+pub fn listing(code: &[u8], effects: bool) -> Vec<String> {
+    let mut out: Vec<String> = disassemble(code)
+        .iter()
+        .map(|ins| {
+            let text = if effects {
+                ins.annotated()
+            } else {
+                ins.to_string()
+            };
+            format!("{:04x}: {}", ins.offset, text)
+        })
+        .collect();
+    let meta = metadata_start(code).unwrap_or(code.len());
+    if let Some(start) = data_start(code) {
+        if start < meta {
+            out.push(format!(
+                "{:04x}: 0x{} ; data",
+                start,
+                hex(&code[start..meta])
+            ));
+        }
+        if meta < code.len() {
+            out.push(format!("{:04x}: 0x{} ; metadata", meta, hex(&code[meta..])));
         }
-        out.push(line);
     }
     out
 }
 
-fn hex(bytes: &[u8]) -> String {
-    let mut s = String::with_capacity(bytes.len() * 2);
-    for b in bytes {
-        s.push_str(&format!("{:02x}", b));
+// Start of the bytes after the executable code: the solc metadata, or data
+// after the last JUMPDEST's block has ended in a terminating instruction,
+// which no jump or fall-through can reach.
+pub fn data_start(code: &[u8]) -> Option<usize> {
+    let end = metadata_start(code).unwrap_or(code.len());
+    let ins = decode(&code[..end]);
+    let from = ins.iter().rposition(|i| i.opcode == JUMPDEST).unwrap_or(0);
+    let stop = ins[from..].iter().find(|i| {
+        matches!(
+            i.opcode,
+            STOP | JUMP | RETURN | REVERT | INVALID | SELFDESTRUCT
+        )
+    });
+    let start = stop.map_or(end, |i| i.next());
+    (start < code.len()).then_some(start)
+}
+
+// Start of the CBOR metadata solc appends, found via its trailing two-byte
+// length
+pub fn metadata_start(code: &[u8]) -> Option<usize> {
+    let n = code.len().checked_sub(2)?;
+    let len = u16::from_be_bytes([code[n], code[n + 1]]) as usize;
+    let start = n.checked_sub(len)?;
+    // A CBOR map whose first key is a text string ("ipfs", "bzzr0", "solc")
+    let is_map = len >= 2 && (0xa1..=0xb7).contains(&code[start]);
+    (is_map && code[start + 1] & 0xe0 == 0x60).then_some(start)
+}
+
+// Offsets of every instruction, skipping PUSH immediates. Index `i` is the
+// i-th instruction, which is how solc source maps count.
+pub fn instruction_starts(code: &[u8]) -> Vec<usize> {
+    let mut out = Vec::new();
+    let mut pc = 0usize;
+    while pc < code.len() {
+        out.push(pc);
+        let op = code[pc];
+        if (PUSH1..=PUSH32).contains(&op) {
+            pc += (op - PUSH1 + 1) as usize;
+        }
+        pc += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_instructions_and_truncated_pushes() {
+        // PUSH2 0x0102; 0x0c; JUMPDEST; PUSH3 0x0304 (truncated)
+        let code = [0x61, 0x01, 0x02, 0x0c, 0x5b, 0x62, 0x03, 0x04];
+        let ins = disassemble(&code);
+        assert_eq!(ins.len(), 4);
+        assert_eq!(
+            ins[0],
+            Instruction {
+                offset: 0,
+                opcode: 0x61,
+                mnemonic: "PUSH2",
+                immediate: vec![1, 2],
+                truncated: false,
+            }
+        );
+        assert_eq!(
+            (ins[1].mnemonic, ins[1].to_string()),
+            ("INVALID", "0x0c".into())
+        );
+        assert_eq!(ins[3].offset, 5);
+        assert!(ins[3].truncated);
+        assert_eq!(ins[3].to_string(), "PUSH3 0x0304 ; truncated");
+        assert_eq!(
+            ins[2].annotated(),
+            format!("{:<24} ; 0 -> 0, gas 1", "JUMPDEST")
+        );
+        assert_eq!(ins[0].to_json()["immediate"], "0x0102");
+    }
+
+    #[test]
+    fn splits_off_data_and_metadata() {
+        // PUSH1 0; JUMPDEST; RETURN; data 0xdead; metadata {"solc": 0x0008}
+        let mut code = vec![0x60, 0x00, 0x5b, 0xf3];
+        code.extend_from_slice(&[0xde, 0xad]);
+        code.extend_from_slice(&[
+            0xa1, 0x64, b's', b'o', b'l', b'c', 0x42, 0x00, 0x08, 0x00, 0x09,
+        ]);
+        assert_eq!(metadata_start(&code), Some(6));
+        assert_eq!(data_start(&code), Some(4));
+        assert_eq!(disassemble(&code).len(), 3);
+        assert_eq!(
+            listing(&code, false),
+            vec![
+                "0000: PUSH1 0x00",
+                "0002: JUMPDEST",
+                "0003: RETURN",
+                "0004: 0xdead ; data",
+                "0006: 0xa164736f6c634200080009 ; metadata",
+            ]
+        );
+        // Code that runs to its end has no data section
+        assert_eq!(data_start(&[0x5b, 0x60, 0x00, 0x56]), None);
+        assert_eq!(data_start(&[0x60, 0x01, 0x00]), None);
     }
-    s
 }
@@ -0,0 +1,65 @@
+// Hex parsing/formatting shared by the JSON formats in the library and the CLI.
+
+use primitive_types::{H160, H256, U256};
+
+pub fn hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push_str(&format!("{:02x}", b));
+    }
+    s
+}
+
+pub fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.is_empty() {
+        return Some(Vec::new());
+    }
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn parse_h160(s: &str) -> Option<H160> {
+    let b = parse_hex(s)?;
+    if b.len() != 20 {
+        return None;
+    }
+    Some(H160::from_slice(&b))
+}
+
+// Accepts short values (e.g. "0x01") and left-pads them to 32 bytes.
+pub fn parse_h256(s: &str) -> Option<H256> {
+    parse_u256(s).map(|v| {
+        let mut buf = [0u8; 32];
+        v.to_big_endian(&mut buf);
+        H256(buf)
+    })
+}
+
+// 0x-prefixed hex (odd length allowed) or decimal
+pub fn parse_u256(s: &str) -> Option<U256> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        // Accept odd-length hex (e.g., 0x0) by padding a leading zero nibble
+        let padded = if !hex.len().is_multiple_of(2) {
+            format!("0x0{}", hex)
+        } else {
+            format!("0x{}", hex)
+        };
+        let b = parse_hex(&padded)?;
+        let mut buf = [0u8; 32];
+        if b.len() > 32 {
+            return None;
+        }
+        buf[32 - b.len()..].copy_from_slice(&b);
+        Some(U256::from_big_endian(&buf))
+    } else {
+        U256::from_dec_str(s).ok()
+    }
+}
@@ -0,0 +1,168 @@
+// Hooks into execution. An inspector is handed to `Evm::run_with`/`step_with`
+// and passed down into every child frame, so it sees nested calls too.
+
+use primitive_types::{H160, U256};
+
+use crate::machine::{Evm, EvmError, ExecutionResult, LogEntry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallScheme {
+    Call,
+    StaticCall,
+    CallCode,
+    DelegateCall,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallInputs {
+    pub scheme: CallScheme,
+    // msg.sender of the new frame
+    pub caller: H160,
+    // Address whose storage and balance the frame runs against
+    pub target: H160,
+    // Address the code is loaded from (differs from `target` for CALLCODE/DELEGATECALL)
+    pub code_address: H160,
+    pub value: U256,
+    pub input: Vec<u8>,
+    pub gas_limit: u64,
+    pub is_static: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CreateScheme {
+    Create,
+    Create2 { salt: U256 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateInputs {
+    pub scheme: CreateScheme,
+    pub caller: H160,
+    pub value: U256,
+    pub init_code: Vec<u8>,
+    pub gas_limit: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateOutcome {
+    pub result: ExecutionResult,
+    // None if the creation failed before an address was assigned
+    pub address: Option<H160>,
+}
+
+// Callbacks invoked by the interpreter, all with no-op defaults. `call`/`create`
+// may return an outcome to skip the real frame entirely, and `call_end`/`create_end`
+// may rewrite the outcome the caller will observe.
+pub trait Inspector {
+    // Before `step`, with write access: the instruction runs against whatever
+    // stack, memory and gas this leaves behind
+    fn interrupt(&mut self, _evm: &mut Evm) {}
+
+    // Before the instruction at `evm.pc` executes
+    fn step(&mut self, _evm: &Evm) {}
+
+    // After it executed; `error` is set if it halted the frame exceptionally
+    fn step_end(&mut self, _evm: &Evm, _error: Option<&EvmError>) {}
+
+    fn call(&mut self, _evm: &Evm, _inputs: &CallInputs) -> Option<ExecutionResult> {
+        None
+    }
+
+    fn call_end(&mut self, _evm: &Evm, _inputs: &CallInputs, _result: &mut ExecutionResult) {}
+
+    fn create(&mut self, _evm: &Evm, _inputs: &CreateInputs) -> Option<CreateOutcome> {
+        None
+    }
+
+    fn create_end(&mut self, _evm: &Evm, _inputs: &CreateInputs, _outcome: &mut CreateOutcome) {}
+
+    fn log(&mut self, _evm: &Evm, _log: &LogEntry) {}
+
+    fn selfdestruct(&mut self, _evm: &Evm, _address: H160, _beneficiary: H160, _value: U256) {}
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopInspector;
+
+impl Inspector for NoopInspector {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::SuccessReason;
+    use crate::testutil::{self, call_callee, callee_world, CALLEE};
+
+    // CALL the callee, which logs and returns 0x2a, and return its output
+    fn evm() -> Evm {
+        let world =
+            callee_world("PUSH1 0x2a PUSH1 0 MSTORE PUSH1 0 PUSH1 0 LOG0 PUSH1 32 PUSH1 0 RETURN");
+        testutil::evm(call_callee(32, "PUSH1 32 PUSH1 0 RETURN"), Some(world))
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        steps_per_depth: [usize; 2],
+        calls: Vec<(CallScheme, H160, u64)>,
+        logs: usize,
+        override_output: Option<Vec<u8>>,
+    }
+
+    impl Inspector for Recorder {
+        fn step(&mut self, evm: &Evm) {
+            self.steps_per_depth[evm.depth] += 1;
+        }
+
+        fn call(&mut self, _evm: &Evm, inputs: &CallInputs) -> Option<ExecutionResult> {
+            self.calls
+                .push((inputs.scheme, inputs.target, inputs.gas_limit));
+            self.override_output
+                .clone()
+                .map(|output| ExecutionResult::Success {
+                    reason: SuccessReason::Return,
+                    gas_used: 0,
+                    gas_refunded: 0,
+                    output,
+                    logs: Vec::new(),
+                })
+        }
+
+        fn log(&mut self, _evm: &Evm, _log: &LogEntry) {
+            self.logs += 1;
+        }
+    }
+
+    #[test]
+    fn sees_nested_frames() {
+        let plain = evm().run();
+        let mut evm = evm();
+        let mut rec = Recorder::default();
+        let result = evm.run_with(&mut rec);
+        assert!(result.is_success());
+        assert_eq!(result.output()[31], 0x2a);
+        assert_eq!(rec.steps_per_depth, [11, 9]);
+        assert_eq!(rec.calls.len(), 1);
+        assert_eq!(rec.calls[0].0, CallScheme::Call);
+        assert_eq!(rec.calls[0].1, CALLEE);
+        // All but a 64th of what is left after the pushes, memory and 700 base
+        let left = 10_000_000 - 23 - 700;
+        assert_eq!(rec.calls[0].2, left - left / 64);
+        assert_eq!(rec.logs, 1);
+        // Inspecting does not change gas: 769 in the caller plus the callee's 29
+        assert_eq!(result.gas_used(), plain.gas_used());
+        assert_eq!(result.gas_used(), 798);
+    }
+
+    #[test]
+    fn call_hook_overrides_outcome() {
+        let mut evm = evm();
+        let mut rec = Recorder {
+            override_output: Some(vec![0x07; 32]),
+            ..Recorder::default()
+        };
+        let result = evm.run_with(&mut rec);
+        assert_eq!(result.output(), &[0x07; 32]);
+        // The callee never ran
+        assert_eq!(rec.steps_per_depth[1], 0);
+        assert_eq!(rec.logs, 0);
+    }
+}
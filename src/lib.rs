@@ -1,5 +1,55 @@
+pub mod abi;
+pub mod analysis;
+pub mod asm;
+pub mod block;
+pub mod call_tracer;
+pub mod cfg;
+pub mod coverage;
+pub mod debugger;
 pub mod disasm;
+pub mod hexutil;
+pub mod inspector;
 pub mod machine;
 pub mod opcodes;
+pub mod prestate;
+pub mod profiler;
+pub mod proof;
+pub mod recording;
+pub mod rlp;
+pub mod selectors;
+pub mod srcmap;
+pub mod statetest;
+pub mod t8n;
+#[cfg(test)]
+mod testutil;
+pub mod tracer;
+pub mod transaction;
+pub mod trie;
+pub mod watch;
 
-pub use machine::{Account, BlockEnv, Evm, EvmConfig, EvmError, Halt, World};
+pub use abi::{
+    Abi, AbiError, AbiType, AbiValue, DecodedLog, Event, Param, RevertReason, Signature,
+};
+pub use analysis::{Analysis, BlockStack, Finding};
+pub use asm::{assemble, AsmError};
+pub use block::{execute_block, BlockError, BlockExecutor, BlockResult, Bloom, Receipt};
+pub use call_tracer::{CallFrame, CallLog, CallTracer};
+pub use cfg::{BasicBlock, Cfg, Edge, EdgeKind};
+pub use coverage::{CodeCoverage, Coverage, CoverageSummary};
+pub use debugger::{Breakpoint, Debugger};
+pub use disasm::Instruction;
+pub use inspector::{
+    CallInputs, CallScheme, CreateInputs, CreateOutcome, CreateScheme, Inspector, NoopInspector,
+};
+pub use machine::{
+    Account, BlockEnv, Evm, EvmConfig, EvmError, ExecutionResult, Halt, LogEntry, SuccessReason,
+    World,
+};
+pub use prestate::{PrestateAccount, PrestateTracer, StateDiff};
+pub use profiler::{FrameGas, GasProfiler, GasStat};
+pub use proof::{AccountProof, StorageProof};
+pub use recording::{Recorder, Recording, StepRecord, StepState};
+pub use selectors::{find_selectors, Selector, SignatureDb, SignatureDbError};
+pub use statetest::{run_state_tests, StateTestError, StateTestResult};
+pub use transaction::{AccessListItem, Transaction, TxError, TxKind, TxResult};
+pub use watch::{Condition, Hit, RunState, Watcher, Watchpoints};
@@ -1,9 +1,14 @@
 use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
 
-use primitive_types::{H160, U256};
+use primitive_types::{H160, H256, U256};
 use thiserror::Error;
 
+use crate::inspector::{
+    CallInputs, CallScheme, CreateInputs, CreateOutcome, CreateScheme, Inspector, NoopInspector,
+};
 use crate::opcodes::*;
+use crate::rlp;
 
 #[derive(Debug, Clone)]
 pub struct EvmConfig {
@@ -35,7 +40,7 @@ impl Default for EvmConfig {
     }
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum EvmError {
     #[error("out of gas")]
     OutOfGas,
@@ -51,14 +56,33 @@ pub enum EvmError {
     MemoryAccess,
     #[error("state modification in static context")]
     StaticViolation,
+    #[error("contract address collision")]
+    CreateCollision,
+    #[error("contract code size exceeds limit")]
+    CodeSizeLimit,
+    #[error("contract code starts with 0xEF")]
+    InvalidCodePrefix,
+    #[error("init code size exceeds limit")]
+    InitCodeSizeLimit,
+    #[error("insufficient balance for transfer")]
+    InsufficientBalance,
+    #[error("max call depth exceeded")]
+    CallDepth,
 }
 
-const STACK_LIMIT: usize = 1024;
+pub(crate) const STACK_LIMIT: usize = 1024;
+const CALL_DEPTH_LIMIT: usize = 1024;
+pub(crate) const MAX_CODE_SIZE: usize = 24_576; // EIP-170
+pub(crate) const MAX_INITCODE_SIZE: usize = 2 * MAX_CODE_SIZE; // EIP-3860
+pub(crate) const INITCODE_WORD_GAS: u64 = 2;
+const KECCAK_WORD_GAS: u64 = 6;
+const CODE_DEPOSIT_GAS: u64 = 200;
 
 #[derive(Debug, Clone)]
 pub struct Evm {
     pub pc: usize,
     pub gas: i128,
+    pub gas_limit: i128,
     pub code: Vec<u8>,
     pub stack: Vec<U256>,
     pub memory: Vec<u8>,
@@ -70,6 +94,10 @@ pub struct Evm {
     pub logs: Vec<LogEntry>,
     pub is_static: bool,
     pub refund: i128,
+    // Call depth; 0 for the outermost frame
+    pub depth: usize,
+    // Accounts touched by this frame and its successful children (EIP-161)
+    pub touched: HashSet<H160>,
     // Env/world
     pub address: Option<H160>,
     pub caller: Option<H160>,
@@ -86,10 +114,71 @@ pub enum Halt {
     Stop,
     Return,
     Revert,
+    // An exceptional halt, which uses all the frame's gas
+    Exception(EvmError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuccessReason {
+    Stop,
+    Return,
+    // Ran off the end of the code without an explicit STOP
+    EndOfCode,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExecutionResult {
+    Success {
+        reason: SuccessReason,
+        gas_used: u64,
+        gas_refunded: u64,
+        output: Vec<u8>,
+        logs: Vec<LogEntry>,
+    },
+    Revert {
+        gas_used: u64,
+        output: Vec<u8>,
+    },
+    // Exceptional halt: all gas given to the frame is consumed
+    Halt {
+        reason: EvmError,
+        gas_used: u64,
+    },
+}
+
+impl ExecutionResult {
+    pub fn is_success(&self) -> bool {
+        matches!(self, ExecutionResult::Success { .. })
+    }
+
+    pub fn gas_used(&self) -> u64 {
+        match self {
+            ExecutionResult::Success { gas_used, .. }
+            | ExecutionResult::Revert { gas_used, .. }
+            | ExecutionResult::Halt { gas_used, .. } => *gas_used,
+        }
+    }
+
+    pub fn output(&self) -> &[u8] {
+        match self {
+            ExecutionResult::Success { output, .. } | ExecutionResult::Revert { output, .. } => {
+                output
+            }
+            ExecutionResult::Halt { .. } => &[],
+        }
+    }
+
+    pub fn logs(&self) -> &[LogEntry] {
+        match self {
+            ExecutionResult::Success { logs, .. } => logs,
+            _ => &[],
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct LogEntry {
+    pub address: H160,
     pub topics: Vec<U256>,
     pub data: Vec<u8>,
 }
@@ -112,6 +201,13 @@ pub struct Account {
     pub storage: HashMap<U256, U256>,
 }
 
+impl Account {
+    // EIP-161 "empty": no code, zero nonce and zero balance
+    pub fn is_empty(&self) -> bool {
+        self.nonce == 0 && self.balance.is_zero() && self.code.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct World {
     pub accounts: HashMap<H160, Account>,
@@ -123,6 +219,7 @@ impl Evm {
         Self {
             pc: 0,
             gas: cfg.gas_limit,
+            gas_limit: cfg.gas_limit,
             code,
             stack: Vec::with_capacity(64),
             memory: Vec::new(),
@@ -134,6 +231,8 @@ impl Evm {
             logs: Vec::new(),
             is_static: false,
             refund: 0,
+            depth: 0,
+            touched: HashSet::new(),
             address: cfg.address,
             caller: cfg.caller,
             origin: cfg.origin,
@@ -145,14 +244,84 @@ impl Evm {
         }
     }
 
-    pub fn run(&mut self) -> Result<(), EvmError> {
-        while self.pc < self.code.len() && self.halted.is_none() {
-            self.step()?;
+    pub fn run(&mut self) -> ExecutionResult {
+        self.run_with(&mut NoopInspector)
+    }
+
+    // Run to completion, reporting every step and nested frame to `insp`
+    pub fn run_with(&mut self, insp: &mut dyn Inspector) -> ExecutionResult {
+        let Ok(result) = self.run_until_pause(insp, |_| None::<Infallible>);
+        result
+    }
+
+    // Steps until the frame stops, or until `pause` hands control back before an
+    // instruction. An exceptional halt is recorded in `halted`, so running the
+    // frame again returns the same result instead of retrying the instruction.
+    pub(crate) fn run_until_pause<T>(
+        &mut self,
+        insp: &mut dyn Inspector,
+        mut pause: impl FnMut(&Evm) -> Option<T>,
+    ) -> Result<ExecutionResult, T> {
+        loop {
+            if let Some(result) = self.result() {
+                return Ok(result);
+            }
+            if let Some(paused) = pause(self) {
+                return Err(paused);
+            }
+            if let Err(reason) = self.step_with(insp) {
+                self.halted = Some(Halt::Exception(reason));
+            }
         }
-        Ok(())
+    }
+
+    // Outcome of a frame that stopped normally, or `None` while it can still step
+    pub fn result(&self) -> Option<ExecutionResult> {
+        let gas_used = (self.gas_limit - self.gas).max(0) as u64;
+        let reason = match self.halted {
+            Some(Halt::Stop) => SuccessReason::Stop,
+            Some(Halt::Return) => SuccessReason::Return,
+            Some(Halt::Revert) => {
+                return Some(ExecutionResult::Revert {
+                    gas_used,
+                    output: self.return_data.clone(),
+                })
+            }
+            Some(Halt::Exception(ref reason)) => {
+                return Some(ExecutionResult::Halt {
+                    reason: reason.clone(),
+                    gas_used: self.gas_limit.max(0) as u64,
+                })
+            }
+            None if self.pc >= self.code.len() => SuccessReason::EndOfCode,
+            None => return None,
+        };
+        Some(ExecutionResult::Success {
+            reason,
+            gas_used,
+            gas_refunded: self.refund.max(0) as u64,
+            output: self.return_data.clone(),
+            logs: self.logs.clone(),
+        })
     }
 
     pub fn step(&mut self) -> Result<(), EvmError> {
+        self.step_with(&mut NoopInspector)
+    }
+
+    pub fn step_with(&mut self, insp: &mut dyn Inspector) -> Result<(), EvmError> {
+        insp.interrupt(self);
+        // The inspector may have moved the frame past its last instruction
+        if self.halted.is_some() || self.pc >= self.code.len() {
+            return Ok(());
+        }
+        insp.step(self);
+        let res = self.execute(insp);
+        insp.step_end(self, res.as_ref().err());
+        res
+    }
+
+    fn execute(&mut self, insp: &mut dyn Inspector) -> Result<(), EvmError> {
         if self.gas <= 0 {
             return Err(EvmError::OutOfGas);
         }
@@ -248,7 +417,7 @@ impl Evm {
                 hasher.update(slice);
                 hasher.finalize(&mut out);
                 self.push(U256::from_big_endian(&out))?;
-                self.gas_dec(30 + (size_usize as i128 + 31) as i128 / 32)?; // rough
+                self.gas_dec(30 + (size_usize as i128 + 31) / 32)?; // rough
                 self.pc += 1;
             }
 
@@ -693,453 +862,157 @@ impl Evm {
                 let s = u256_to_usize(msize);
                 self.ensure_memory(o + s);
                 let data = self.memory[o..o + s].to_vec();
-                self.logs.push(LogEntry { topics, data });
+                let log = LogEntry {
+                    address: self.address.unwrap_or_default(),
+                    topics,
+                    data,
+                };
+                insp.log(self, &log);
+                self.logs.push(log);
                 self.gas_dec(8 + (s as i128 + 31) / 32)?; // rough
                 self.pc += 1;
             }
 
             // CALL (simplified)
-            CALL => {
-                let _gas = self.pop()?;
+            CALL | CALLCODE => {
+                let gas = self.pop()?;
                 let to = self.pop()?;
                 let value = self.pop()?;
                 let in_off = self.pop()?;
                 let in_sz = self.pop()?;
                 let out_off = self.pop()?;
                 let out_sz = self.pop()?;
-                let to_h = u256_to_h160(to);
-                let io = u256_to_usize(in_off);
-                let isz = u256_to_usize(in_sz);
-                let oo = u256_to_usize(out_off);
-                let osz = u256_to_usize(out_sz);
-                self.charge_memory(io + isz)?;
-                self.ensure_memory(io + isz);
-                self.charge_memory(oo + osz)?;
-                self.ensure_memory(oo + osz);
-                let input = self.memory[io..io + isz].to_vec();
-                let (success, ret) = {
-                    let from_addr = self.address.unwrap_or_default();
-                    let (forward, base) = call_gas(self.gas, _gas.as_u128(), !value.is_zero());
-                    self.gas_dec(base as i128)?;
-                    let mut ret = Vec::new();
-                    let success;
-                    if let Some(w) = &mut self.world {
-                        // snapshot world for potential revert
-                        let mut w_clone = w.clone();
-                        let from_acc = w_clone.accounts.entry(from_addr).or_default();
-                        if from_acc.balance >= value {
-                            // precompile hook (identity at 0x0004)
-                            if let Some(pc_ret) = precompile(to_h, &input) {
-                                ret = pc_ret;
-                                success = true;
-                            } else {
-                                from_acc.balance -= value;
-                                let to_acc = w_clone.accounts.entry(to_h).or_default();
-                                to_acc.balance += value;
-                                let code = to_acc.code.clone();
-                                let mut child = Evm::new(
-                                    code,
-                                    EvmConfig {
-                                        gas_limit: (forward
-                                            + if !value.is_zero() { 2300 } else { 0 })
-                                            as i128,
-                                        calldata: input.clone(),
-                                        address: Some(to_h),
-                                        caller: Some(from_addr),
-                                        origin: self.origin,
-                                        value,
-                                        gas_price: self.gas_price,
-                                        block: self.block.clone(),
-                                        world: Some(w_clone.clone()),
-                                    },
-                                );
-                                if let Err(_e) = child.run() {
-                                    success = false;
-                                } else {
-                                    success = !matches!(child.halted, Some(Halt::Revert));
-                                    ret = child.return_data.clone();
-                                    if success {
-                                        if let Some(child_world) = child.world.take() {
-                                            *w = child_world;
-                                        }
-                                    }
-                                }
-                            }
-                        } else {
-                            success = false;
-                        }
-                    } else {
-                        // no world state; simulate as empty call
-                        success = true;
-                    }
-                    (success, ret)
-                };
-                // write return
-                for i in 0..osz {
-                    self.memory[oo + i] = *ret.get(i).unwrap_or(&0);
+                // EIP-214: no value transfers in a static context
+                if op == CALL && self.is_static && !value.is_zero() {
+                    return Err(EvmError::StaticViolation);
                 }
-                self.last_return_data = ret;
-                self.push(if success { U256::one() } else { U256::zero() })?;
-                self.gas_dec(40)?;
-                self.pc += 1;
-            }
-            STATICCALL => {
-                let _gas = self.pop()?;
-                let to = self.pop()?;
-                let in_off = self.pop()?;
-                let in_sz = self.pop()?;
-                let out_off = self.pop()?;
-                let out_sz = self.pop()?;
+                let input = self.call_memory(in_off, in_sz, out_off, out_sz)?;
+                let self_addr = self.address.unwrap_or_default();
+                let (forward, base) = call_gas(self.gas, saturate_u128(gas), !value.is_zero());
+                self.gas_dec(base as i128)?;
                 let to_h = u256_to_h160(to);
-                let io = u256_to_usize(in_off);
-                let isz = u256_to_usize(in_sz);
-                let oo = u256_to_usize(out_off);
-                let osz = u256_to_usize(out_sz);
-                self.charge_memory(io + isz)?;
-                self.ensure_memory(io + isz);
-                self.charge_memory(oo + osz)?;
-                self.ensure_memory(oo + osz);
-                let input = self.memory[io..io + isz].to_vec();
-                let (success, ret) = {
-                    let mut ret = Vec::new();
-                    let success;
-                    if let Some(w) = &mut self.world {
-                        let from_addr = self.address.unwrap_or_default();
-                        if let Some(pc_ret) = precompile(to_h, &input) {
-                            ret = pc_ret;
-                            success = true;
-                        } else {
-                            let code = w
-                                .accounts
-                                .get(&to_h)
-                                .map(|a| a.code.clone())
-                                .unwrap_or_default();
-                            let mut child = Evm::new(
-                                code,
-                                EvmConfig {
-                                    gas_limit: self.gas,
-                                    calldata: input.clone(),
-                                    address: Some(to_h),
-                                    caller: Some(from_addr),
-                                    origin: self.origin,
-                                    value: U256::zero(),
-                                    gas_price: self.gas_price,
-                                    block: self.block.clone(),
-                                    world: Some(w.clone()),
-                                },
-                            );
-                            child.is_static = true;
-                            if let Err(_e) = child.run() {
-                                success = false;
-                            } else {
-                                success = !matches!(child.halted, Some(Halt::Revert));
-                                ret = child.return_data.clone();
-                                if let Some(child_world) = child.world.take() {
-                                    *w = child_world;
-                                }
-                            }
-                        }
+                let inputs = CallInputs {
+                    scheme: if op == CALL {
+                        CallScheme::Call
                     } else {
-                        success = true;
-                    }
-                    (success, ret)
+                        CallScheme::CallCode
+                    },
+                    caller: self_addr,
+                    // CALLCODE runs the target's code against our own account
+                    target: if op == CALL { to_h } else { self_addr },
+                    code_address: to_h,
+                    value,
+                    input,
+                    gas_limit: (forward + if value.is_zero() { 0 } else { 2300 }) as u64,
+                    is_static: self.is_static,
                 };
-                for i in 0..osz {
-                    self.memory[oo + i] = *ret.get(i).unwrap_or(&0);
-                }
-                self.last_return_data = ret;
-                self.push(if success { U256::one() } else { U256::zero() })?;
-                self.gas_dec(40)?;
-                self.pc += 1;
+                // the value stipend is free for the caller
+                let stipend = if value.is_zero() { 0 } else { 2300 };
+                self.call_with(inputs, out_off, out_sz, stipend, insp)?;
             }
-
-            // CALLCODE: code from target, state/address from current
-            CALLCODE => {
-                let _gas = self.pop()?;
+            STATICCALL | DELEGATECALL => {
+                let gas = self.pop()?;
                 let to = self.pop()?;
-                let value = self.pop()?;
                 let in_off = self.pop()?;
                 let in_sz = self.pop()?;
                 let out_off = self.pop()?;
                 let out_sz = self.pop()?;
+                let input = self.call_memory(in_off, in_sz, out_off, out_sz)?;
                 let to_h = u256_to_h160(to);
-                let io = u256_to_usize(in_off);
-                let isz = u256_to_usize(in_sz);
-                let oo = u256_to_usize(out_off);
-                let osz = u256_to_usize(out_sz);
-                self.charge_memory(io + isz)?;
-                self.ensure_memory(io + isz);
-                self.charge_memory(oo + osz)?;
-                self.ensure_memory(oo + osz);
-                let input = self.memory[io..io + isz].to_vec();
-                let (success, ret) = {
-                    let mut ret = Vec::new();
-                    let success;
-                    let self_addr = self.address.unwrap_or_default();
-                    let (forward, base) = call_gas(self.gas, _gas.as_u128(), !value.is_zero());
-                    self.gas_dec(base as i128)?;
-                    if let Some(w) = &mut self.world {
-                        let mut w_clone = w.clone();
-                        if w_clone.accounts.entry(self_addr).or_default().balance >= value {
-                            if let Some(pc_ret) = precompile(to_h, &input) {
-                                ret = pc_ret;
-                                success = true;
-                            } else {
-                                let code = w_clone
-                                    .accounts
-                                    .get(&to_h)
-                                    .map(|a| a.code.clone())
-                                    .unwrap_or_default();
-                                let mut child = Evm::new(
-                                    code,
-                                    EvmConfig {
-                                        gas_limit: (forward
-                                            + if !value.is_zero() { 2300 } else { 0 })
-                                            as i128,
-                                        calldata: input.clone(),
-                                        address: Some(self_addr),
-                                        caller: Some(self_addr),
-                                        origin: self.origin,
-                                        value,
-                                        gas_price: self.gas_price,
-                                        block: self.block.clone(),
-                                        world: Some(w_clone.clone()),
-                                    },
-                                );
-                                if let Err(_e) = child.run() {
-                                    success = false;
-                                } else {
-                                    success = !matches!(child.halted, Some(Halt::Revert));
-                                    ret = child.return_data.clone();
-                                    if success {
-                                        if let Some(child_world) = child.world.take() {
-                                            *w = child_world;
-                                        }
-                                    }
-                                }
-                            }
-                        } else {
-                            success = false;
-                        }
-                    } else {
-                        success = true;
+                let (forward, base) = call_gas(self.gas, saturate_u128(gas), false);
+                self.gas_dec(base as i128)?;
+                let inputs = if op == STATICCALL {
+                    CallInputs {
+                        scheme: CallScheme::StaticCall,
+                        caller: self.address.unwrap_or_default(),
+                        target: to_h,
+                        code_address: to_h,
+                        value: U256::zero(),
+                        input,
+                        gas_limit: forward as u64,
+                        is_static: true,
                     }
-                    (success, ret)
-                };
-                for i in 0..osz {
-                    self.memory[oo + i] = *ret.get(i).unwrap_or(&0);
-                }
-                self.last_return_data = ret;
-                self.push(if success { U256::one() } else { U256::zero() })?;
-                self.gas_dec(40)?;
-                self.pc += 1;
-            }
-
-            // DELEGATECALL: code at target, storage/address/caller/value inherited
-            DELEGATECALL => {
-                let _gas = self.pop()?;
-                let to = self.pop()?;
-                let in_off = self.pop()?;
-                let in_sz = self.pop()?;
-                let out_off = self.pop()?;
-                let out_sz = self.pop()?;
-                let to_h = u256_to_h160(to);
-                let io = u256_to_usize(in_off);
-                let isz = u256_to_usize(in_sz);
-                let oo = u256_to_usize(out_off);
-                let osz = u256_to_usize(out_sz);
-                self.charge_memory(io + isz)?;
-                self.ensure_memory(io + isz);
-                self.charge_memory(oo + osz)?;
-                self.ensure_memory(oo + osz);
-                let input = self.memory[io..io + isz].to_vec();
-                let (success, ret) = {
-                    let mut ret = Vec::new();
-                    let (forward, base) = call_gas(self.gas, _gas.as_u128(), false);
-                    self.gas_dec(base as i128)?;
-                    let success;
-                    if let Some(w) = &mut self.world {
-                        let w_clone = w.clone();
-                        if let Some(pc_ret) = precompile(to_h, &input) {
-                            ret = pc_ret;
-                            success = true;
-                        } else {
-                            let code = w_clone
-                                .accounts
-                                .get(&to_h)
-                                .map(|a| a.code.clone())
-                                .unwrap_or_default();
-                            let mut child = Evm::new(
-                                code,
-                                EvmConfig {
-                                    gas_limit: forward as i128,
-                                    calldata: input.clone(),
-                                    address: self.address,
-                                    caller: self.caller,
-                                    origin: self.origin,
-                                    value: self.callvalue,
-                                    gas_price: self.gas_price,
-                                    block: self.block.clone(),
-                                    world: Some(w_clone.clone()),
-                                },
-                            );
-                            if let Err(_e) = child.run() {
-                                success = false;
-                            } else {
-                                success = !matches!(child.halted, Some(Halt::Revert));
-                                ret = child.return_data.clone();
-                                if success {
-                                    if let Some(child_world) = child.world.take() {
-                                        *w = child_world;
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        success = true;
+                } else {
+                    // DELEGATECALL: code at target, storage/address/caller/value inherited
+                    CallInputs {
+                        scheme: CallScheme::DelegateCall,
+                        caller: self.caller.unwrap_or_default(),
+                        target: self.address.unwrap_or_default(),
+                        code_address: to_h,
+                        value: self.callvalue,
+                        input,
+                        gas_limit: forward as u64,
+                        is_static: self.is_static,
                     }
-                    (success, ret)
                 };
-                for i in 0..osz {
-                    self.memory[oo + i] = *ret.get(i).unwrap_or(&0);
-                }
-                self.last_return_data = ret;
-                self.push(if success { U256::one() } else { U256::zero() })?;
-                self.gas_dec(40)?;
-                self.pc += 1;
+                self.call_with(inputs, out_off, out_sz, 0, insp)?;
             }
 
-            // CREATE: value, offset, size
-            CREATE => {
+            // CREATE: value, offset, size; CREATE2 adds salt
+            CREATE | CREATE2 => {
                 if self.is_static {
                     return Err(EvmError::StaticViolation);
                 }
                 let value = self.pop()?;
                 let offset = self.pop()?;
                 let size = self.pop()?;
+                let scheme = if op == CREATE2 {
+                    CreateScheme::Create2 { salt: self.pop()? }
+                } else {
+                    CreateScheme::Create
+                };
                 let o = u256_to_usize(offset);
                 let s = u256_to_usize(size);
+                if s > MAX_INITCODE_SIZE {
+                    return Err(EvmError::InitCodeSizeLimit);
+                }
                 self.charge_memory(o + s)?;
                 self.ensure_memory(o + s);
-                let init = self.memory[o..o + s].to_vec();
-                let (success, created) = if let Some(w) = &mut self.world {
-                    let from = self.address.unwrap_or_default();
-                    let mut w_clone = w.clone();
-                    let acc = w_clone.accounts.entry(from).or_default();
-                    if acc.balance >= value {
-                        let nonce = acc.nonce;
-                        acc.nonce = acc.nonce.saturating_add(1);
-                        let created = create_address(from, nonce);
-                        acc.balance -= value;
-                        let entry = w_clone.accounts.entry(created).or_default();
-                        entry.balance += value;
-                        let mut child = Evm::new(
-                            init,
-                            EvmConfig {
-                                gas_limit: self.gas,
-                                calldata: Vec::new(),
-                                address: Some(created),
-                                caller: Some(from),
-                                origin: self.origin,
-                                value,
-                                gas_price: self.gas_price,
-                                block: self.block.clone(),
-                                world: Some(w_clone.clone()),
-                            },
-                        );
-                        if let Err(_e) = child.run() {
-                            (false, created)
-                        } else {
-                            let success = !matches!(child.halted, Some(Halt::Revert));
-                            if success {
-                                let code = child.return_data.clone();
-                                if let Some(mut child_world) = child.world.take() {
-                                    let e = child_world.accounts.entry(created).or_default();
-                                    e.code = code;
-                                    *w = child_world;
-                                }
-                            }
-                            (success, created)
-                        }
-                    } else {
-                        (false, H160::zero())
-                    }
-                } else {
-                    (false, H160::zero())
+                // CREATE2 also pays for hashing the init code into the address
+                let word_gas = INITCODE_WORD_GAS + if op == CREATE2 { KECCAK_WORD_GAS } else { 0 };
+                self.gas_dec((32000 + word_gas * words(s)) as i128)?;
+                let inputs = CreateInputs {
+                    scheme,
+                    caller: self.address.unwrap_or_default(),
+                    value,
+                    init_code: self.memory[o..o + s].to_vec(),
+                    // all but one 64th (EIP-150)
+                    gas_limit: (self.gas - self.gas / 64).max(0) as u64,
                 };
-                if success {
-                    self.push(h160_to_u256(created))?;
-                } else {
-                    self.push(U256::zero())?;
+                let mut outcome = match insp.create(self, &inputs) {
+                    Some(outcome) => outcome,
+                    None => self.create_frame(&inputs, insp),
+                };
+                insp.create_end(self, &inputs, &mut outcome);
+                self.gas_dec(outcome.result.gas_used() as i128)?;
+                match outcome.address {
+                    Some(created) if outcome.result.is_success() => {
+                        self.push(h160_to_u256(created))?
+                    }
+                    _ => self.push(U256::zero())?,
                 }
-                self.gas_dec(32000)?;
                 self.pc += 1;
             }
 
-            // CREATE2: value, offset, size, salt
-            CREATE2 => {
+            SELFDESTRUCT => {
                 if self.is_static {
                     return Err(EvmError::StaticViolation);
                 }
-                let value = self.pop()?;
-                let offset = self.pop()?;
-                let size = self.pop()?;
-                let salt = self.pop()?;
-                let o = u256_to_usize(offset);
-                let s = u256_to_usize(size);
-                self.charge_memory(o + s)?;
-                self.ensure_memory(o + s);
-                let init = self.memory[o..o + s].to_vec();
-                let (success, created) = if let Some(w) = &mut self.world {
-                    let from = self.address.unwrap_or_default();
-                    let mut w_clone = w.clone();
-                    let acc = w_clone.accounts.entry(from).or_default();
-                    if acc.balance >= value {
-                        let created = create2_address(from, salt, &init);
-                        acc.balance -= value;
-                        let entry = w_clone.accounts.entry(created).or_default();
-                        entry.balance += value;
-                        let mut child = Evm::new(
-                            init,
-                            EvmConfig {
-                                gas_limit: self.gas,
-                                calldata: Vec::new(),
-                                address: Some(created),
-                                caller: Some(from),
-                                origin: self.origin,
-                                value,
-                                gas_price: self.gas_price,
-                                block: self.block.clone(),
-                                world: Some(w_clone.clone()),
-                            },
-                        );
-                        if let Err(_e) = child.run() {
-                            (false, created)
-                        } else {
-                            let success = !matches!(child.halted, Some(Halt::Revert));
-                            if success {
-                                let code = child.return_data.clone();
-                                if let Some(mut child_world) = child.world.take() {
-                                    let e = child_world.accounts.entry(created).or_default();
-                                    e.code = code;
-                                    *w = child_world;
-                                }
-                            }
-                            (success, created)
-                        }
-                    } else {
-                        (false, H160::zero())
-                    }
-                } else {
-                    (false, H160::zero())
-                };
-                if success {
-                    self.push(h160_to_u256(created))?;
-                } else {
-                    self.push(U256::zero())?;
+                let beneficiary = u256_to_h160(self.pop()?);
+                self.gas_dec(5000)?;
+                let address = self.address.unwrap_or_default();
+                let mut value = U256::zero();
+                // Only the balance moves; the account is kept, even one created in this
+                // transaction (EIP-6780 would delete that)
+                if let Some(w) = &mut self.world {
+                    let acc = w.accounts.entry(address).or_default();
+                    value = std::mem::take(&mut acc.balance);
+                    w.accounts.entry(beneficiary).or_default().balance += value;
                 }
-                self.gas_dec(32000)?;
-                self.pc += 1;
+                self.touched.insert(beneficiary);
+                insp.selfdestruct(self, address, beneficiary, value);
+                self.halted = Some(Halt::Stop);
+                self.pc = self.code.len();
             }
 
             _ => return Err(EvmError::InvalidOpcode(op, self.pc)),
@@ -1147,6 +1020,211 @@ impl Evm {
         Ok(())
     }
 
+    // Charge and expand memory for a call's input and output regions; returns the input.
+    fn call_memory(
+        &mut self,
+        in_off: U256,
+        in_sz: U256,
+        out_off: U256,
+        out_sz: U256,
+    ) -> Result<Vec<u8>, EvmError> {
+        let io = u256_to_usize(in_off);
+        let isz = u256_to_usize(in_sz);
+        let oo = u256_to_usize(out_off);
+        let osz = u256_to_usize(out_sz);
+        self.charge_memory(io + isz)?;
+        self.ensure_memory(io + isz);
+        self.charge_memory(oo + osz)?;
+        self.ensure_memory(oo + osz);
+        Ok(self.memory[io..io + isz].to_vec())
+    }
+
+    fn call_with(
+        &mut self,
+        inputs: CallInputs,
+        out_off: U256,
+        out_sz: U256,
+        stipend: u64,
+        insp: &mut dyn Inspector,
+    ) -> Result<(), EvmError> {
+        let mut result = match insp.call(self, &inputs) {
+            Some(result) => result,
+            None => self.call_frame(&inputs, insp),
+        };
+        insp.call_end(self, &inputs, &mut result);
+        // Signed: a callee that used less than the stipend leaves the rest to the caller
+        self.gas -= result.gas_used() as i128 - stipend as i128;
+        if self.gas < 0 {
+            return Err(EvmError::OutOfGas);
+        }
+        let ret = result.output().to_vec();
+        let oo = u256_to_usize(out_off);
+        for i in 0..u256_to_usize(out_sz) {
+            self.memory[oo + i] = *ret.get(i).unwrap_or(&0);
+        }
+        self.last_return_data = ret;
+        self.push(if result.is_success() {
+            U256::one()
+        } else {
+            U256::zero()
+        })?;
+        self.gas_dec(40)?;
+        self.pc += 1;
+        Ok(())
+    }
+
+    fn call_frame(&mut self, inputs: &CallInputs, insp: &mut dyn Inspector) -> ExecutionResult {
+        if self.depth >= CALL_DEPTH_LIMIT {
+            return ExecutionResult::Halt {
+                reason: EvmError::CallDepth,
+                gas_used: 0,
+            };
+        }
+        let Some(w) = &self.world else {
+            // no world state; simulate as empty call
+            return returned(Vec::new());
+        };
+        // child runs on a snapshot that is only kept if it succeeds
+        let mut world = w.clone();
+        let transfers = matches!(inputs.scheme, CallScheme::Call | CallScheme::CallCode);
+        let balance = world
+            .accounts
+            .get(&inputs.caller)
+            .map(|a| a.balance)
+            .unwrap_or_default();
+        if transfers && balance < inputs.value {
+            return ExecutionResult::Halt {
+                reason: EvmError::InsufficientBalance,
+                gas_used: 0,
+            };
+        }
+        // precompile hook (identity at 0x0004)
+        if let Some(result) = precompile(inputs.code_address, &inputs.input, inputs.gas_limit) {
+            if result.is_success() {
+                self.touched.insert(inputs.target);
+            }
+            return result;
+        }
+        if inputs.scheme == CallScheme::Call {
+            world.accounts.entry(inputs.caller).or_default().balance -= inputs.value;
+            world.accounts.entry(inputs.target).or_default().balance += inputs.value;
+        }
+        let code = world
+            .accounts
+            .get(&inputs.code_address)
+            .map(|a| a.code.clone())
+            .unwrap_or_default();
+        let mut child = Evm::new(
+            code,
+            EvmConfig {
+                gas_limit: inputs.gas_limit as i128,
+                calldata: inputs.input.clone(),
+                address: Some(inputs.target),
+                caller: Some(inputs.caller),
+                origin: self.origin,
+                value: inputs.value,
+                gas_price: self.gas_price,
+                block: self.block.clone(),
+                world: Some(world),
+            },
+        );
+        child.is_static = inputs.is_static;
+        child.depth = self.depth + 1;
+        child.touched.insert(inputs.target);
+        let result = child.run_with(insp);
+        if result.is_success() {
+            self.logs.append(&mut child.logs);
+            self.touched.extend(child.touched.drain());
+            self.world = child.world.take();
+        }
+        result
+    }
+
+    fn create_frame(&mut self, inputs: &CreateInputs, insp: &mut dyn Inspector) -> CreateOutcome {
+        if self.depth >= CALL_DEPTH_LIMIT {
+            return CreateOutcome {
+                result: ExecutionResult::Halt {
+                    reason: EvmError::CallDepth,
+                    gas_used: 0,
+                },
+                address: None,
+            };
+        }
+        let Some(w) = &mut self.world else {
+            // nowhere to deploy without world state
+            return CreateOutcome {
+                result: ExecutionResult::Revert {
+                    gas_used: 0,
+                    output: Vec::new(),
+                },
+                address: None,
+            };
+        };
+        let acc = w.accounts.entry(inputs.caller).or_default();
+        if acc.balance < inputs.value {
+            return CreateOutcome {
+                result: ExecutionResult::Halt {
+                    reason: EvmError::InsufficientBalance,
+                    gas_used: 0,
+                },
+                address: None,
+            };
+        }
+        // The nonce is bumped even if the init code fails, so it is not
+        // part of the snapshot
+        let nonce = acc.nonce;
+        acc.nonce = acc.nonce.saturating_add(1);
+        let created = match inputs.scheme {
+            CreateScheme::Create => create_address(inputs.caller, nonce),
+            CreateScheme::Create2 { salt } => {
+                create2_address(inputs.caller, salt, &inputs.init_code)
+            }
+        };
+        if create_collision(w, created) {
+            return CreateOutcome {
+                result: ExecutionResult::Halt {
+                    reason: EvmError::CreateCollision,
+                    gas_used: inputs.gas_limit,
+                },
+                address: Some(created),
+            };
+        }
+        let mut world = w.clone();
+        world.accounts.entry(inputs.caller).or_default().balance -= inputs.value;
+        let account = world.accounts.entry(created).or_default();
+        account.balance += inputs.value;
+        account.nonce = 1;
+        let mut child = Evm::new(
+            inputs.init_code.clone(),
+            EvmConfig {
+                gas_limit: inputs.gas_limit as i128,
+                calldata: Vec::new(),
+                address: Some(created),
+                caller: Some(inputs.caller),
+                origin: self.origin,
+                value: inputs.value,
+                gas_price: self.gas_price,
+                block: self.block.clone(),
+                world: Some(world),
+            },
+        );
+        child.depth = self.depth + 1;
+        child.touched.insert(created);
+        let result = deploy_checks(child.run_with(insp), inputs.gas_limit);
+        if result.is_success() {
+            self.logs.append(&mut child.logs);
+            self.touched.extend(child.touched.drain());
+            if let Some(mut child_world) = child.world.take() {
+                child_world.accounts.entry(created).or_default().code = result.output().to_vec();
+                self.world = Some(child_world);
+            }
+        }
+        CreateOutcome {
+            result,
+            address: Some(created),
+        }
+    }
+
     fn push(&mut self, v: U256) -> Result<(), EvmError> {
         if self.stack.len() >= STACK_LIMIT {
             return Err(EvmError::StackOverflow);
@@ -1218,7 +1296,19 @@ impl Evm {
     }
 }
 
-fn scan_jumpdests(code: &[u8]) -> HashSet<usize> {
+// Successful frame that returned `output` without running code (no world)
+fn returned(output: Vec<u8>) -> ExecutionResult {
+    ExecutionResult::Success {
+        reason: SuccessReason::Return,
+        gas_used: 0,
+        gas_refunded: 0,
+        output,
+        logs: Vec::new(),
+    }
+}
+
+// Offsets of JUMPDEST instructions, skipping PUSH data
+pub(crate) fn scan_jumpdests(code: &[u8]) -> HashSet<usize> {
     let mut set = HashSet::new();
     let mut pc = 0usize;
     while pc < code.len() {
@@ -1264,6 +1354,11 @@ fn words(size: usize) -> u64 {
 fn mem_cost(words: u64) -> u64 {
     3 * words + (words * words) / 512
 }
+// Gas operands above u128::MAX (e.g. `not(0)`) just ask for everything
+fn saturate_u128(v: U256) -> u128 {
+    v.min(U256::from(u128::MAX)).as_u128()
+}
+
 fn call_gas(available: i128, requested: u128, has_value: bool) -> (u128, u64) {
     // Base cost rough: 700 + 9000 if value
     let base: u64 = 700 + if has_value { 9000 } else { 0 };
@@ -1277,47 +1372,65 @@ fn call_gas(available: i128, requested: u128, has_value: bool) -> (u128, u64) {
     (forward, base)
 }
 
-fn rlp_bytes(b: &[u8]) -> Vec<u8> {
-    if b.len() == 1 && b[0] < 0x80 {
-        return vec![b[0]];
-    }
-    let mut out = Vec::new();
-    out.push(0x80 + (b.len() as u8));
-    out.extend_from_slice(b);
-    out
-}
-
-fn rlp_u64(n: u64) -> Vec<u8> {
-    if n == 0 {
-        return vec![0x80];
-    }
-    let mut buf = Vec::new();
-    let mut x = n;
-    let mut tmp = [0u8; 8];
-    let mut i = 8;
-    while x > 0 {
-        i -= 1;
-        tmp[i] = (x & 0xff) as u8;
-        x >>= 8;
-    }
-    buf.extend_from_slice(&tmp[i..]);
-    rlp_bytes(&buf)
-}
-
-fn create_address(from: H160, nonce: u64) -> H160 {
-    let mut rlp = Vec::new();
-    let enc_from = rlp_bytes(from.as_bytes());
-    let enc_nonce = rlp_u64(nonce);
-    let list_len = enc_from.len() + enc_nonce.len();
-    rlp.push(0xC0 + (list_len as u8));
-    rlp.extend_from_slice(&enc_from);
-    rlp.extend_from_slice(&enc_nonce);
+pub fn keccak256(data: &[u8]) -> H256 {
     use tiny_keccak::{Hasher, Keccak};
     let mut out = [0u8; 32];
     let mut k = Keccak::v256();
-    k.update(&rlp);
+    k.update(data);
     k.finalize(&mut out);
-    H160::from_slice(&out[12..])
+    H256(out)
+}
+
+pub(crate) fn create_address(from: H160, nonce: u64) -> H160 {
+    let rlp = rlp::encode_list(&[rlp::encode_bytes(from.as_bytes()), rlp::encode_u64(nonce)]);
+    H160::from_slice(&keccak256(&rlp)[12..])
+}
+
+// Nothing may be deployed over an account that already has code or a nonce
+pub(crate) fn create_collision(world: &World, address: H160) -> bool {
+    world
+        .accounts
+        .get(&address)
+        .is_some_and(|acc| acc.nonce != 0 || !acc.code.is_empty())
+}
+
+// Checks on the code returned by init code, for creation transactions and CREATE/CREATE2
+// alike: the 200 gas/byte deposit must fit in `gas_limit`, and the code must respect the
+// EIP-170 size limit and the EIP-3541 0xEF prefix ban. A failure uses all of `gas_limit`.
+pub(crate) fn deploy_checks(result: ExecutionResult, gas_limit: u64) -> ExecutionResult {
+    let ExecutionResult::Success {
+        reason,
+        gas_used,
+        gas_refunded,
+        output,
+        logs,
+    } = result
+    else {
+        return result;
+    };
+    let gas_used = gas_used + CODE_DEPOSIT_GAS * output.len() as u64;
+    let failure = if output.len() > MAX_CODE_SIZE {
+        Some(EvmError::CodeSizeLimit)
+    } else if output.first() == Some(&0xEF) {
+        Some(EvmError::InvalidCodePrefix)
+    } else if gas_used > gas_limit {
+        Some(EvmError::OutOfGas)
+    } else {
+        None
+    };
+    match failure {
+        Some(reason) => ExecutionResult::Halt {
+            reason,
+            gas_used: gas_limit,
+        },
+        None => ExecutionResult::Success {
+            reason,
+            gas_used,
+            gas_refunded,
+            output,
+            logs,
+        },
+    }
 }
 
 fn create2_address(from: H160, salt: U256, init: &[u8]) -> H160 {
@@ -1338,24 +1451,40 @@ fn create2_address(from: H160, salt: U256, init: &[u8]) -> H160 {
     H160::from_slice(&out[12..])
 }
 
-fn precompile(addr: H160, input: &[u8]) -> Option<Vec<u8>> {
+// Outcome of calling the precompile at `addr` with `gas_limit`, None if there is none
+pub(crate) fn precompile(addr: H160, input: &[u8], gas_limit: u64) -> Option<ExecutionResult> {
     // Minimal: identity at 0x000...04; others unimplemented
-    if addr == H160::from_low_u64_be(4) {
-        return Some(input.to_vec());
+    if addr != H160::from_low_u64_be(4) {
+        return None;
+    }
+    let gas_used = 15 + 3 * words(input.len());
+    if gas_used > gas_limit {
+        return Some(ExecutionResult::Halt {
+            reason: EvmError::OutOfGas,
+            gas_used: gas_limit,
+        });
     }
-    None
+    Some(ExecutionResult::Success {
+        reason: SuccessReason::Return,
+        gas_used,
+        gas_refunded: 0,
+        output: input.to_vec(),
+        logs: Vec::new(),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::asm::assemble;
+    use crate::testutil;
 
     #[test]
     fn simple_add() {
         // PUSH1 0x42; PUSH1 0xFF; ADD
         let code = vec![0x60, 0x42, 0x60, 0xFF, 0x01];
         let mut evm = Evm::new(code, EvmConfig::default());
-        evm.run().unwrap();
+        assert!(evm.run().is_success());
         assert_eq!(evm.stack.len(), 1);
         assert_eq!(evm.stack[0], U256::from(0x42u64 + 0xFFu64));
     }
@@ -1368,7 +1497,334 @@ mod tests {
         code.push(1);
         code.push(0x50); // POP
         let mut evm = Evm::new(code, EvmConfig::default());
-        evm.run().unwrap();
+        assert!(evm.run().is_success());
+        assert!(evm.stack.is_empty());
+    }
+
+    #[test]
+    fn run_reports_end_of_code_and_revert() {
+        // PUSH1 1 and nothing else
+        let mut evm = Evm::new(vec![0x60, 0x01], EvmConfig::default());
+        match evm.run() {
+            ExecutionResult::Success {
+                reason, gas_used, ..
+            } => {
+                assert_eq!(reason, SuccessReason::EndOfCode);
+                assert_eq!(gas_used, 3);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        // PUSH1 0x2a; PUSH1 0; MSTORE8; PUSH1 1; PUSH1 0; REVERT
+        let code = vec![0x60, 0x2a, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xfd];
+        let mut evm = Evm::new(code, EvmConfig::default());
+        match evm.run() {
+            ExecutionResult::Revert { output, .. } => assert_eq!(output, vec![0x2a]),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn run_reports_exceptional_halt() {
+        let cfg = EvmConfig {
+            gas_limit: 1_000,
+            ..EvmConfig::default()
+        };
+        let mut evm = Evm::new(vec![0x60, 0x01, 0xfe], cfg);
+        match evm.run() {
+            ExecutionResult::Halt { reason, gas_used } => {
+                assert_eq!(reason, EvmError::InvalidOpcode(0xfe, 2));
+                assert_eq!(gas_used, 1_000);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn caller_pays_for_callee_gas_minus_stipend() {
+        let callee = H160::repeat_byte(0xbb);
+        let caller = H160::repeat_byte(0xaa);
+        let run = |value: u8, callee_code: &str| {
+            let mut world = World::default();
+            world.accounts.insert(
+                callee,
+                Account {
+                    code: assemble(callee_code).unwrap(),
+                    ..Account::default()
+                },
+            );
+            world.accounts.insert(
+                caller,
+                Account {
+                    balance: U256::from(10),
+                    ..Account::default()
+                },
+            );
+            // CALL(0xffff, callee, value, 0, 0, 0, 0)
+            let code = assemble(&format!(
+                "PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 {value} PUSH20 {callee:?} PUSH2 0xffff CALL"
+            ))
+            .unwrap();
+            let mut evm = Evm::new(
+                code,
+                EvmConfig {
+                    address: Some(caller),
+                    world: Some(world),
+                    ..EvmConfig::default()
+                },
+            );
+            let result = evm.run();
+            assert_eq!(evm.stack, vec![U256::one()]);
+            result.gas_used()
+        };
+        let sstore = "PUSH1 1 PUSH1 0 SSTORE";
+        // 7 pushes, the call's base cost and 40, plus the callee's 20006
+        assert_eq!(run(0, sstore), 21 + 700 + 40 + 20006);
+        // 2300 of the callee's gas came from the value stipend
+        assert_eq!(run(1, sstore), 21 + 9700 + 40 + 20006 - 2300);
+        // A callee using 5 gives the rest of its stipend back
+        assert_eq!(run(1, "PUSH1 1 POP"), 21 + 9700 + 40 + 5 - 2300);
+    }
+
+    #[test]
+    fn staticcall_charges_base_and_forwards_requested_gas() {
+        let callee = H160::repeat_byte(0xbb);
+        let mut world = World::default();
+        // Return the gas left when the callee starts
+        world.accounts.insert(
+            callee,
+            Account {
+                code: assemble("GAS PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN").unwrap(),
+                ..Account::default()
+            },
+        );
+        // STATICCALL(100, callee, 0, 0, 0, 32); MLOAD(0)
+        let code = assemble(&format!(
+            "PUSH1 32 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 {callee:?} PUSH1 100 STATICCALL PUSH1 0 MLOAD"
+        ))
+        .unwrap();
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                world: Some(world),
+                ..EvmConfig::default()
+            },
+        );
+        let result = evm.run();
+        assert_eq!(evm.stack, vec![U256::one(), U256::from(100)]);
+        // 6 pushes, 32 bytes of memory, 700 base, the callee's 14, 40, PUSH1 and MLOAD
+        assert_eq!(result.gas_used(), 18 + 3 + 700 + 14 + 40 + 6);
+    }
+
+    #[test]
+    fn create_charges_up_front_and_forwards_63_64ths() {
+        // Init code SSTORE(0, GAS) records the gas the new frame started with
+        let code =
+            assemble("PUSH4 0x5a600055 PUSH1 0 MSTORE PUSH1 4 PUSH1 28 PUSH1 0 CREATE").unwrap();
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                gas_limit: 100_000,
+                world: Some(World::default()),
+                ..EvmConfig::default()
+            },
+        );
+        let result = evm.run();
+        let created = u256_to_h160(evm.stack[0]);
+        let forwarded = evm.world.as_ref().unwrap().accounts[&created].storage[&U256::zero()];
+        // 5 pushes and MSTORE come first, then 32000 and 2 for the init code word
+        let left = 100_000 - 18 - 32002;
+        assert_eq!(forwarded, U256::from(left - left / 64));
+        // The init code's 20005 is charged to the creator
+        assert_eq!(result.gas_used(), 18 + 32002 + 20005);
+    }
+
+    #[test]
+    fn create_rejects_code_starting_with_0xef() {
+        // Init code MSTORE8(0, 0xEF) RETURN(0, 1)
+        let code = assemble(
+            "PUSH10 0x60ef60005360016000f3 PUSH1 0 MSTORE PUSH1 10 PUSH1 22 PUSH1 0 CREATE",
+        )
+        .unwrap();
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                gas_limit: 100_000,
+                world: Some(World::default()),
+                ..EvmConfig::default()
+            },
+        );
+        let result = evm.run();
+        assert_eq!(evm.stack, vec![U256::zero()]);
+        // The failed deployment uses all the gas it was given
+        let left = 100_000 - 18 - 32002;
+        assert_eq!(result.gas_used(), 18 + 32002 + left - left / 64);
+        assert_eq!(evm.world.as_ref().unwrap().accounts.len(), 1);
+    }
+
+    #[test]
+    fn calls_and_creates_fail_past_depth_1024() {
+        let create = "PUSH1 0 PUSH1 0 PUSH1 0 CREATE";
+        let mut evm = testutil::evm(
+            testutil::call_callee(0, create),
+            Some(testutil::callee_world("PUSH1 1 PUSH1 0 SSTORE")),
+        );
+        evm.depth = 1024;
+        let result = evm.run();
+        assert_eq!(evm.stack, vec![U256::zero(), U256::zero()]);
+        // Only the call's own costs are paid; the forwarded gas comes back
+        assert_eq!(result.gas_used(), 20 + 700 + 40 + 9 + 32000);
+        // Neither the callee's SSTORE nor the creator's nonce bump happened
+        let world = evm.world.as_ref().unwrap();
+        assert!(world.accounts[&testutil::CALLEE].storage.is_empty());
+        assert!(!world.accounts.contains_key(&testutil::CONTRACT));
+    }
+
+    #[test]
+    fn failed_create_still_bumps_the_nonce() {
+        let creator = H160::repeat_byte(0xaa);
+        // Init code REVERT(0, 0), created twice
+        let create = "PUSH5 0x60006000fd PUSH1 0 MSTORE PUSH1 5 PUSH1 27 PUSH1 0 CREATE";
+        let code = assemble(&format!("{create} {create}")).unwrap();
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                address: Some(creator),
+                world: Some(World::default()),
+                ..EvmConfig::default()
+            },
+        );
+        assert!(evm.run().is_success());
+        assert_eq!(evm.stack, vec![U256::zero(), U256::zero()]);
+        assert_eq!(evm.world.as_ref().unwrap().accounts[&creator].nonce, 2);
+    }
+
+    #[test]
+    fn selfdestruct_moves_the_balance_for_5000_gas() {
+        let contract = H160::repeat_byte(0xaa);
+        let beneficiary = H160::repeat_byte(0xbb);
+        let mut world = World::default();
+        world.accounts.insert(
+            contract,
+            Account {
+                balance: U256::from(7),
+                ..Account::default()
+            },
+        );
+        let code = assemble(&format!("PUSH20 {beneficiary:?} SELFDESTRUCT PUSH1 1")).unwrap();
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                address: Some(contract),
+                world: Some(world),
+                ..EvmConfig::default()
+            },
+        );
+        let result = evm.run();
+        assert!(result.is_success());
+        assert_eq!(result.gas_used(), 3 + 5000);
+        // Execution stops; the account and its code stay
         assert!(evm.stack.is_empty());
+        let accounts = &evm.world.as_ref().unwrap().accounts;
+        assert_eq!(accounts[&contract].balance, U256::zero());
+        assert_eq!(accounts[&beneficiary].balance, U256::from(7));
+        assert!(evm.touched.contains(&beneficiary));
+    }
+
+    #[test]
+    fn staticcall_rejects_a_nested_value_call() {
+        let outer = H160::repeat_byte(0xaa);
+        let inner = H160::repeat_byte(0xbb);
+        let mut world = World::default();
+        // CALL(GAS, inner, 1, 0, 0, 0, 0)
+        world.accounts.insert(
+            outer,
+            Account {
+                balance: U256::from(5),
+                code: assemble(&format!(
+                    "PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 1 PUSH20 {inner:?} GAS CALL"
+                ))
+                .unwrap(),
+                ..Account::default()
+            },
+        );
+        let code = assemble(&format!(
+            "PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 {outer:?} PUSH2 0xffff STATICCALL"
+        ))
+        .unwrap();
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                world: Some(world),
+                ..EvmConfig::default()
+            },
+        );
+        assert!(evm.run().is_success());
+        // The outer frame halted, so the STATICCALL failed and no value moved
+        assert_eq!(evm.stack, vec![U256::zero()]);
+        let accounts = &evm.world.as_ref().unwrap().accounts;
+        assert_eq!(accounts[&outer].balance, U256::from(5));
+        assert!(!accounts.contains_key(&inner));
+    }
+
+    #[test]
+    fn call_gas_operand_above_u128_forwards_all_gas() {
+        // STATICCALL(not(0), 0x04, 0, 0, 0, 0)
+        let code = assemble(&format!(
+            "PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 4 PUSH32 0x{} STATICCALL",
+            "ff".repeat(32)
+        ))
+        .unwrap();
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                world: Some(World::default()),
+                ..EvmConfig::default()
+            },
+        );
+        assert!(evm.run().is_success());
+        assert_eq!(evm.stack, vec![U256::one()]);
+    }
+
+    #[test]
+    fn static_context_reaches_nested_calls() {
+        let outer = H160::repeat_byte(0xaa);
+        let inner = H160::repeat_byte(0xbb);
+        let mut world = World::default();
+        world.accounts.insert(
+            inner,
+            Account {
+                code: assemble("PUSH1 1 PUSH1 0 SSTORE").unwrap(),
+                ..Account::default()
+            },
+        );
+        // Return whether CALL(0xffff, inner, 0, 0, 0, 0, 0) succeeded
+        world.accounts.insert(
+            outer,
+            Account {
+                code: assemble(&format!(
+                    "PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 {inner:?} PUSH2 0xffff CALL
+                     PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN"
+                ))
+                .unwrap(),
+                ..Account::default()
+            },
+        );
+        let code = assemble(&format!(
+            "PUSH1 32 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 {outer:?} PUSH2 0xffff STATICCALL PUSH1 0 MLOAD"
+        ))
+        .unwrap();
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                world: Some(world),
+                ..EvmConfig::default()
+            },
+        );
+        assert!(evm.run().is_success());
+        // The outer frame returned, but its SSTORE-ing CALL failed
+        assert_eq!(evm.stack, vec![U256::one(), U256::zero()]);
+        let accounts = &evm.world.as_ref().unwrap().accounts;
+        assert!(accounts[&inner].storage.is_empty());
     }
 }
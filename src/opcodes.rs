@@ -6,15 +6,28 @@ pub const ADD: u8 = 0x01;
 pub const MUL: u8 = 0x02;
 pub const SUB: u8 = 0x03;
 pub const DIV: u8 = 0x04;
+pub const SDIV: u8 = 0x05;
+pub const MOD: u8 = 0x06;
+pub const SMOD: u8 = 0x07;
+pub const ADDMOD: u8 = 0x08;
+pub const MULMOD: u8 = 0x09;
+pub const EXP: u8 = 0x0A;
+pub const SIGNEXTEND: u8 = 0x0B;
 // logical/bitwise
 pub const LT: u8 = 0x10;
 pub const GT: u8 = 0x11;
+pub const SLT: u8 = 0x12;
+pub const SGT: u8 = 0x13;
 pub const EQ: u8 = 0x14;
 pub const ISZERO: u8 = 0x15;
 pub const AND: u8 = 0x16;
 pub const OR: u8 = 0x17;
 pub const XOR: u8 = 0x18;
 pub const NOT: u8 = 0x19;
+pub const BYTE: u8 = 0x1A;
+pub const SHL: u8 = 0x1B;
+pub const SHR: u8 = 0x1C;
+pub const SAR: u8 = 0x1D;
 // SHA3
 pub const SHA3: u8 = 0x20;
 
@@ -46,6 +59,8 @@ pub const GASLIMIT_OP: u8 = 0x45; // avoid gaslimit name clash
 pub const CHAINID: u8 = 0x46;
 pub const SELFBALANCE: u8 = 0x47;
 pub const BASEFEE: u8 = 0x48;
+pub const BLOBHASH: u8 = 0x49; // Cancun
+pub const BLOBBASEFEE: u8 = 0x4A; // Cancun
 
 // 0x50 range - stack/memory/storage/flow
 pub const POP: u8 = 0x50;
@@ -60,6 +75,9 @@ pub const PC: u8 = 0x58;
 pub const MSIZE: u8 = 0x59;
 pub const GAS: u8 = 0x5A;
 pub const JUMPDEST: u8 = 0x5B;
+pub const TLOAD: u8 = 0x5C; // Cancun
+pub const TSTORE: u8 = 0x5D; // Cancun
+pub const MCOPY: u8 = 0x5E; // Cancun
 pub const PUSH0: u8 = 0x5F; // Shanghai
 
 // 0x60..0x7f - PUSH1..PUSH32
@@ -77,6 +95,7 @@ pub const SWAP16: u8 = 0x9F;
 // 0xf0.. returns
 pub const RETURN: u8 = 0xF3;
 pub const REVERT: u8 = 0xFD;
+pub const INVALID: u8 = 0xFE;
 
 // 0xf0.. calls/create (subset)
 pub const CREATE: u8 = 0xF0;
@@ -85,6 +104,7 @@ pub const CALLCODE: u8 = 0xF2;
 pub const STATICCALL: u8 = 0xFA;
 pub const DELEGATECALL: u8 = 0xF4;
 pub const CREATE2: u8 = 0xF5;
+pub const SELFDESTRUCT: u8 = 0xFF;
 // CREATE/CREATE2 could be added later
 
 // logs
@@ -93,3 +113,145 @@ pub const LOG1: u8 = 0xA1;
 pub const LOG2: u8 = 0xA2;
 pub const LOG3: u8 = 0xA3;
 pub const LOG4: u8 = 0xA4;
+
+// Mnemonics for every byte value; empty for undefined opcodes. Includes opcodes
+// this EVM does not implement so traces and disassembly can still name them.
+#[rustfmt::skip]
+const NAMES: [&str; 256] = [
+    "STOP", "ADD", "MUL", "SUB", "DIV", "SDIV", "MOD", "SMOD",
+    "ADDMOD", "MULMOD", "EXP", "SIGNEXTEND", "", "", "", "",
+    "LT", "GT", "SLT", "SGT", "EQ", "ISZERO", "AND", "OR",
+    "XOR", "NOT", "BYTE", "SHL", "SHR", "SAR", "", "",
+    "SHA3", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "ADDRESS", "BALANCE", "ORIGIN", "CALLER", "CALLVALUE", "CALLDATALOAD", "CALLDATASIZE", "CALLDATACOPY",
+    "CODESIZE", "CODECOPY", "GASPRICE", "EXTCODESIZE", "EXTCODECOPY", "RETURNDATASIZE", "RETURNDATACOPY", "EXTCODEHASH",
+    "BLOCKHASH", "COINBASE", "TIMESTAMP", "NUMBER", "PREVRANDAO", "GASLIMIT", "CHAINID", "SELFBALANCE",
+    "BASEFEE", "BLOBHASH", "BLOBBASEFEE", "", "", "", "", "",
+    "POP", "MLOAD", "MSTORE", "MSTORE8", "SLOAD", "SSTORE", "JUMP", "JUMPI",
+    "PC", "MSIZE", "GAS", "JUMPDEST", "TLOAD", "TSTORE", "MCOPY", "PUSH0",
+    "PUSH1", "PUSH2", "PUSH3", "PUSH4", "PUSH5", "PUSH6", "PUSH7", "PUSH8",
+    "PUSH9", "PUSH10", "PUSH11", "PUSH12", "PUSH13", "PUSH14", "PUSH15", "PUSH16",
+    "PUSH17", "PUSH18", "PUSH19", "PUSH20", "PUSH21", "PUSH22", "PUSH23", "PUSH24",
+    "PUSH25", "PUSH26", "PUSH27", "PUSH28", "PUSH29", "PUSH30", "PUSH31", "PUSH32",
+    "DUP1", "DUP2", "DUP3", "DUP4", "DUP5", "DUP6", "DUP7", "DUP8",
+    "DUP9", "DUP10", "DUP11", "DUP12", "DUP13", "DUP14", "DUP15", "DUP16",
+    "SWAP1", "SWAP2", "SWAP3", "SWAP4", "SWAP5", "SWAP6", "SWAP7", "SWAP8",
+    "SWAP9", "SWAP10", "SWAP11", "SWAP12", "SWAP13", "SWAP14", "SWAP15", "SWAP16",
+    "LOG0", "LOG1", "LOG2", "LOG3", "LOG4", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+    "CREATE", "CALL", "CALLCODE", "RETURN", "DELEGATECALL", "CREATE2", "", "",
+    "", "", "STATICCALL", "", "", "REVERT", "INVALID", "SELFDESTRUCT",
+];
+
+pub fn opcode_name(op: u8) -> &'static str {
+    match NAMES[op as usize] {
+        "" => "INVALID",
+        name => name,
+    }
+}
+
+// Case-insensitive inverse of `opcode_name`; KECCAK256 is accepted for SHA3
+pub fn opcode_by_name(name: &str) -> Option<u8> {
+    let name = name.to_ascii_uppercase();
+    let name = if name == "KECCAK256" { "SHA3" } else { &name };
+    NAMES.iter().position(|n| *n == name).map(|i| i as u8)
+}
+
+// (items popped, items pushed); undefined opcodes take and leave nothing
+pub fn stack_effect(op: u8) -> (usize, usize) {
+    match op {
+        ADD..=SMOD | EXP | SIGNEXTEND | LT..=EQ | AND..=XOR | BYTE..=SAR | SHA3 => (2, 1),
+        ADDMOD | MULMOD => (3, 1),
+        ISZERO | NOT | BALANCE | CALLDATALOAD | EXTCODESIZE | EXTCODEHASH | BLOCKHASH
+        | BLOBHASH | MLOAD | SLOAD | TLOAD => (1, 1),
+        ADDRESS
+        | ORIGIN
+        | CALLER
+        | CALLVALUE
+        | CALLDATASIZE
+        | CODESIZE
+        | GASPRICE
+        | RETURNDATASIZE
+        | COINBASE..=BASEFEE
+        | BLOBBASEFEE
+        | PC
+        | MSIZE
+        | GAS
+        | PUSH0..=PUSH32 => (0, 1),
+        CALLDATACOPY | CODECOPY | RETURNDATACOPY | MCOPY => (3, 0),
+        EXTCODECOPY => (4, 0),
+        POP | JUMP | SELFDESTRUCT => (1, 0),
+        MSTORE | MSTORE8 | SSTORE | JUMPI | TSTORE | RETURN | REVERT => (2, 0),
+        DUP1..=DUP16 => {
+            let n = (op - DUP1 + 1) as usize;
+            (n, n + 1)
+        }
+        SWAP1..=SWAP16 => {
+            let n = (op - SWAP1 + 2) as usize;
+            (n, n)
+        }
+        LOG0..=LOG4 => ((op - LOG0 + 2) as usize, 0),
+        CREATE => (3, 1),
+        CREATE2 => (4, 1),
+        CALL | CALLCODE => (7, 1),
+        DELEGATECALL | STATICCALL => (6, 1),
+        _ => (0, 0),
+    }
+}
+
+// Fixed part of an opcode's gas cost per the current fork, and whether memory
+// expansion, access lists or the operands add to it at runtime. The
+// interpreter's own charges are simpler; see machine.rs.
+pub fn static_gas(op: u8) -> (u64, bool) {
+    match op {
+        JUMPDEST => (1, false),
+        ADDRESS
+        | ORIGIN
+        | CALLER
+        | CALLVALUE
+        | CALLDATASIZE
+        | CODESIZE
+        | GASPRICE
+        | RETURNDATASIZE
+        | COINBASE..=CHAINID
+        | BASEFEE
+        | BLOBBASEFEE
+        | POP
+        | PC
+        | MSIZE
+        | GAS
+        | PUSH0 => (2, false),
+        ADD
+        | SUB
+        | LT..=SAR
+        | CALLDATALOAD
+        | BLOBHASH
+        | PUSH1..=PUSH32
+        | DUP1..=DUP16
+        | SWAP1..=SWAP16 => (3, false),
+        MUL | DIV..=SMOD | SIGNEXTEND | SELFBALANCE => (5, false),
+        ADDMOD | MULMOD | JUMP => (8, false),
+        JUMPI => (10, false),
+        BLOCKHASH => (20, false),
+        TLOAD | TSTORE => (100, false),
+        MLOAD | MSTORE | MSTORE8 | CALLDATACOPY | CODECOPY | RETURNDATACOPY | MCOPY => (3, true),
+        EXP => (10, true),
+        SHA3 => (30, true),
+        BALANCE | EXTCODESIZE | EXTCODECOPY | EXTCODEHASH | SLOAD | SSTORE | CALL | CALLCODE
+        | DELEGATECALL | STATICCALL => (100, true),
+        LOG0..=LOG4 => (375 * (op - LOG0 + 1) as u64, true),
+        CREATE | CREATE2 => (32000, true),
+        SELFDESTRUCT => (5000, true),
+        RETURN | REVERT => (0, true),
+        _ => (0, false),
+    }
+}
@@ -0,0 +1,321 @@
+// Pre-state and state-diff tracing in the shape of geth's `prestateTracer`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use primitive_types::{H160, U256};
+use serde_json::{json, Map, Value};
+
+use crate::hexutil::hex;
+use crate::inspector::{CallInputs, CreateInputs, CreateOutcome, Inspector};
+use crate::machine::{Account, Evm, ExecutionResult, World};
+use crate::opcodes::{BALANCE, EXTCODECOPY, EXTCODEHASH, EXTCODESIZE, SLOAD, SSTORE};
+use crate::trie::u256_bytes;
+
+// One account in a prestate or diff. Fields left as None are omitted from the JSON.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrestateAccount {
+    pub balance: Option<U256>,
+    pub nonce: Option<u64>,
+    pub code: Option<Vec<u8>>,
+    pub storage: BTreeMap<U256, U256>,
+}
+
+impl PrestateAccount {
+    // The full account, with only the given storage slots
+    fn snapshot(acc: &Account, slots: impl Iterator<Item = U256>) -> Self {
+        Self {
+            balance: Some(acc.balance),
+            nonce: Some(acc.nonce),
+            code: Some(acc.code.clone()),
+            storage: slots.map(|k| (k, slot(acc, k))).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut v = json!({});
+        if let Some(balance) = self.balance {
+            v["balance"] = format!("0x{:x}", balance).into();
+        }
+        if let Some(nonce) = self.nonce.filter(|n| *n > 0) {
+            v["nonce"] = nonce.into();
+        }
+        if let Some(code) = self.code.as_ref().filter(|c| !c.is_empty()) {
+            v["code"] = format!("0x{}", hex(code)).into();
+        }
+        if !self.storage.is_empty() {
+            let storage: Map<String, Value> = self
+                .storage
+                .iter()
+                .map(|(k, v)| (word(*k), word(*v).into()))
+                .collect();
+            v["storage"] = storage.into();
+        }
+        v
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub pre: BTreeMap<H160, PrestateAccount>,
+    pub post: BTreeMap<H160, PrestateAccount>,
+}
+
+impl StateDiff {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "pre": accounts_json(&self.pre),
+            "post": accounts_json(&self.post),
+        })
+    }
+}
+
+pub fn accounts_json(accounts: &BTreeMap<H160, PrestateAccount>) -> Value {
+    let map: Map<String, Value> = accounts
+        .iter()
+        .map(|(addr, acc)| (format!("0x{}", hex(addr.as_bytes())), acc.to_json()))
+        .collect();
+    map.into()
+}
+
+fn word(v: U256) -> String {
+    format!("0x{}", hex(&u256_bytes(v)))
+}
+
+fn slot(acc: &Account, key: U256) -> U256 {
+    acc.storage.get(&key).copied().unwrap_or_default()
+}
+
+// Records which accounts and storage slots execution touched. The states
+// themselves are read afterwards from the world before and after the run.
+#[derive(Debug, Clone, Default)]
+pub struct PrestateTracer {
+    pub touched: BTreeMap<H160, BTreeSet<U256>>,
+    pub created: BTreeSet<H160>,
+}
+
+impl PrestateTracer {
+    // Starts with the caller and address of the outermost frame
+    pub fn new(evm: &Evm) -> Self {
+        let mut tracer = Self::default();
+        for addr in [evm.caller, evm.address].into_iter().flatten() {
+            tracer.touch(addr);
+        }
+        tracer
+    }
+
+    pub fn touch(&mut self, addr: H160) {
+        self.touched.entry(addr).or_default();
+    }
+
+    pub fn touch_slot(&mut self, addr: H160, key: U256) {
+        self.touched.entry(addr).or_default().insert(key);
+    }
+
+    // Touched accounts that existed before the run, with the touched slots
+    pub fn prestate(&self, pre: &World) -> BTreeMap<H160, PrestateAccount> {
+        self.touched
+            .iter()
+            .filter_map(|(addr, slots)| {
+                let acc = pre.accounts.get(addr)?;
+                Some((*addr, PrestateAccount::snapshot(acc, slots.iter().copied())))
+            })
+            .collect()
+    }
+
+    // Only what changed: `pre` has the old account with the changed slots and
+    // `post` the fields that differ. Created accounts are only in `post`,
+    // deleted ones only in `pre`. A run that did not succeed changed nothing,
+    // whatever was left in `post`.
+    pub fn diff(&self, pre: &World, post: &World, result: &ExecutionResult) -> StateDiff {
+        let mut diff = StateDiff::default();
+        let post = if result.is_success() { post } else { pre };
+        for (addr, slots) in &self.touched {
+            // A contract created over an empty account had no real pre-state
+            let before = pre
+                .accounts
+                .get(addr)
+                .filter(|a| !(self.created.contains(addr) && a.is_empty()));
+            match (before, post.accounts.get(addr)) {
+                (None, None) => {}
+                (Some(old), None) => {
+                    let acc = PrestateAccount::snapshot(old, slots.iter().copied());
+                    diff.pre.insert(*addr, acc);
+                }
+                (None, Some(new)) => {
+                    let mut acc = PrestateAccount::snapshot(new, slots.iter().copied());
+                    acc.storage.retain(|_, v| !v.is_zero());
+                    diff.post.insert(*addr, acc);
+                }
+                (Some(old), Some(new)) => {
+                    let changed: Vec<U256> = slots
+                        .iter()
+                        .copied()
+                        .filter(|k| slot(old, *k) != slot(new, *k))
+                        .collect();
+                    let after = PrestateAccount {
+                        balance: (old.balance != new.balance).then_some(new.balance),
+                        nonce: (old.nonce != new.nonce).then_some(new.nonce),
+                        code: (old.code != new.code).then(|| new.code.clone()),
+                        storage: changed
+                            .iter()
+                            .map(|k| (*k, slot(new, *k)))
+                            .filter(|(_, v)| !v.is_zero())
+                            .collect(),
+                    };
+                    let modified = after.balance.is_some()
+                        || after.nonce.is_some()
+                        || after.code.is_some()
+                        || !changed.is_empty();
+                    if modified {
+                        diff.pre
+                            .insert(*addr, PrestateAccount::snapshot(old, changed.into_iter()));
+                        diff.post.insert(*addr, after);
+                    }
+                }
+            }
+        }
+        diff
+    }
+}
+
+impl Inspector for PrestateTracer {
+    fn step(&mut self, evm: &Evm) {
+        let op = evm.code.get(evm.pc).copied().unwrap_or(0);
+        let Some(top) = evm.stack.last().copied() else {
+            return;
+        };
+        match op {
+            SLOAD | SSTORE => {
+                if let Some(addr) = evm.address {
+                    self.touch_slot(addr, top);
+                }
+            }
+            BALANCE | EXTCODESIZE | EXTCODECOPY | EXTCODEHASH => {
+                self.touch(H160::from_slice(&u256_bytes(top)[12..]));
+            }
+            _ => {}
+        }
+    }
+
+    fn call(&mut self, _evm: &Evm, inputs: &CallInputs) -> Option<ExecutionResult> {
+        self.touch(inputs.target);
+        self.touch(inputs.code_address);
+        None
+    }
+
+    fn create_end(&mut self, _evm: &Evm, _inputs: &CreateInputs, outcome: &mut CreateOutcome) {
+        if let Some(addr) = outcome.address {
+            self.touch(addr);
+            self.created.insert(addr);
+        }
+    }
+
+    fn selfdestruct(&mut self, _evm: &Evm, address: H160, beneficiary: H160, _value: U256) {
+        self.touch(address);
+        self.touch(beneficiary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::EvmConfig;
+
+    const CONTRACT: [u8; 20] = [0xaa; 20];
+    const OTHER: [u8; 20] = [0xbb; 20];
+
+    fn run() -> (World, World, PrestateTracer, ExecutionResult) {
+        run_code(vec![0x00])
+    }
+
+    // Runs the fixture, then `tail`
+    fn run_code(tail: Vec<u8>) -> (World, World, PrestateTracer, ExecutionResult) {
+        let mut pre = World::default();
+        let mut acc = Account {
+            nonce: 1,
+            balance: U256::from(7),
+            ..Account::default()
+        };
+        acc.storage.insert(U256::from(1), U256::from(5));
+        acc.storage.insert(U256::from(2), U256::from(9));
+        pre.accounts.insert(H160(CONTRACT), acc);
+        pre.accounts.insert(
+            H160(OTHER),
+            Account {
+                balance: U256::from(3),
+                ..Account::default()
+            },
+        );
+        // SLOAD 1; SSTORE(2, 0x2a); SSTORE(3, 0); BALANCE 0xbb..bb
+        let mut code = vec![0x60, 0x01, 0x54, 0x60, 0x2a, 0x60, 0x02, 0x55];
+        code.extend_from_slice(&[0x60, 0x00, 0x60, 0x03, 0x55, 0x73]);
+        code.extend_from_slice(&OTHER);
+        code.push(0x31);
+        code.extend(tail);
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                address: Some(H160(CONTRACT)),
+                world: Some(pre.clone()),
+                ..EvmConfig::default()
+            },
+        );
+        let mut tracer = PrestateTracer::new(&evm);
+        let result = evm.run_with(&mut tracer);
+        (pre, evm.world.unwrap(), tracer, result)
+    }
+
+    #[test]
+    fn records_touched_prestate() {
+        let (pre, _, tracer, _) = run();
+        let state = tracer.prestate(&pre);
+        assert_eq!(state.len(), 2);
+        let acc = &state[&H160(CONTRACT)];
+        assert_eq!(acc.balance, Some(U256::from(7)));
+        let slots: Vec<_> = acc
+            .storage
+            .iter()
+            .map(|(k, v)| (k.as_u64(), v.as_u64()))
+            .collect();
+        assert_eq!(slots, [(1, 5), (2, 9), (3, 0)]);
+        let json = accounts_json(&state);
+        let other = &json["0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"];
+        assert_eq!(other, &json!({"balance": "0x3"}));
+    }
+
+    #[test]
+    fn diff_keeps_only_changes() {
+        let (pre, post, tracer, result) = run();
+        assert!(result.is_success());
+        let diff = tracer.diff(&pre, &post, &result);
+        // OTHER was only read
+        assert_eq!(diff.pre.len(), 1);
+        assert_eq!(diff.post.len(), 1);
+        let before = &diff.pre[&H160(CONTRACT)];
+        assert_eq!(before.nonce, Some(1));
+        assert_eq!(before.storage.len(), 1);
+        assert_eq!(before.storage[&U256::from(2)], U256::from(9));
+        let after = &diff.post[&H160(CONTRACT)];
+        assert_eq!(after.balance, None);
+        assert_eq!(after.storage[&U256::from(2)], U256::from(0x2a));
+        let json = diff.to_json();
+        assert_eq!(
+            json["post"]["0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"],
+            json!({"storage": {
+                "0x0000000000000000000000000000000000000000000000000000000000000002":
+                    "0x000000000000000000000000000000000000000000000000000000000000002a"
+            }})
+        );
+    }
+
+    #[test]
+    fn reverted_run_has_an_empty_diff() {
+        // REVERT(0, 0) after the same writes
+        let (pre, post, tracer, result) = run_code(vec![0x60, 0x00, 0x80, 0xfd]);
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+        let diff = tracer.diff(&pre, &post, &result);
+        assert_eq!(diff, StateDiff::default());
+        // The prestate still lists what was touched
+        assert_eq!(tracer.prestate(&pre).len(), 2);
+    }
+}
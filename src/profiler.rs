@@ -0,0 +1,302 @@
+// Gas profiler: attributes every unit of gas used to an opcode, a program
+// counter and a call frame.
+//
+// A CALL/CREATE step is charged only its own cost (base, memory expansion,
+// value transfer); the gas its child frame used is attributed to the child's
+// instructions. The per-opcode totals therefore add up to the gas used, less
+// what callees ran on the 2300 stipend of a value call, which the caller
+// does not pay for.
+
+use std::collections::BTreeMap;
+
+use primitive_types::H160;
+
+use crate::hexutil::hex;
+use crate::inspector::{
+    CallInputs, CallScheme, CreateInputs, CreateOutcome, CreateScheme, Inspector,
+};
+use crate::machine::{Evm, EvmError, ExecutionResult};
+use crate::opcodes::opcode_name;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GasStat {
+    pub count: u64,
+    pub gas: u64,
+}
+
+impl GasStat {
+    fn add(&mut self, gas: u64) {
+        self.count += 1;
+        self.gas += gas;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameGas {
+    pub parent: Option<usize>,
+    pub depth: usize,
+    // CALL, STATICCALL, DELEGATECALL, CALLCODE, CREATE or CREATE2
+    pub kind: &'static str,
+    // Address whose code ran; None for a creation that failed early
+    pub address: Option<H160>,
+    // Gas of the frame's own instructions
+    pub self_gas: u64,
+    // Including every frame below it
+    pub total_gas: u64,
+}
+
+impl FrameGas {
+    pub fn label(&self) -> String {
+        match self.address {
+            Some(addr) => format!("{} 0x{}", self.kind, hex(addr.as_bytes())),
+            None => self.kind.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingStep {
+    pc: usize,
+    op: u8,
+    gas: u64,
+    // What the parent was charged for its children; negative if a callee left
+    // part of its stipend
+    child_gas: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GasProfiler {
+    pub opcodes: BTreeMap<u8, GasStat>,
+    // Keyed by the address of the code and the pc within it
+    pub pcs: BTreeMap<(Option<H160>, usize), (u8, GasStat)>,
+    pub frames: Vec<FrameGas>,
+    // "frame;frame;OPCODE" paths and their gas, for flamegraphs
+    pub folded: BTreeMap<String, u64>,
+    // Indices into `frames` of the frames currently running
+    active: Vec<usize>,
+    pending: Vec<PendingStep>,
+}
+
+impl GasProfiler {
+    // Starts with the outermost frame of `evm`
+    pub fn new(evm: &Evm) -> Self {
+        let mut profiler = Self::default();
+        profiler.enter("CALL", evm.address);
+        profiler
+    }
+
+    // Gas used by the whole execution
+    pub fn total_gas(&self) -> u64 {
+        self.frames.first().map(|f| f.total_gas).unwrap_or(0)
+    }
+
+    // "stack gas" lines in the folded format read by flamegraph.pl and inferno
+    pub fn folded_lines(&self) -> Vec<String> {
+        self.folded
+            .iter()
+            .filter(|(_, gas)| **gas > 0)
+            .map(|(path, gas)| format!("{path} {gas}"))
+            .collect()
+    }
+
+    fn enter(&mut self, kind: &'static str, address: Option<H160>) {
+        self.frames.push(FrameGas {
+            parent: self.active.last().copied(),
+            depth: self.active.len(),
+            kind,
+            address,
+            self_gas: 0,
+            total_gas: 0,
+        });
+        self.active.push(self.frames.len() - 1);
+    }
+
+    // `stipend` is the part of the child's gas the parent got for free
+    fn exit(&mut self, address: Option<H160>, stipend: u64) {
+        if self.active.len() < 2 {
+            return;
+        }
+        let idx = self.active.pop().unwrap();
+        if address.is_some() {
+            self.frames[idx].address = address;
+        }
+        // What the parent was charged, as in `Evm::call_with`; it counts towards the
+        // parent's total with the CALL/CREATE step, but not towards that step's own gas
+        let gas = self.frames[idx].total_gas as i64 - stipend as i64;
+        if let Some(step) = self.pending.last_mut() {
+            step.child_gas += gas;
+        }
+    }
+}
+
+impl Inspector for GasProfiler {
+    fn step(&mut self, evm: &Evm) {
+        self.pending.push(PendingStep {
+            pc: evm.pc,
+            op: evm.code.get(evm.pc).copied().unwrap_or(0),
+            gas: evm.gas.max(0) as u64,
+            child_gas: 0,
+        });
+    }
+
+    fn step_end(&mut self, evm: &Evm, error: Option<&EvmError>) {
+        let Some(step) = self.pending.pop() else {
+            return;
+        };
+        // An exceptional halt consumes everything the frame had left
+        let used = match error {
+            Some(_) => step.gas,
+            None => step.gas.saturating_sub(evm.gas.max(0) as u64),
+        };
+        let gas = (used as i64 - step.child_gas).max(0) as u64;
+        let idx = *self.active.last().unwrap();
+        let frame = &mut self.frames[idx];
+        frame.self_gas += gas;
+        frame.total_gas += used;
+        let address = frame.address;
+        self.opcodes.entry(step.op).or_default().add(gas);
+        self.pcs
+            .entry((address, step.pc))
+            .or_insert((step.op, GasStat::default()))
+            .1
+            .add(gas);
+        let mut path: Vec<String> = self
+            .active
+            .iter()
+            .map(|i| self.frames[*i].label())
+            .collect();
+        path.push(opcode_name(step.op).to_string());
+        *self.folded.entry(path.join(";")).or_default() += gas;
+    }
+
+    fn call(&mut self, _evm: &Evm, inputs: &CallInputs) -> Option<ExecutionResult> {
+        let kind = match inputs.scheme {
+            CallScheme::Call => "CALL",
+            CallScheme::StaticCall => "STATICCALL",
+            CallScheme::CallCode => "CALLCODE",
+            CallScheme::DelegateCall => "DELEGATECALL",
+        };
+        self.enter(kind, Some(inputs.code_address));
+        None
+    }
+
+    fn call_end(&mut self, _evm: &Evm, inputs: &CallInputs, _result: &mut ExecutionResult) {
+        let transfers = matches!(inputs.scheme, CallScheme::Call | CallScheme::CallCode);
+        let stipend = if transfers && !inputs.value.is_zero() {
+            2300
+        } else {
+            0
+        };
+        self.exit(None, stipend);
+    }
+
+    fn create(&mut self, _evm: &Evm, inputs: &CreateInputs) -> Option<CreateOutcome> {
+        let kind = match inputs.scheme {
+            CreateScheme::Create => "CREATE",
+            CreateScheme::Create2 { .. } => "CREATE2",
+        };
+        self.enter(kind, None);
+        None
+    }
+
+    fn create_end(&mut self, _evm: &Evm, _inputs: &CreateInputs, outcome: &mut CreateOutcome) {
+        self.exit(outcome.address, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use primitive_types::U256;
+
+    use super::*;
+    use crate::asm::assemble;
+    use crate::machine::{Account, EvmConfig, World};
+    use crate::opcodes::CALL;
+    use crate::testutil::{call_callee, callee_world, CALLEE};
+
+    fn run(code: Vec<u8>, world: Option<World>, gas_limit: i128) -> (GasProfiler, ExecutionResult) {
+        let mut evm = Evm::new(
+            code,
+            EvmConfig {
+                gas_limit,
+                address: Some(H160::repeat_byte(0xaa)),
+                world,
+                ..EvmConfig::default()
+            },
+        );
+        let mut profiler = GasProfiler::new(&evm);
+        let result = evm.run_with(&mut profiler);
+        (profiler, result)
+    }
+
+    #[test]
+    fn opcode_totals_add_up_across_frames() {
+        let world = callee_world("PUSH1 1 PUSH1 0 MSTORE STOP");
+        let code = call_callee(0, "STOP");
+        let (profiler, result) = run(code, Some(world), 100_000);
+        let total: u64 = profiler.opcodes.values().map(|s| s.gas).sum();
+        assert_eq!(total, result.gas_used());
+        assert_eq!(profiler.total_gas(), result.gas_used());
+        assert_eq!(profiler.frames.len(), 2);
+        let child = &profiler.frames[1];
+        assert_eq!(child.parent, Some(0));
+        assert_eq!(child.address, Some(CALLEE));
+        // PUSH1, PUSH1, MSTORE
+        assert_eq!(child.total_gas, 9);
+        assert_eq!(child.self_gas, 9);
+        assert_eq!(profiler.frames[0].self_gas, result.gas_used() - 9);
+        assert_eq!(profiler.opcodes[&0x52].gas, 3);
+        let folded = profiler.folded_lines();
+        assert!(folded.contains(&format!(
+            "CALL 0x{};CALL 0x{};MSTORE 3",
+            "aa".repeat(20),
+            "bb".repeat(20)
+        )));
+    }
+
+    #[test]
+    fn value_call_leaves_the_stipend_out_of_the_caller() {
+        let caller = H160::repeat_byte(0xaa);
+        let callee = H160::repeat_byte(0xbb);
+        // SSTORE(0, 1) uses more than the stipend, PUSH1 1 POP less
+        for (callee_code, callee_gas) in [("PUSH1 1 PUSH1 0 SSTORE", 20006), ("PUSH1 1 POP", 5)] {
+            let mut world = World::default();
+            world.accounts.insert(
+                callee,
+                Account {
+                    code: assemble(callee_code).unwrap(),
+                    ..Account::default()
+                },
+            );
+            world.accounts.insert(
+                caller,
+                Account {
+                    balance: U256::one(),
+                    ..Account::default()
+                },
+            );
+            let code = assemble(&format!(
+                "PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 1 PUSH20 {callee:?} PUSH2 0xffff CALL"
+            ))
+            .unwrap();
+            let (profiler, result) = run(code, Some(world), 100_000);
+            // The CALL step keeps its own cost: 9700 base and 40
+            assert_eq!(profiler.opcodes[&CALL].gas, 9740);
+            let (root, child) = (&profiler.frames[0], &profiler.frames[1]);
+            assert_eq!(child.total_gas, callee_gas);
+            assert_eq!(root.self_gas, 21 + 9740);
+            // The caller pays for all but the 2300 stipend of its callee's gas
+            assert_eq!(root.total_gas, root.self_gas + child.total_gas - 2300);
+            assert_eq!(profiler.total_gas(), result.gas_used());
+        }
+    }
+
+    #[test]
+    fn halting_step_takes_remaining_gas() {
+        // PUSH1 1; INVALID
+        let (profiler, result) = run(vec![0x60, 0x01, 0xfe], None, 1_000);
+        assert_eq!(result.gas_used(), 1_000);
+        assert_eq!(profiler.opcodes[&0xfe].gas, 997);
+        assert_eq!(profiler.pcs[&(Some(H160::repeat_byte(0xaa)), 2)].1.gas, 997);
+    }
+}
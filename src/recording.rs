@@ -0,0 +1,374 @@
+// Execution recording for time-travel debugging. Each step stores only what
+// it changed; the state before any step is rebuilt by replaying the deltas of
+// that step's frame, without executing anything again.
+
+use primitive_types::{H160, U256};
+
+use crate::inspector::{CallInputs, CreateInputs, CreateOutcome, Inspector};
+use crate::machine::{Evm, EvmError, ExecutionResult, Halt};
+use crate::opcodes::SSTORE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub offset: usize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageWrite {
+    pub address: H160,
+    pub slot: U256,
+    pub old: U256,
+    pub new: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepRecord {
+    // Index into `Recording::frames`
+    pub frame: usize,
+    pub pc: usize,
+    pub op: u8,
+    // Gas left before the step, and what it cost (including any child frame)
+    pub gas: u64,
+    pub gas_cost: u64,
+    // Stack items removed from the top, then the ones pushed, bottom first
+    pub popped: Vec<U256>,
+    pub pushed: Vec<U256>,
+    // Changed bytes, and the memory size after the step
+    pub memory: Option<MemoryWrite>,
+    pub memory_size: usize,
+    pub storage: Option<StorageWrite>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameRecord {
+    pub parent: Option<usize>,
+    pub depth: usize,
+    pub address: Option<H160>,
+    pub code: Vec<u8>,
+    // Index of the first step after the frame returned
+    pub end: Option<usize>,
+    // The frame failed, so its storage writes were rolled back
+    pub reverted: bool,
+}
+
+// Interpreter state just before a recorded step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepState {
+    pub step: usize,
+    pub frame: usize,
+    pub depth: usize,
+    pub address: Option<H160>,
+    pub pc: usize,
+    pub op: u8,
+    pub gas: u64,
+    pub stack: Vec<U256>,
+    pub memory: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub steps: Vec<StepRecord>,
+    pub frames: Vec<FrameRecord>,
+}
+
+impl Recording {
+    pub fn state_at(&self, step: usize) -> Option<StepState> {
+        let rec = self.steps.get(step)?;
+        let frame = &self.frames[rec.frame];
+        let mut stack = Vec::new();
+        let mut memory = Vec::new();
+        for prev in self.steps[..step].iter().filter(|s| s.frame == rec.frame) {
+            stack.truncate(stack.len().saturating_sub(prev.popped.len()));
+            stack.extend_from_slice(&prev.pushed);
+            memory.resize(prev.memory_size.max(memory.len()), 0);
+            if let Some(w) = &prev.memory {
+                memory[w.offset..w.offset + w.data.len()].copy_from_slice(&w.data);
+            }
+        }
+        Some(StepState {
+            step,
+            frame: rec.frame,
+            depth: frame.depth,
+            address: frame.address,
+            pc: rec.pc,
+            op: rec.op,
+            gas: rec.gas,
+            stack,
+            memory,
+        })
+    }
+
+    // Value of a storage slot just before `step`, if any recorded write
+    // tells us; untouched slots are unknown
+    pub fn storage_at(&self, step: usize, address: H160, slot: U256) -> Option<U256> {
+        let writes = self.writes(address, slot);
+        let first = writes.first().map(|(_, w)| w.old)?;
+        Some(
+            writes
+                .iter()
+                .rfind(|(j, _)| *j < step && self.in_effect(*j, step))
+                .map(|(_, w)| w.new)
+                .unwrap_or(first),
+        )
+    }
+
+    // The last SSTORE to `slot` of `address` that ran before `step`
+    pub fn last_sstore(&self, step: usize, address: H160, slot: U256) -> Option<usize> {
+        self.writes(address, slot)
+            .into_iter()
+            .rev()
+            .map(|(j, _)| j)
+            .find(|j| *j < step)
+    }
+
+    fn writes(&self, address: H160, slot: U256) -> Vec<(usize, StorageWrite)> {
+        self.steps
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| Some((i, s.storage?)))
+            .filter(|(_, w)| w.address == address && w.slot == slot)
+            .collect()
+    }
+
+    // A write made at step `j` still holds at `step` unless its frame, or a
+    // frame above it, failed in between
+    fn in_effect(&self, j: usize, step: usize) -> bool {
+        let mut frame = Some(self.steps[j].frame);
+        while let Some(f) = frame {
+            let rec = &self.frames[f];
+            if rec.reverted && rec.end.is_some_and(|end| end <= step) {
+                return false;
+            }
+            frame = rec.parent;
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    step: usize,
+    stack: Vec<U256>,
+    memory: Vec<u8>,
+    storage: Option<StorageWrite>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    pub recording: Recording,
+    // Indices into `frames` of the frames currently running
+    active: Vec<usize>,
+    pending: Vec<Pending>,
+}
+
+impl Recorder {
+    pub fn into_recording(mut self) -> Recording {
+        let end = self.recording.steps.len();
+        for f in self.active.drain(..) {
+            self.recording.frames[f].end.get_or_insert(end);
+        }
+        self.recording
+    }
+
+    // Index of the step being executed, once `step` has seen it
+    pub fn current(&self) -> Option<usize> {
+        self.pending.last().map(|p| p.step)
+    }
+
+    fn exit(&mut self, depth: usize, result: &ExecutionResult) {
+        if self.active.len() > depth + 1 {
+            let f = self.active.pop().unwrap();
+            let frame = &mut self.recording.frames[f];
+            frame.end = Some(self.recording.steps.len());
+            frame.reverted = !result.is_success();
+        }
+    }
+}
+
+impl Inspector for Recorder {
+    fn step(&mut self, evm: &Evm) {
+        // A new frame starts with its first step
+        if self.active.len() <= evm.depth {
+            self.recording.frames.push(FrameRecord {
+                parent: self.active.last().copied(),
+                depth: evm.depth,
+                address: evm.address,
+                code: evm.code.clone(),
+                end: None,
+                reverted: false,
+            });
+            self.active.push(self.recording.frames.len() - 1);
+        }
+        let op = evm.code.get(evm.pc).copied().unwrap_or(0);
+        let storage = match (op, evm.address, evm.stack.len()) {
+            (SSTORE, Some(address), n) if n >= 2 => {
+                let slot = evm.stack[n - 1];
+                let old = evm
+                    .world
+                    .as_ref()
+                    .and_then(|w| w.accounts.get(&address))
+                    .and_then(|a| a.storage.get(&slot))
+                    .copied()
+                    .unwrap_or_default();
+                Some(StorageWrite {
+                    address,
+                    slot,
+                    old,
+                    new: evm.stack[n - 2],
+                })
+            }
+            _ => None,
+        };
+        self.pending.push(Pending {
+            step: self.recording.steps.len(),
+            stack: evm.stack.clone(),
+            memory: evm.memory.clone(),
+            storage,
+        });
+        self.recording.steps.push(StepRecord {
+            frame: *self.active.last().unwrap(),
+            pc: evm.pc,
+            op,
+            gas: evm.gas.max(0) as u64,
+            gas_cost: 0,
+            popped: Vec::new(),
+            pushed: Vec::new(),
+            memory: None,
+            memory_size: evm.memory.len(),
+            storage: None,
+            error: None,
+        });
+    }
+
+    fn step_end(&mut self, evm: &Evm, error: Option<&EvmError>) {
+        let Some(before) = self.pending.pop() else {
+            return;
+        };
+        // The outermost frame has no call_end; its failure shows on its last step
+        let failed = error.is_some() || matches!(evm.halted, Some(Halt::Revert));
+        if evm.depth == 0 && failed {
+            if let Some(&f) = self.active.first() {
+                let frame = &mut self.recording.frames[f];
+                frame.end = Some(before.step + 1);
+                frame.reverted = true;
+            }
+        }
+        let rec = &mut self.recording.steps[before.step];
+        rec.gas_cost = rec.gas.saturating_sub(evm.gas.max(0) as u64);
+        rec.error = error.map(|e| e.to_string());
+        if error.is_some() {
+            return;
+        }
+        let kept = before
+            .stack
+            .iter()
+            .zip(&evm.stack)
+            .take_while(|(a, b)| a == b)
+            .count();
+        rec.popped = before.stack[kept..].to_vec();
+        rec.pushed = evm.stack[kept..].to_vec();
+        let old = |i: usize| before.memory.get(i).copied().unwrap_or(0);
+        let mut changed = (0..evm.memory.len()).filter(|i| evm.memory[*i] != old(*i));
+        if let Some(first) = changed.next() {
+            let last = changed.next_back().unwrap_or(first);
+            rec.memory = Some(MemoryWrite {
+                offset: first,
+                data: evm.memory[first..=last].to_vec(),
+            });
+        }
+        rec.memory_size = evm.memory.len();
+        rec.storage = before.storage;
+    }
+
+    fn call_end(&mut self, evm: &Evm, _inputs: &CallInputs, result: &mut ExecutionResult) {
+        self.exit(evm.depth, result);
+    }
+
+    fn create_end(&mut self, evm: &Evm, _inputs: &CreateInputs, outcome: &mut CreateOutcome) {
+        self.exit(evm.depth, &outcome.result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{Account, World};
+    use crate::testutil::{call_callee, callee_world, evm, CALLEE, CONTRACT};
+
+    fn record(code: Vec<u8>, world: World) -> (Recording, Evm) {
+        let mut evm = evm(code, Some(world));
+        let mut recorder = Recorder::default();
+        evm.run_with(&mut recorder);
+        (recorder.into_recording(), evm)
+    }
+
+    #[test]
+    fn rebuilds_state_before_any_step() {
+        // MSTORE(0, 0x2a); PUSH1 1; PUSH1 2; ADD; POP; STOP
+        let code = vec![
+            0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x01, 0x60, 0x02, 0x01, 0x50, 0x00,
+        ];
+        let (rec, evm) = record(code, World::default());
+        assert_eq!(rec.steps.len(), 8);
+        let add = rec.state_at(5).unwrap();
+        assert_eq!((add.pc, add.op), (9, 0x01));
+        assert_eq!(add.stack, vec![U256::from(1), U256::from(2)]);
+        assert_eq!(add.memory[31], 0x2a);
+        assert_eq!(rec.steps[5].popped, vec![U256::from(1), U256::from(2)]);
+        assert_eq!(rec.steps[5].pushed, vec![U256::from(3)]);
+        let mstore = &rec.steps[2];
+        assert_eq!(mstore.memory.as_ref().unwrap().offset, 31);
+        assert_eq!(mstore.memory_size, 32);
+        // Replaying every step gives back the final state
+        let last = rec.state_at(7).unwrap();
+        assert_eq!(last.memory, evm.memory);
+        assert_eq!(last.stack, evm.stack);
+        assert!(rec.state_at(8).is_none());
+    }
+
+    #[test]
+    fn tracks_storage_writes_across_reverted_calls() {
+        let mut world = callee_world("PUSH1 7 PUSH1 1 SSTORE PUSH1 0 PUSH1 0 REVERT");
+        world.accounts.insert(CONTRACT, Account::default());
+        let callee = world.accounts.get_mut(&CALLEE).unwrap();
+        callee.storage.insert(U256::from(1), U256::from(5));
+        // Then SSTORE(1, 9); STOP
+        let code = call_callee(0, "PUSH1 9 PUSH1 1 SSTORE STOP");
+        let (rec, _) = record(code, world);
+        assert_eq!(rec.frames.len(), 2);
+        assert!(rec.frames[1].reverted);
+        let slot = U256::from(1);
+        // The callee's SSTORE is step 10 (after 8 caller steps and 2 pushes)
+        assert_eq!(rec.last_sstore(20, CALLEE, slot), Some(10));
+        assert_eq!(rec.steps[10].storage.unwrap().old, U256::from(5));
+        assert_eq!(rec.storage_at(11, CALLEE, slot), Some(U256::from(7)));
+        // Rolled back once the call returned
+        let end = rec.frames[1].end.unwrap();
+        assert_eq!(rec.storage_at(end, CALLEE, slot), Some(U256::from(5)));
+        assert_eq!(rec.storage_at(end, CONTRACT, slot), Some(U256::zero()));
+        assert_eq!(rec.storage_at(end, CONTRACT, U256::from(2)), None);
+        let store = rec.last_sstore(rec.steps.len(), CONTRACT, slot).unwrap();
+        assert_eq!(rec.frames[rec.steps[store].frame].depth, 0);
+        assert_eq!(
+            rec.storage_at(rec.steps.len(), CONTRACT, slot),
+            Some(U256::from(9))
+        );
+    }
+
+    #[test]
+    fn rolls_back_a_top_level_revert() {
+        // SSTORE(1, 9); REVERT(0, 0)
+        let code = vec![0x60, 0x09, 0x60, 0x01, 0x55, 0x60, 0x00, 0x60, 0x00, 0xfd];
+        let (rec, _) = record(code, World::default());
+        assert!(rec.frames[0].reverted);
+        assert_eq!(rec.frames[0].end, Some(rec.steps.len()));
+        let slot = U256::from(1);
+        assert_eq!(rec.storage_at(3, CONTRACT, slot), Some(U256::from(9)));
+        assert_eq!(
+            rec.storage_at(rec.steps.len(), CONTRACT, slot),
+            Some(U256::zero())
+        );
+    }
+}
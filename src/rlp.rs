@@ -0,0 +1,148 @@
+// Minimal RLP encoding and decoding. List helpers take already-encoded items.
+
+use primitive_types::U256;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RlpItem {
+    Bytes(Vec<u8>),
+    List(Vec<RlpItem>),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RlpError {
+    #[error("unexpected end of rlp input")]
+    UnexpectedEnd,
+    #[error("trailing bytes after rlp item")]
+    TrailingBytes,
+}
+
+pub fn encode_bytes(b: &[u8]) -> Vec<u8> {
+    if b.len() == 1 && b[0] < 0x80 {
+        return vec![b[0]];
+    }
+    let mut out = length_prefix(b.len(), 0x80);
+    out.extend_from_slice(b);
+    out
+}
+
+pub fn encode_u64(n: u64) -> Vec<u8> {
+    let bytes = n.to_be_bytes();
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    encode_bytes(&bytes[first..])
+}
+
+pub fn encode_u256(n: U256) -> Vec<u8> {
+    let mut bytes = [0u8; 32];
+    n.to_big_endian(&mut bytes);
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    encode_bytes(&bytes[first..])
+}
+
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let len = items.iter().map(|i| i.len()).sum();
+    let mut out = length_prefix(len, 0xC0);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+fn length_prefix(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let bytes = (len as u64).to_be_bytes();
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    let mut out = vec![offset + 55 + (bytes.len() - first) as u8];
+    out.extend_from_slice(&bytes[first..]);
+    out
+}
+
+pub fn decode(data: &[u8]) -> Result<RlpItem, RlpError> {
+    let (item, used) = decode_item(data)?;
+    if used != data.len() {
+        return Err(RlpError::TrailingBytes);
+    }
+    Ok(item)
+}
+
+fn decode_item(data: &[u8]) -> Result<(RlpItem, usize), RlpError> {
+    let first = *data.first().ok_or(RlpError::UnexpectedEnd)?;
+    if first < 0x80 {
+        return Ok((RlpItem::Bytes(vec![first]), 1));
+    }
+    let (is_list, header, len) = match first {
+        0x80..=0xB7 => (false, 1, (first - 0x80) as usize),
+        0xB8..=0xBF => {
+            let n = (first - 0xB7) as usize;
+            (false, 1 + n, read_len(data, n)?)
+        }
+        0xC0..=0xF7 => (true, 1, (first - 0xC0) as usize),
+        _ => {
+            let n = (first - 0xF7) as usize;
+            (true, 1 + n, read_len(data, n)?)
+        }
+    };
+    let end = header.checked_add(len).ok_or(RlpError::UnexpectedEnd)?;
+    let body = data.get(header..end).ok_or(RlpError::UnexpectedEnd)?;
+    if !is_list {
+        return Ok((RlpItem::Bytes(body.to_vec()), end));
+    }
+    let mut items = Vec::new();
+    let mut pos = 0;
+    while pos < body.len() {
+        let (item, used) = decode_item(&body[pos..])?;
+        items.push(item);
+        pos += used;
+    }
+    Ok((RlpItem::List(items), end))
+}
+
+fn read_len(data: &[u8], n: usize) -> Result<usize, RlpError> {
+    let bytes = data.get(1..1 + n).ok_or(RlpError::UnexpectedEnd)?;
+    Ok(bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_spec_examples() {
+        assert_eq!(encode_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+        assert_eq!(encode_bytes(&[]), vec![0x80]);
+        assert_eq!(encode_u64(0), vec![0x80]);
+        assert_eq!(encode_u64(15), vec![0x0f]);
+        assert_eq!(encode_u64(1024), vec![0x82, 0x04, 0x00]);
+        assert_eq!(
+            encode_list(&[encode_bytes(b"cat"), encode_bytes(b"dog")]),
+            vec![0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+        );
+        let long = encode_bytes(&[b'a'; 56]);
+        assert_eq!(&long[..2], &[0xb8, 56]);
+        assert_eq!(long.len(), 58);
+    }
+
+    #[test]
+    fn decode_round_trips() {
+        let encoded = encode_list(&[
+            encode_bytes(b"cat"),
+            encode_list(&[encode_u64(1024), encode_bytes(&[b'a'; 60])]),
+            encode_bytes(&[]),
+        ]);
+        assert_eq!(
+            decode(&encoded).unwrap(),
+            RlpItem::List(vec![
+                RlpItem::Bytes(b"cat".to_vec()),
+                RlpItem::List(vec![
+                    RlpItem::Bytes(vec![0x04, 0x00]),
+                    RlpItem::Bytes(vec![b'a'; 60]),
+                ]),
+                RlpItem::Bytes(vec![]),
+            ])
+        );
+        assert_eq!(decode(&[0x83, b'd']), Err(RlpError::UnexpectedEnd));
+        assert_eq!(decode(&[0x01, 0x02]), Err(RlpError::TrailingBytes));
+    }
+}
@@ -0,0 +1,291 @@
+// External function selectors from a contract's dispatcher, found by matching
+// the comparisons compilers emit against the calldata selector:
+//
+//   solc   DUP1 PUSH4 sel EQ PUSH2 dest JUMPI           entry at dest
+//   vyper  PUSH4 sel PUSH1 0 MLOAD EQ ISZERO PUSH2 skip JUMPI
+//          PUSH4 sel DUP2 XOR PUSH2 skip JUMPI            entry right after JUMPI
+//
+// Every instruction is scanned, so the EQ checks in each range of a solc
+// binary-search dispatcher are found while its `PUSH4 pivot GT` splits are not.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::abi::Signature;
+use crate::disasm::{disassemble, Instruction};
+use crate::hexutil::parse_hex;
+use crate::machine::scan_jumpdests;
+use crate::opcodes::*;
+
+const PUSH2: u8 = PUSH1 + 1;
+const PUSH4: u8 = PUSH1 + 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selector {
+    pub selector: [u8; 4],
+    // Where the function's code starts
+    pub entry: usize,
+    // The PUSH4 of the selector
+    pub pc: usize,
+}
+
+// Selectors in the order the dispatcher checks them, each listed once
+pub fn find_selectors(code: &[u8]) -> Vec<Selector> {
+    let ins = disassemble(code);
+    let jumpdests = scan_jumpdests(code);
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    for (i, push) in ins.iter().enumerate() {
+        if push.opcode != PUSH4 || push.truncated {
+            continue;
+        }
+        let Some(entry) = match_check(&ins[i + 1..], &jumpdests) else {
+            continue;
+        };
+        let selector = [
+            push.immediate[0],
+            push.immediate[1],
+            push.immediate[2],
+            push.immediate[3],
+        ];
+        if seen.insert(selector) {
+            out.push(Selector {
+                selector,
+                entry,
+                pc: push.offset,
+            });
+        }
+    }
+    out
+}
+
+// Entry of the function when `rest` compares the selector just pushed and
+// branches on it
+fn match_check(rest: &[Instruction], jumpdests: &HashSet<usize>) -> Option<usize> {
+    // Loading the calldata selector to compare against
+    let loads = rest
+        .iter()
+        .take(3)
+        .take_while(|i| {
+            matches!(i.opcode, DUP1..=DUP16 | SWAP1..=SWAP16 | MLOAD | CALLDATALOAD | PUSH0..=PUSH2)
+        })
+        .count();
+    let rest = &rest[loads..];
+    let (negated, rest) = match rest.first()?.opcode {
+        EQ if rest.get(1)?.opcode == ISZERO => (true, &rest[2..]),
+        EQ => (false, &rest[1..]),
+        XOR | SUB => (true, &rest[1..]),
+        _ => return None,
+    };
+    let (dest, jumpi) = (rest.first()?, rest.get(1)?);
+    if !(PUSH1..=PUSH4).contains(&dest.opcode) || jumpi.opcode != JUMPI {
+        return None;
+    }
+    if negated {
+        return Some(jumpi.next());
+    }
+    let target = dest
+        .immediate
+        .iter()
+        .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+    jumpdests.contains(&target).then_some(target)
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SignatureDbError {
+    #[error("line {0}: expected \"0x<selector> <signature>\" or a signature")]
+    Line(usize),
+    #[error("invalid JSON: {0}")]
+    Json(String),
+}
+
+// Selector -> text signatures, more than one where they collide
+#[derive(Debug, Clone, Default)]
+pub struct SignatureDb {
+    pub signatures: HashMap<[u8; 4], Vec<String>>,
+}
+
+impl SignatureDb {
+    // Text with one "0xa9059cbb transfer(address,uint256)" or bare signature
+    // per line ('#' starts a comment), or JSON: a {"0xa9059cbb": signature or
+    // [signatures]} map, or 4byte.directory's {"results": [{"hex_signature",
+    // "text_signature"}]}
+    pub fn parse(text: &str) -> Result<SignatureDb, SignatureDbError> {
+        let mut db = SignatureDb::default();
+        let t = text.trim_start();
+        if t.starts_with('{') || t.starts_with('[') {
+            let v: Value =
+                serde_json::from_str(t).map_err(|e| SignatureDbError::Json(e.to_string()))?;
+            db.add_json(&v)?;
+            return Ok(db);
+        }
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad = || SignatureDbError::Line(i + 1);
+            let (first, rest) = line
+                .split_once(|c: char| c.is_whitespace() || c == ':')
+                .unwrap_or((line, ""));
+            match selector_bytes(first) {
+                Some(sel) if !rest.trim().is_empty() => db.add(sel, rest.trim()),
+                Some(_) => return Err(bad()),
+                None => {
+                    let sig = Signature::parse(line).map_err(|_| bad())?;
+                    db.add(sig.selector(), &sig.canonical());
+                }
+            }
+        }
+        Ok(db)
+    }
+
+    fn add_json(&mut self, v: &Value) -> Result<(), SignatureDbError> {
+        let bad = |what: &str| SignatureDbError::Json(format!("unexpected {what}"));
+        match v {
+            Value::Object(map) if map.contains_key("results") => self.add_json(&map["results"]),
+            Value::Object(map) => {
+                for (key, sigs) in map {
+                    let sel = selector_bytes(key).ok_or_else(|| bad("selector key"))?;
+                    match sigs {
+                        Value::String(s) => self.add(sel, s),
+                        Value::Array(items) => {
+                            for s in items {
+                                self.add(sel, s.as_str().ok_or_else(|| bad("signature"))?);
+                            }
+                        }
+                        _ => return Err(bad("signature")),
+                    }
+                }
+                Ok(())
+            }
+            Value::Array(items) => {
+                for item in items {
+                    let sel = item
+                        .get("hex_signature")
+                        .and_then(Value::as_str)
+                        .and_then(selector_bytes);
+                    let sig = item.get("text_signature").and_then(Value::as_str);
+                    match (sel, sig) {
+                        (Some(sel), Some(sig)) => self.add(sel, sig),
+                        _ => return Err(bad("entry")),
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(bad("value")),
+        }
+    }
+
+    pub fn add(&mut self, selector: [u8; 4], signature: &str) {
+        let sigs = self.signatures.entry(selector).or_default();
+        if !sigs.iter().any(|s| s == signature) {
+            sigs.push(signature.to_string());
+        }
+    }
+
+    pub fn lookup(&self, selector: [u8; 4]) -> &[String] {
+        self.signatures.get(&selector).map_or(&[], |s| s.as_slice())
+    }
+}
+
+// "0xa9059cbb" or "a9059cbb"
+fn selector_bytes(s: &str) -> Option<[u8; 4]> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    if digits.len() != 8 {
+        return None;
+    }
+    let b = parse_hex(digits)?;
+    Some([b[0], b[1], b[2], b[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn finds_solc_and_vyper_dispatch_checks() {
+        // solc binary search: the GT pivot splits the selectors in two ranges
+        let code = assemble(
+            "
+            PUSH1 0xe0 PUSH1 0 CALLDATALOAD SHR
+            DUP1 PUSH4 0x70a08231 GT PUSH @upper JUMPI
+            DUP1 PUSH4 0x06fdde03 EQ PUSH @name JUMPI
+            PUSH4 0x18160ddd DUP2 EQ PUSH @supply JUMPI
+            PUSH1 0 DUP1 REVERT
+            JUMPDEST @upper
+            DUP1 PUSH4 0xa9059cbb EQ PUSH @transfer JUMPI
+            PUSH1 0 DUP1 REVERT
+            JUMPDEST @name STOP
+            JUMPDEST @supply STOP
+            JUMPDEST @transfer STOP
+            ",
+        )
+        .unwrap();
+        let found: Vec<(u32, usize)> = find_selectors(&code)
+            .iter()
+            .map(|s| (u32::from_be_bytes(s.selector), s.entry))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(0x06fdde03, 0x37), (0x18160ddd, 0x39), (0xa9059cbb, 0x3b)]
+        );
+
+        let code = assemble(
+            "
+            PUSH4 0x06fdde03 PUSH1 0 MLOAD EQ ISZERO PUSH @next JUMPI
+            STOP
+            JUMPDEST @next
+            PUSH4 0x18160ddd DUP2 XOR PUSH @end JUMPI
+            STOP
+            JUMPDEST @end STOP
+            ",
+        )
+        .unwrap();
+        let found: Vec<(usize, usize)> = find_selectors(&code)
+            .iter()
+            .map(|s| (s.pc, s.entry))
+            .collect();
+        assert_eq!(found, vec![(0, 0x0d), (0x0f, 0x19)]);
+    }
+
+    #[test]
+    fn parses_signature_databases() {
+        let text = "
+            # selector and signature, or just the signature
+            0xa9059cbb transfer(address,uint256)
+            70a08231:balanceOf(address)
+            function approve(address spender, uint256 amount)
+        ";
+        let db = SignatureDb::parse(text).unwrap();
+        assert_eq!(
+            db.lookup([0xa9, 0x05, 0x9c, 0xbb]),
+            ["transfer(address,uint256)"]
+        );
+        assert_eq!(db.lookup([0x70, 0xa0, 0x82, 0x31]), ["balanceOf(address)"]);
+        assert_eq!(
+            db.lookup([0x09, 0x5e, 0xa7, 0xb3]),
+            ["approve(address,uint256)"]
+        );
+        assert_eq!(
+            SignatureDb::parse("0x1234").unwrap_err(),
+            SignatureDbError::Line(1)
+        );
+
+        let json = r#"{"results": [
+            {"hex_signature": "0x06fdde03", "text_signature": "name()"},
+            {"hex_signature": "0x06fdde03", "text_signature": "collision()"}
+        ]}"#;
+        let db = SignatureDb::parse(json).unwrap();
+        assert_eq!(
+            db.lookup([0x06, 0xfd, 0xde, 0x03]),
+            ["name()", "collision()"]
+        );
+        let db = SignatureDb::parse(r#"{"0x18160ddd": "totalSupply()"}"#).unwrap();
+        assert_eq!(db.lookup([0x18, 0x16, 0x0d, 0xdd]), ["totalSupply()"]);
+        assert!(db.lookup([0; 4]).is_empty());
+    }
+}
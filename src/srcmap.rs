@@ -0,0 +1,326 @@
+// solc source maps: `s:l:f:j:m` entries separated by `;`, one per instruction.
+// Empty fields repeat the previous entry's value.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use primitive_types::H256;
+use serde_json::Value;
+
+use crate::disasm::instruction_starts;
+use crate::hexutil::parse_hex;
+use crate::machine::keccak256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceRange {
+    // Byte offset and length in the source file
+    pub offset: usize,
+    pub length: usize,
+    // Index into the compiler's source list; -1 for generated code
+    pub file: i64,
+    // 'i' into a function, 'o' out of one, '-' a regular jump
+    pub jump: char,
+}
+
+pub fn decode(srcmap: &str) -> Result<Vec<SourceRange>, String> {
+    let mut out = Vec::new();
+    let mut cur = SourceRange {
+        offset: 0,
+        length: 0,
+        file: -1,
+        jump: '-',
+    };
+    if srcmap.trim().is_empty() {
+        return Ok(out);
+    }
+    for (i, entry) in srcmap.trim().split(';').enumerate() {
+        let bad = |field: &str| format!("source map entry {i}: invalid {field}");
+        let fields: Vec<&str> = entry.split(':').collect();
+        if let Some(s) = fields.first().filter(|s| !s.is_empty()) {
+            cur.offset = field(s).ok_or_else(|| bad("offset"))?;
+        }
+        if let Some(l) = fields.get(1).filter(|s| !s.is_empty()) {
+            cur.length = field(l).ok_or_else(|| bad("length"))?;
+        }
+        if let Some(f) = fields.get(2).filter(|s| !s.is_empty()) {
+            cur.file = f.parse().map_err(|_| bad("file"))?;
+        }
+        if let Some(j) = fields.get(3).filter(|s| !s.is_empty()) {
+            cur.jump = j.chars().next().unwrap();
+        }
+        out.push(cur);
+    }
+    Ok(out)
+}
+
+// Offsets and lengths are sometimes -1 for generated code
+fn field(s: &str) -> Option<usize> {
+    s.parse::<i64>().ok().map(|v| v.max(0) as usize)
+}
+
+// Source range of each pc that starts an instruction
+pub fn pc_index(code: &[u8], ranges: &[SourceRange]) -> HashMap<usize, SourceRange> {
+    instruction_starts(code)
+        .into_iter()
+        .zip(ranges.iter().copied())
+        .collect()
+}
+
+// 1-based line containing byte `offset`
+pub fn line_of(source: &str, offset: usize) -> usize {
+    let end = offset.min(source.len());
+    source.as_bytes()[..end]
+        .iter()
+        .filter(|b| **b == b'\n')
+        .count()
+        + 1
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    // 1-based
+    pub line: usize,
+    pub column: usize,
+    // First line of the mapped range, trimmed
+    pub snippet: String,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+// PC -> source index for one contract's runtime code
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    index: HashMap<usize, SourceRange>,
+    // (name, text) in the compiler's source list order
+    sources: Vec<(String, String)>,
+}
+
+impl SourceMap {
+    pub fn new(code: &[u8], ranges: &[SourceRange], sources: Vec<(String, String)>) -> Self {
+        Self {
+            index: pc_index(code, ranges),
+            sources,
+        }
+    }
+
+    pub fn location(&self, pc: usize) -> Option<SourceLocation> {
+        let range = self.index.get(&pc)?;
+        let (name, text) = self.sources.get(usize::try_from(range.file).ok()?)?;
+        let start = floor_char_boundary(text, range.offset);
+        let end = floor_char_boundary(text, range.offset.saturating_add(range.length)).max(start);
+        let line_start = text[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Some(SourceLocation {
+            file: name.clone(),
+            line: line_of(text, start),
+            column: text[line_start..start].chars().count() + 1,
+            snippet: text[start..end]
+                .lines()
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_string(),
+        })
+    }
+}
+
+// `offset` clamped to `text` and moved back to the start of the char it falls in;
+// a source map built against other text can point anywhere
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut i = offset.min(text.len());
+    while !text.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+// Runtime code and source map of one compiled contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub name: String,
+    pub code: Vec<u8>,
+    pub srcmap: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SolcOutput {
+    pub contracts: Vec<Artifact>,
+    // Source file names; a source map's file index points into this
+    pub source_list: Vec<String>,
+}
+
+impl SolcOutput {
+    // Accepts `solc --combined-json bin-runtime,srcmap-runtime` output as well as
+    // standard-JSON output. Contracts without runtime code or with unlinked
+    // library placeholders are skipped.
+    pub fn parse(v: &Value) -> Result<Self, String> {
+        let contracts = v
+            .get("contracts")
+            .and_then(|c| c.as_object())
+            .ok_or("missing contracts")?;
+        let mut out = SolcOutput::default();
+        if let Some(list) = v.get("sourceList").and_then(|l| l.as_array()) {
+            out.source_list = list
+                .iter()
+                .filter_map(|s| s.as_str().map(String::from))
+                .collect();
+            for (name, c) in contracts {
+                let bin = c.get("bin-runtime").and_then(|b| b.as_str()).unwrap_or("");
+                let srcmap = c
+                    .get("srcmap-runtime")
+                    .and_then(|b| b.as_str())
+                    .unwrap_or("");
+                out.push(name.clone(), bin, srcmap);
+            }
+        } else if let Some(sources) = v.get("sources").and_then(|s| s.as_object()) {
+            let mut ids: Vec<(u64, &String)> = sources
+                .iter()
+                .filter_map(|(name, s)| Some((s.get("id")?.as_u64()?, name)))
+                .collect();
+            ids.sort();
+            out.source_list = ids.into_iter().map(|(_, name)| name.clone()).collect();
+            for (file, by_name) in contracts {
+                for (name, c) in by_name.as_object().into_iter().flatten() {
+                    let deployed = &c["evm"]["deployedBytecode"];
+                    let bin = deployed["object"].as_str().unwrap_or("");
+                    let srcmap = deployed["sourceMap"].as_str().unwrap_or("");
+                    out.push(format!("{file}:{name}"), bin, srcmap);
+                }
+            }
+        } else {
+            return Err("expected sourceList (combined JSON) or sources (standard JSON)".into());
+        }
+        Ok(out)
+    }
+
+    fn push(&mut self, name: String, bin: &str, srcmap: &str) {
+        if let Some(code) = parse_hex(bin).filter(|c| !c.is_empty()) {
+            self.contracts.push(Artifact {
+                name,
+                code,
+                srcmap: srcmap.to_string(),
+            });
+        }
+    }
+
+    // Source maps keyed by the hash of each contract's runtime code, so frames
+    // can be matched to a contract by the code they run. `sources` holds the
+    // text of each file in `source_list`.
+    pub fn source_maps(
+        &self,
+        sources: &[(String, String)],
+    ) -> Result<HashMap<H256, SourceMap>, String> {
+        let mut out = HashMap::new();
+        for c in &self.contracts {
+            let ranges = decode(&c.srcmap).map_err(|e| format!("{}: {e}", c.name))?;
+            out.insert(
+                keccak256(&c.code),
+                SourceMap::new(&c.code, &ranges, sources.to_vec()),
+            );
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_compressed_entries() {
+        let ranges = decode("0:10:0:-;;5:2;:3::i;-1:-1:-1:o").unwrap();
+        assert_eq!(ranges.len(), 5);
+        assert_eq!(ranges[1], ranges[0]);
+        assert_eq!(
+            (ranges[2].offset, ranges[2].length, ranges[2].file),
+            (5, 2, 0)
+        );
+        assert_eq!(
+            (ranges[3].offset, ranges[3].length, ranges[3].jump),
+            (5, 3, 'i')
+        );
+        assert_eq!((ranges[4].offset, ranges[4].file), (0, -1));
+        assert!(decode("x:1").is_err());
+    }
+
+    #[test]
+    fn maps_instructions_to_pcs() {
+        // PUSH1 1; PUSH2 0x0203; ADD
+        let ranges = decode("0:1:0;2:1;4:1").unwrap();
+        let index = pc_index(&[0x60, 0x01, 0x61, 0x02, 0x03, 0x01], &ranges);
+        assert_eq!(index[&2].offset, 2);
+        assert_eq!(index[&5].offset, 4);
+        assert_eq!(line_of("a\nb\nc", 4), 3);
+    }
+
+    fn token_sources() -> Vec<(String, String)> {
+        let text = "contract T {\n    function f() public {\n        revert();\n    }\n}\n";
+        vec![("T.sol".to_string(), text.to_string())]
+    }
+
+    #[test]
+    fn locates_pcs_in_source() {
+        // PUSH1 0; DUP1; REVERT mapped to the contract, the function, then revert()
+        let map = SourceMap::new(
+            &[0x60, 0x00, 0x80, 0xfd],
+            &decode("0:60:0;17:41;47:8").unwrap(),
+            token_sources(),
+        );
+        let loc = map.location(3).unwrap();
+        assert_eq!(loc.to_string(), "T.sol:3:9");
+        assert_eq!(loc.snippet, "revert()");
+        assert_eq!(map.location(2).unwrap().snippet, "function f() public {");
+        assert!(map.location(1).is_none());
+    }
+
+    #[test]
+    fn locates_pcs_in_multibyte_source() {
+        let text = "// ünïcode\ncontract T {}";
+        let range = |offset, length| SourceRange {
+            offset,
+            length,
+            file: 0,
+            jump: '-',
+        };
+        // STOP; STOP: one range starts inside 'ü' and runs past the end, one is "code"
+        let map = SourceMap::new(
+            &[0x00, 0x00],
+            &[range(4, usize::MAX), range(8, 4)],
+            vec![("U.sol".to_string(), text.to_string())],
+        );
+        let loc = map.location(0).unwrap();
+        assert_eq!(loc.to_string(), "U.sol:1:4");
+        assert_eq!(loc.snippet, "ünïcode");
+        let loc = map.location(1).unwrap();
+        assert_eq!(loc.to_string(), "U.sol:1:7");
+        assert_eq!(loc.snippet, "code");
+    }
+
+    #[test]
+    fn parses_combined_and_standard_json() {
+        let combined = serde_json::json!({
+            "contracts": {"T.sol:T": {"bin-runtime": "600080fd", "srcmap-runtime": "0:60:0;17:41;47:8"}},
+            "sourceList": ["T.sol"],
+        });
+        let out = SolcOutput::parse(&combined).unwrap();
+        assert_eq!(out.source_list, ["T.sol"]);
+        assert_eq!(out.contracts[0].code, vec![0x60, 0x00, 0x80, 0xfd]);
+        let maps = out.source_maps(&token_sources()).unwrap();
+        let map = &maps[&keccak256(&[0x60, 0x00, 0x80, 0xfd])];
+        assert_eq!(map.location(3).unwrap().line, 3);
+
+        let standard = serde_json::json!({
+            "contracts": {"T.sol": {"T": {"evm": {"deployedBytecode": {
+                "object": "600080fd", "sourceMap": "0:60:0;17:41;47:8"
+            }}}}},
+            "sources": {"T.sol": {"id": 0}},
+        });
+        let out = SolcOutput::parse(&standard).unwrap();
+        assert_eq!(out.contracts[0].name, "T.sol:T");
+        assert_eq!(out.source_list, ["T.sol"]);
+    }
+}
@@ -0,0 +1,314 @@
+// Runner for the ethereum/tests GeneralStateTests JSON format.
+
+use primitive_types::{H256, U256};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::block::{logs_hash, BlockExecutor};
+use crate::hexutil::{hex, parse_h160, parse_h256, parse_hex, parse_u256};
+use crate::machine::{Account, BlockEnv, World};
+use crate::transaction::{secret_key_address, AccessListItem, Transaction, TxKind};
+
+// The only fork whose post states are checked. The EVM has a subset of its
+// rules: EIP-1559 fees, EIP-3529 refunds, EIP-3860 initcode limits and word gas,
+// PUSH0. There is no EIP-2929 warm/cold pricing, and TLOAD/TSTORE, MCOPY and
+// the blob opcodes do not execute, so fixtures relying on those fail.
+pub const FORK: &str = "Cancun";
+
+#[derive(Debug, Error)]
+pub enum StateTestError {
+    #[error("invalid json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported fork {0}: only {FORK} is implemented")]
+    UnsupportedFork(String),
+    #[error("{test}: missing or invalid field `{field}`")]
+    Field { test: String, field: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateTestResult {
+    pub name: String,
+    pub fork: String,
+    // Position of the case in the fork's post list
+    pub index: usize,
+    pub pass: bool,
+    pub state_root: H256,
+    pub expected_state_root: H256,
+    pub logs_hash: H256,
+    pub expected_logs_hash: H256,
+    // Why the transaction was rejected, if it was
+    pub error: Option<String>,
+}
+
+impl StateTestResult {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "fork": self.fork,
+            "index": self.index,
+            "pass": self.pass,
+            "stateRoot": format!("0x{}", hex(self.state_root.as_bytes())),
+            "expectedStateRoot": format!("0x{}", hex(self.expected_state_root.as_bytes())),
+            "logsHash": format!("0x{}", hex(self.logs_hash.as_bytes())),
+            "expectedLogsHash": format!("0x{}", hex(self.expected_logs_hash.as_bytes())),
+            "error": self.error,
+        })
+    }
+}
+
+// Run every test in a fixture file against its `FORK` post states; post states
+// of other forks are skipped. `fork`, if given, must be `FORK`.
+pub fn run_state_tests(
+    json: &str,
+    fork: Option<&str>,
+) -> Result<Vec<StateTestResult>, StateTestError> {
+    check_fork(fork)?;
+    let tests: Map<String, Value> = serde_json::from_str(json)?;
+    let mut results = Vec::new();
+    for (name, test) in &tests {
+        results.extend(run_state_test(name, test, fork)?);
+    }
+    Ok(results)
+}
+
+fn check_fork(fork: Option<&str>) -> Result<(), StateTestError> {
+    match fork.filter(|f| *f != FORK) {
+        Some(f) => Err(StateTestError::UnsupportedFork(f.to_string())),
+        None => Ok(()),
+    }
+}
+
+pub fn run_state_test(
+    name: &str,
+    test: &Value,
+    fork: Option<&str>,
+) -> Result<Vec<StateTestResult>, StateTestError> {
+    check_fork(fork)?;
+    let field = |field: &str| StateTestError::Field {
+        test: name.to_string(),
+        field: field.to_string(),
+    };
+    let env = test.get("env").ok_or_else(|| field("env"))?;
+    let block = parse_env(env).ok_or_else(|| field("env"))?;
+    let pre =
+        parse_alloc(test.get("pre").ok_or_else(|| field("pre"))?).ok_or_else(|| field("pre"))?;
+    let tx = test
+        .get("transaction")
+        .ok_or_else(|| field("transaction"))?;
+    let post = test
+        .get("post")
+        .and_then(|p| p.as_object())
+        .ok_or_else(|| field("post"))?;
+
+    let mut results = Vec::new();
+    for (fork_name, cases) in post {
+        if fork_name != FORK {
+            continue;
+        }
+        let cases = cases.as_array().ok_or_else(|| field("post"))?;
+        for (index, case) in cases.iter().enumerate() {
+            let indexes = case.get("indexes").ok_or_else(|| field("post.indexes"))?;
+            let tx = build_transaction(tx, indexes).ok_or_else(|| field("transaction"))?;
+            let expected_state_root = case
+                .get("hash")
+                .and_then(|h| h.as_str())
+                .and_then(parse_h256)
+                .ok_or_else(|| field("post.hash"))?;
+            let expected_logs_hash = case
+                .get("logs")
+                .and_then(|h| h.as_str())
+                .and_then(parse_h256)
+                .ok_or_else(|| field("post.logs"))?;
+            let expect_exception = case.get("expectException").is_some();
+
+            let mut executor = BlockExecutor::new(pre.clone(), block.clone());
+            let (logs, error) = match executor.execute_transaction(&tx) {
+                Ok(receipt) => (receipt.logs.clone(), None),
+                Err(e) => (Vec::new(), Some(e.to_string())),
+            };
+            let state_root = executor.world.state_root();
+            let logs_hash = logs_hash(&logs);
+            results.push(StateTestResult {
+                name: name.to_string(),
+                fork: fork_name.clone(),
+                index,
+                pass: state_root == expected_state_root
+                    && logs_hash == expected_logs_hash
+                    && error.is_some() == expect_exception,
+                state_root,
+                expected_state_root,
+                logs_hash,
+                expected_logs_hash,
+                error,
+            });
+        }
+    }
+    Ok(results)
+}
+
+fn parse_env(env: &Value) -> Option<BlockEnv> {
+    let num = |key: &str| env.get(key)?.as_str().and_then(parse_u256);
+    Some(BlockEnv {
+        coinbase: parse_h160(env.get("currentCoinbase")?.as_str()?)?,
+        timestamp: num("currentTimestamp")?.low_u64(),
+        number: num("currentNumber")?.low_u64(),
+        gas_limit: num("currentGasLimit")?,
+        // State tests are always signed for mainnet
+        chain_id: U256::one(),
+        basefee: num("currentBaseFee").unwrap_or_default(),
+    })
+}
+
+// Parse an `{address: {balance, code, nonce, storage}}` allocation
+pub fn parse_alloc(v: &Value) -> Option<World> {
+    let mut world = World::default();
+    for (addr, acc) in v.as_object()? {
+        let str_field = |key: &str| acc.get(key).and_then(|x| x.as_str());
+        let mut account = Account {
+            nonce: str_field("nonce")
+                .map_or(Some(U256::zero()), parse_u256)?
+                .low_u64(),
+            balance: str_field("balance").map_or(Some(U256::zero()), parse_u256)?,
+            code: str_field("code").map_or(Some(Vec::new()), parse_hex)?,
+            ..Account::default()
+        };
+        if let Some(storage) = acc.get("storage").and_then(|s| s.as_object()) {
+            for (key, value) in storage {
+                let value = parse_u256(value.as_str()?)?;
+                if !value.is_zero() {
+                    account.storage.insert(parse_u256(key)?, value);
+                }
+            }
+        }
+        world.accounts.insert(parse_h160(addr)?, account);
+    }
+    Some(world)
+}
+
+// Pick the data/gas/value variant selected by `indexes` out of the shared transaction
+fn build_transaction(tx: &Value, indexes: &Value) -> Option<Transaction> {
+    let index = |key: &str| indexes.get(key)?.as_u64().map(|i| i as usize);
+    let (d, g, v) = (index("data")?, index("gas")?, index("value")?);
+    let pick = |key: &str, i: usize| tx.get(key)?.as_array()?.get(i)?.as_str();
+    let num = |key: &str| tx.get(key)?.as_str().and_then(parse_u256);
+
+    let caller = match tx.get("sender").and_then(|s| s.as_str()) {
+        Some(sender) => parse_h160(sender)?,
+        None => secret_key_address(&parse_hex(tx.get("secretKey")?.as_str()?)?)?,
+    };
+    let to = match tx.get("to").and_then(|t| t.as_str()).unwrap_or("") {
+        "" => TxKind::Create,
+        to => TxKind::Call(parse_h160(to)?),
+    };
+    let access_list = match tx.get("accessLists").and_then(|a| a.as_array()?.get(d)) {
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| {
+                Some(AccessListItem {
+                    address: parse_h160(item.get("address")?.as_str()?)?,
+                    storage_keys: item
+                        .get("storageKeys")?
+                        .as_array()?
+                        .iter()
+                        .map(|k| parse_u256(k.as_str()?))
+                        .collect::<Option<_>>()?,
+                })
+            })
+            .collect::<Option<_>>()?,
+        _ => Vec::new(),
+    };
+    let max_priority_fee_per_gas = num("maxPriorityFeePerGas");
+    // The fixtures have no type field; the fee and access list fields imply it
+    let tx_type = if max_priority_fee_per_gas.is_some() {
+        2
+    } else if tx.get("accessLists").is_some() {
+        1
+    } else {
+        0
+    };
+    Some(Transaction {
+        tx_type,
+        caller,
+        nonce: num("nonce")?.low_u64(),
+        gas_limit: parse_u256(pick("gasLimit", g)?)?.low_u64(),
+        gas_price: num("gasPrice").or_else(|| num("maxFeePerGas"))?,
+        max_priority_fee_per_gas,
+        to,
+        value: parse_u256(pick("value", v)?)?,
+        data: parse_hex(pick("data", d)?)?,
+        access_list,
+        chain_id: (tx_type != 0).then(U256::one),
+        signature: None,
+    })
+}
+
+// (passed, failed)
+pub fn summarize(results: &[StateTestResult]) -> (usize, usize) {
+    let passed = results.iter().filter(|r| r.pass).count();
+    (passed, results.len() - passed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDER_KEY: &str = "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8";
+
+    #[test]
+    fn derives_sender_from_secret_key() {
+        assert_eq!(
+            secret_key_address(&parse_hex(SENDER_KEY).unwrap()),
+            parse_h160("0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b")
+        );
+    }
+
+    // Post-state roots computed outside this crate: accounts RLP-encoded into a
+    // secure trie by an independent Keccak/RLP/MPT implementation, checked
+    // against the empty-trie root and the ethereum/tests trie vectors.
+    const FIXTURE: &str = r#"{
+  "transfer": {
+    "env": { "currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba", "currentGasLimit": "0x0f4240",
+             "currentNumber": "0x01", "currentTimestamp": "0x03e8", "currentBaseFee": "0x07" },
+    "pre": { "0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b": { "balance": "0x0f4240", "code": "0x", "nonce": "0x00", "storage": {} } },
+    "transaction": { "data": ["0x"], "gasLimit": ["0x5208", "0x01"], "gasPrice": "0x0a", "nonce": "0x00",
+                     "secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+                     "to": "0x095e7baea6a6c7c4c2dfeb977efac326af552d87", "value": ["0x05"] },
+    "post": {
+      "Cancun": [
+        { "hash": "0x0d23ca393a12333471a814d84331506f4dc89f44822dbdc3ee9c8eca046ea1a2",
+          "logs": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+          "indexes": { "data": 0, "gas": 0, "value": 0 } },
+        { "hash": "0x18c1342886baeaf926b3fbbfb0484bf0cd1ced43576547c11153d3c8944d5c47",
+          "logs": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+          "indexes": { "data": 0, "gas": 1, "value": 0 }, "expectException": "TR_IntrinsicGas" },
+        { "hash": "0x18c1342886baeaf926b3fbbfb0484bf0cd1ced43576547c11153d3c8944d5c47",
+          "logs": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+          "indexes": { "data": 0, "gas": 0, "value": 0 } }
+      ],
+      "Berlin": [
+        { "hash": "0x0d23ca393a12333471a814d84331506f4dc89f44822dbdc3ee9c8eca046ea1a2",
+          "logs": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+          "indexes": { "data": 0, "gas": 0, "value": 0 } }
+      ]
+    }
+  }
+}"#;
+
+    #[test]
+    fn value_transfer_fixture() {
+        // 21000 gas at 10 wei moves 5 wei to the recipient; basefee 7 is burned
+        let results = run_state_tests(FIXTURE, None).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].pass, "{:?}", results[0]);
+        assert!(results[1].pass, "{:?}", results[1]);
+        assert!(results[1].error.is_some());
+        // Expects the pre-state root after a successful transfer
+        assert!(!results[2].pass);
+        assert_eq!(summarize(&results), (2, 1));
+        assert!(results.iter().all(|r| r.fork == FORK));
+        assert!(matches!(
+            run_state_tests(FIXTURE, Some("Berlin")),
+            Err(StateTestError::UnsupportedFork(f)) if f == "Berlin"
+        ));
+    }
+}
@@ -0,0 +1,497 @@
+// State transition ("t8n") in the format used by geth's `evm t8n`:
+// alloc + env + txs in, result + alloc out.
+
+use primitive_types::{H160, H256, U256};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::block::{logs_hash, BlockError, BlockExecutor};
+use crate::hexutil::{hex, parse_h160, parse_hex, parse_u256};
+use crate::machine::{BlockEnv, LogEntry, World};
+use crate::rlp;
+use crate::statetest::{parse_alloc, FORK};
+use crate::transaction::{AccessListItem, Signature, Transaction, TxKind};
+use crate::trie::{ordered_trie_root, u256_bytes};
+
+#[derive(Debug, Error)]
+pub enum T8nError {
+    #[error("unsupported fork {0}: only {FORK} is implemented")]
+    UnsupportedFork(String),
+    #[error("invalid {what}: {reason}")]
+    Input { what: &'static str, reason: String },
+}
+
+fn input(what: &'static str, reason: impl Into<String>) -> T8nError {
+    T8nError::Input {
+        what,
+        reason: reason.into(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub index: u64,
+    pub validator_index: u64,
+    pub address: H160,
+    // In Gwei
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct T8nEnv {
+    pub block: BlockEnv,
+    pub withdrawals: Vec<Withdrawal>,
+}
+
+#[derive(Debug, Clone)]
+pub struct T8nOptions {
+    pub fork: String,
+    pub chain_id: U256,
+    // Paid to the coinbase after all transactions; None disables it
+    pub reward: Option<U256>,
+}
+
+impl Default for T8nOptions {
+    fn default() -> Self {
+        Self {
+            fork: FORK.to_string(),
+            chain_id: U256::one(),
+            reward: None,
+        }
+    }
+}
+
+pub fn parse_env(v: &Value) -> Result<T8nEnv, T8nError> {
+    let num = |key: &str| -> Result<Option<U256>, T8nError> {
+        match v.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(x) => x
+                .as_str()
+                .and_then(parse_u256)
+                .map(Some)
+                .ok_or_else(|| input("env", format!("bad {key}"))),
+        }
+    };
+    let required = |key: &str| num(key)?.ok_or_else(|| input("env", format!("missing {key}")));
+    let coinbase = v
+        .get("currentCoinbase")
+        .and_then(|x| x.as_str())
+        .and_then(parse_h160)
+        .ok_or_else(|| input("env", "missing currentCoinbase"))?;
+    let mut withdrawals = Vec::new();
+    for w in v
+        .get("withdrawals")
+        .and_then(|w| w.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let field = |key: &str| w.get(key).and_then(|x| x.as_str());
+        let num = |key: &str| field(key).and_then(parse_u256).map(|n| n.low_u64());
+        withdrawals.push(
+            (|| {
+                Some(Withdrawal {
+                    index: num("index")?,
+                    validator_index: num("validatorIndex")?,
+                    address: parse_h160(field("address")?)?,
+                    amount: num("amount")?,
+                })
+            })()
+            .ok_or_else(|| input("env", "bad withdrawal"))?,
+        );
+    }
+    Ok(T8nEnv {
+        block: BlockEnv {
+            coinbase,
+            timestamp: required("currentTimestamp")?.low_u64(),
+            number: required("currentNumber")?.low_u64(),
+            gas_limit: required("currentGasLimit")?,
+            chain_id: U256::one(),
+            basefee: num("currentBaseFee")?.unwrap_or_default(),
+        },
+        withdrawals,
+    })
+}
+
+// Parse txs.json. Each entry is either a transaction (sender recovered from the
+// signature, or signed here if it carries a `secretKey`) or a per-tx error.
+pub fn parse_txs(v: &Value, chain_id: U256) -> Result<Vec<Result<Transaction, String>>, T8nError> {
+    let txs = v
+        .as_array()
+        .ok_or_else(|| input("txs", "expected an array"))?;
+    Ok(txs.iter().map(|tx| parse_tx(tx, chain_id)).collect())
+}
+
+fn parse_tx(v: &Value, default_chain_id: U256) -> Result<Transaction, String> {
+    let num = |key: &str| -> Result<Option<U256>, String> {
+        match v.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(x) => x
+                .as_str()
+                .and_then(parse_u256)
+                .map(Some)
+                .ok_or_else(|| format!("invalid {key}")),
+        }
+    };
+    let required = |key: &str| num(key)?.ok_or_else(|| format!("missing {key}"));
+    let tx_type = num("type")?.unwrap_or_default().low_u64();
+    if tx_type > 2 {
+        return Err(format!("unsupported transaction type {tx_type}"));
+    }
+    let to = match v.get("to").and_then(|t| t.as_str()) {
+        None | Some("") => TxKind::Create,
+        Some(to) => TxKind::Call(parse_h160(to).ok_or("invalid to")?),
+    };
+    let data = v
+        .get("input")
+        .or_else(|| v.get("data"))
+        .and_then(|d| d.as_str())
+        .map_or(Some(Vec::new()), parse_hex)
+        .ok_or("invalid input")?;
+    let mut access_list = Vec::new();
+    for item in v
+        .get("accessList")
+        .and_then(|a| a.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let parsed = (|| {
+            Some(AccessListItem {
+                address: parse_h160(item.get("address")?.as_str()?)?,
+                storage_keys: item
+                    .get("storageKeys")?
+                    .as_array()?
+                    .iter()
+                    .map(|k| parse_u256(k.as_str()?))
+                    .collect::<Option<_>>()?,
+            })
+        })();
+        access_list.push(parsed.ok_or("invalid accessList")?);
+    }
+    let (gas_price, max_priority_fee_per_gas) = if tx_type == 2 {
+        (
+            required("maxFeePerGas")?,
+            Some(required("maxPriorityFeePerGas")?),
+        )
+    } else {
+        (required("gasPrice")?, None)
+    };
+    let v_field = num("v")?;
+    // Legacy transactions are EIP-155 protected unless they say otherwise
+    let chain_id = match (tx_type, num("chainId")?) {
+        (0, _) if v.get("protected").and_then(|p| p.as_bool()) == Some(false) => None,
+        (0, _) if v.get("secretKey").is_none() => v_field
+            .filter(|v| v.low_u64() >= 35)
+            .map(|v| U256::from((v.low_u64() - 35) / 2)),
+        (_, id) => Some(id.unwrap_or(default_chain_id)),
+    };
+    let mut tx = Transaction {
+        tx_type: tx_type as u8,
+        caller: H160::zero(),
+        nonce: required("nonce")?.low_u64(),
+        gas_limit: required("gas")?.low_u64(),
+        gas_price,
+        max_priority_fee_per_gas,
+        to,
+        value: num("value")?.unwrap_or_default(),
+        data,
+        access_list,
+        chain_id,
+        signature: None,
+    };
+    tx.check_type().map_err(|e| e.to_string())?;
+    if let Some(key) = v.get("secretKey").and_then(|k| k.as_str()) {
+        let key = parse_hex(key).ok_or("invalid secretKey")?;
+        tx.sign(&key).ok_or("invalid secretKey")?;
+    } else {
+        tx.signature = Some(Signature {
+            v: v_field.ok_or("missing v")?.low_u64(),
+            r: required("r")?,
+            s: required("s")?,
+        });
+        tx.caller = tx.recover_sender().ok_or("invalid signature")?;
+    }
+    Ok(tx)
+}
+
+// Apply `txs` on top of `alloc`. Returns the post state and geth's result.json.
+pub fn transition(
+    alloc: World,
+    env: &T8nEnv,
+    txs: &[Result<Transaction, String>],
+    opts: &T8nOptions,
+) -> Result<(World, Value), T8nError> {
+    // The EVM has one set of rules; other forks would get the wrong post state
+    if opts.fork != FORK {
+        return Err(T8nError::UnsupportedFork(opts.fork.clone()));
+    }
+    let mut block = env.block.clone();
+    block.chain_id = opts.chain_id;
+
+    let mut executor = BlockExecutor::new(alloc, block.clone());
+    let mut rejected = Vec::new();
+    let mut receipts = Vec::new();
+    let mut all_logs: Vec<LogEntry> = Vec::new();
+    for (i, tx) in txs.iter().enumerate() {
+        let tx = match tx {
+            Ok(tx) => tx,
+            Err(e) => {
+                rejected.push(json!({ "index": i, "error": e }));
+                continue;
+            }
+        };
+        let tx_index = receipts.len();
+        match executor.execute_transaction(tx) {
+            Ok(receipt) => {
+                let tx_hash = tx.hash();
+                let logs: Vec<Value> = receipt
+                    .logs
+                    .iter()
+                    .enumerate()
+                    .map(|(j, log)| {
+                        json!({
+                            "address": format!("0x{}", hex(log.address.as_bytes())),
+                            "topics": log.topics.iter().map(|t| format!("0x{}", hex(&u256_bytes(*t)))).collect::<Vec<_>>(),
+                            "data": format!("0x{}", hex(&log.data)),
+                            "blockNumber": format!("0x{:x}", block.number),
+                            "transactionHash": format!("0x{}", hex(tx_hash.as_bytes())),
+                            "transactionIndex": format!("0x{:x}", tx_index),
+                            "blockHash": format!("0x{}", hex(H256::zero().as_bytes())),
+                            "logIndex": format!("0x{:x}", all_logs.len() + j),
+                            "removed": false,
+                        })
+                    })
+                    .collect();
+                all_logs.extend(receipt.logs.iter().cloned());
+                receipts.push(json!({
+                    "type": format!("0x{:x}", receipt.tx_type),
+                    "root": "0x",
+                    "status": format!("0x{:x}", receipt.success as u8),
+                    "cumulativeGasUsed": format!("0x{:x}", receipt.cumulative_gas_used),
+                    "logsBloom": format!("0x{}", hex(&receipt.logs_bloom.0)),
+                    "logs": if logs.is_empty() { Value::Null } else { Value::Array(logs) },
+                    "transactionHash": format!("0x{}", hex(tx_hash.as_bytes())),
+                    "contractAddress": format!("0x{}", hex(receipt.contract_address.unwrap_or_default().as_bytes())),
+                    "gasUsed": format!("0x{:x}", receipt.gas_used),
+                    "blockHash": format!("0x{}", hex(H256::zero().as_bytes())),
+                    "transactionIndex": format!("0x{:x}", tx_index),
+                }));
+            }
+            Err(e) => {
+                let error = match e {
+                    BlockError::InvalidTransaction { source, .. } => source.to_string(),
+                    other => other.to_string(),
+                };
+                rejected.push(json!({ "index": i, "error": error }));
+            }
+        }
+    }
+
+    let (mut world, result) = executor.finish();
+    if let Some(reward) = opts.reward {
+        world.accounts.entry(block.coinbase).or_default().balance += reward;
+    }
+    for w in &env.withdrawals {
+        world.accounts.entry(w.address).or_default().balance +=
+            U256::from(w.amount) * U256::from(1_000_000_000u64);
+    }
+
+    let mut out = json!({
+        "stateRoot": format!("0x{}", hex(world.state_root().as_bytes())),
+        "txRoot": format!("0x{}", hex(result.transactions_root.as_bytes())),
+        "receiptsRoot": format!("0x{}", hex(result.receipts_root.as_bytes())),
+        "logsHash": format!("0x{}", hex(logs_hash(&all_logs).as_bytes())),
+        "logsBloom": format!("0x{}", hex(&result.logs_bloom.0)),
+        "receipts": receipts,
+        "currentDifficulty": Value::Null,
+        "gasUsed": format!("0x{:x}", result.gas_used),
+        "currentBaseFee": format!("0x{:x}", block.basefee),
+        "withdrawalsRoot": format!("0x{}", hex(withdrawals_root(&env.withdrawals).as_bytes())),
+    });
+    if !rejected.is_empty() {
+        out["rejected"] = Value::Array(rejected);
+    }
+    Ok((world, out))
+}
+
+pub fn withdrawals_root(withdrawals: &[Withdrawal]) -> H256 {
+    ordered_trie_root(
+        &withdrawals
+            .iter()
+            .map(|w| {
+                rlp::encode_list(&[
+                    rlp::encode_u64(w.index),
+                    rlp::encode_u64(w.validator_index),
+                    rlp::encode_bytes(w.address.as_bytes()),
+                    rlp::encode_u64(w.amount),
+                ])
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+pub fn parse_alloc_json(v: &Value) -> Result<World, T8nError> {
+    parse_alloc(v).ok_or_else(|| input("alloc", "malformed account"))
+}
+
+// geth's alloc format; zero fields and empty code/storage are omitted
+pub fn alloc_to_json(world: &World) -> Value {
+    let mut addrs: Vec<_> = world.accounts.keys().collect();
+    addrs.sort();
+    let mut out = Map::new();
+    for addr in addrs {
+        let acc = &world.accounts[addr];
+        let mut entry = Map::new();
+        if !acc.code.is_empty() {
+            entry.insert("code".into(), format!("0x{}", hex(&acc.code)).into());
+        }
+        let mut slots: Vec<_> = acc.storage.iter().filter(|(_, v)| !v.is_zero()).collect();
+        if !slots.is_empty() {
+            slots.sort();
+            let storage: Map<String, Value> = slots
+                .into_iter()
+                .map(|(k, v)| {
+                    (
+                        format!("0x{}", hex(&u256_bytes(*k))),
+                        format!("0x{}", hex(&u256_bytes(*v))).into(),
+                    )
+                })
+                .collect();
+            entry.insert("storage".into(), Value::Object(storage));
+        }
+        entry.insert("balance".into(), format!("0x{:x}", acc.balance).into());
+        if acc.nonce != 0 {
+            entry.insert("nonce".into(), format!("0x{:x}", acc.nonce).into());
+        }
+        out.insert(format!("0x{}", hex(addr.as_bytes())), Value::Object(entry));
+    }
+    Value::Object(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDER: &str = "0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b";
+    const SENDER_KEY: &str = "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8";
+
+    fn env() -> Value {
+        json!({
+            "currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+            "currentGasLimit": "0x0f4240",
+            "currentNumber": "0x01",
+            "currentTimestamp": "0x03e8",
+            "currentBaseFee": "0x07",
+            "withdrawals": [
+                { "index": "0x0", "validatorIndex": "0x1", "address": "0x00000000000000000000000000000000000000ee", "amount": "0x2" }
+            ],
+        })
+    }
+
+    #[test]
+    fn applies_signed_and_keyed_transactions() {
+        let alloc =
+            parse_alloc_json(&json!({ SENDER: { "balance": "0x0de0b6b3a7640000" } })).unwrap();
+        let env = parse_env(&env()).unwrap();
+        let opts = T8nOptions::default();
+        let txs = parse_txs(
+            &json!([
+                { "type": "0x2", "chainId": "0x1", "nonce": "0x0", "gas": "0x5208", "maxFeePerGas": "0x0a",
+                  "maxPriorityFeePerGas": "0x01", "to": "0x00000000000000000000000000000000000000bb",
+                  "value": "0x01", "input": "0x", "secretKey": SENDER_KEY },
+                // Wrong nonce: rejected, later transactions still apply
+                { "type": "0x0", "nonce": "0x5", "gas": "0x5208", "gasPrice": "0x0a",
+                  "to": "0x00000000000000000000000000000000000000bb", "value": "0x01", "secretKey": SENDER_KEY },
+                // Garbage signature
+                { "type": "0x0", "nonce": "0x1", "gas": "0x5208", "gasPrice": "0x0a",
+                  "to": null, "v": "0x1b", "r": "0x1", "s": "0x0" },
+            ]),
+            opts.chain_id,
+        )
+        .unwrap();
+        assert!(txs[2].is_err());
+
+        // Re-sign the first transaction's payload through v/r/s to exercise recovery
+        let signed = txs[0].clone().unwrap();
+        let sig = signed.signature.unwrap();
+        let recovered = parse_txs(
+            &json!([{ "type": "0x2", "chainId": "0x1", "nonce": "0x0", "gas": "0x5208", "maxFeePerGas": "0x0a",
+                "maxPriorityFeePerGas": "0x01", "to": "0x00000000000000000000000000000000000000bb",
+                "value": "0x01", "input": "0x", "v": format!("0x{:x}", sig.v),
+                "r": format!("0x{:x}", sig.r), "s": format!("0x{:x}", sig.s) }]),
+            opts.chain_id,
+        )
+        .unwrap();
+        assert_eq!(
+            recovered[0].as_ref().unwrap().caller,
+            parse_h160(SENDER).unwrap()
+        );
+
+        let (world, result) = transition(alloc, &env, &txs, &opts).unwrap();
+        assert_eq!(result["receipts"].as_array().unwrap().len(), 1);
+        assert_eq!(result["gasUsed"], "0x5208");
+        assert_eq!(result["currentBaseFee"], "0x7");
+        let rejected = result["rejected"].as_array().unwrap();
+        assert_eq!(rejected.len(), 2);
+        assert_eq!(rejected[0]["index"], 1);
+        assert_eq!(
+            result["stateRoot"],
+            format!("0x{}", hex(world.state_root().as_bytes()))
+        );
+        let ee = parse_h160("0x00000000000000000000000000000000000000ee").unwrap();
+        assert_eq!(world.accounts[&ee].balance, U256::from(2_000_000_000u64));
+        let out = alloc_to_json(&world);
+        assert_eq!(out[SENDER]["nonce"], "0x1");
+        assert_eq!(
+            parse_alloc_json(&out).unwrap().state_root(),
+            world.state_root()
+        );
+    }
+
+    #[test]
+    fn keeps_type_1_with_empty_access_list() {
+        let alloc =
+            parse_alloc_json(&json!({ SENDER: { "balance": "0x0de0b6b3a7640000" } })).unwrap();
+        let env = parse_env(&env()).unwrap();
+        let opts = T8nOptions::default();
+        let txs = parse_txs(
+            &json!([
+                { "type": "0x1", "chainId": "0x1", "nonce": "0x0", "gas": "0x5208", "gasPrice": "0x0a",
+                  "to": "0x00000000000000000000000000000000000000bb", "value": "0x01", "accessList": [],
+                  "secretKey": SENDER_KEY },
+                // Legacy transactions cannot carry an access list
+                { "type": "0x0", "nonce": "0x1", "gas": "0x5208", "gasPrice": "0x0a", "to": null,
+                  "accessList": [{ "address": SENDER, "storageKeys": [] }], "secretKey": SENDER_KEY },
+            ]),
+            opts.chain_id,
+        )
+        .unwrap();
+        let tx = txs[0].as_ref().unwrap();
+        assert_eq!(tx.tx_type, 1);
+        assert_eq!(tx.encode()[0], 1);
+        assert!(txs[1]
+            .as_ref()
+            .unwrap_err()
+            .contains("unexpected access list"));
+
+        let (_, result) = transition(alloc, &env, &txs, &opts).unwrap();
+        assert_eq!(result["receipts"][0]["type"], "0x1");
+        assert_eq!(result["rejected"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn only_the_implemented_fork_is_accepted() {
+        let env = parse_env(&env()).unwrap();
+        for fork in ["Berlin", "Prague", "Nope"] {
+            let opts = T8nOptions {
+                fork: fork.to_string(),
+                ..T8nOptions::default()
+            };
+            assert!(matches!(
+                transition(World::default(), &env, &[], &opts),
+                Err(T8nError::UnsupportedFork(f)) if f == fork
+            ));
+        }
+        let (_, result) = transition(World::default(), &env, &[], &T8nOptions::default()).unwrap();
+        assert!(result.get("withdrawalsRoot").is_some());
+    }
+}
@@ -0,0 +1,42 @@
+// Fixtures shared by the inspector tests: a contract that calls a callee.
+
+use primitive_types::H160;
+
+use crate::asm::assemble;
+use crate::machine::{Account, Evm, EvmConfig, World};
+
+pub const CONTRACT: H160 = H160([0xaa; 20]);
+pub const CALLEE: H160 = H160([0xbb; 20]);
+
+// Evm running `code` as CONTRACT
+pub fn evm(code: Vec<u8>, world: Option<World>) -> Evm {
+    Evm::new(
+        code,
+        EvmConfig {
+            address: Some(CONTRACT),
+            world,
+            ..EvmConfig::default()
+        },
+    )
+}
+
+// A world where CALLEE has `code`
+pub fn callee_world(code: &str) -> World {
+    let mut world = World::default();
+    world.accounts.insert(
+        CALLEE,
+        Account {
+            code: assemble(code).unwrap(),
+            ..Account::default()
+        },
+    );
+    world
+}
+
+// CALL(GAS, CALLEE, 0, 0, 0, 0, out_len) followed by `tail`
+pub fn call_callee(out_len: usize, tail: &str) -> Vec<u8> {
+    assemble(&format!(
+        "PUSH1 {out_len} PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH20 {CALLEE:?} GAS CALL {tail}"
+    ))
+    .unwrap()
+}
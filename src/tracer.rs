@@ -0,0 +1,133 @@
+// EIP-3155 structured JSON traces, one line per executed instruction plus a summary.
+
+use primitive_types::U256;
+use serde_json::{json, Value};
+
+use crate::hexutil::hex;
+use crate::inspector::Inspector;
+use crate::machine::{Evm, EvmError, ExecutionResult};
+use crate::opcodes::opcode_name;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepTrace {
+    pub pc: usize,
+    pub op: u8,
+    pub gas: u64,
+    pub gas_cost: u64,
+    pub mem_size: usize,
+    pub stack: Vec<U256>,
+    pub depth: usize,
+    pub return_data: Vec<u8>,
+    pub refund: u64,
+    pub error: Option<String>,
+}
+
+impl StepTrace {
+    // Interpreter state before the instruction at `evm.pc` executes
+    pub fn capture(evm: &Evm, depth: usize) -> Self {
+        Self {
+            pc: evm.pc,
+            op: evm.code.get(evm.pc).copied().unwrap_or(0),
+            gas: evm.gas.max(0) as u64,
+            gas_cost: 0,
+            mem_size: evm.memory.len(),
+            stack: evm.stack.clone(),
+            depth,
+            return_data: evm.last_return_data.clone(),
+            refund: evm.refund.max(0) as u64,
+            error: None,
+        }
+    }
+
+    // Fill in the cost and error once the instruction has run
+    pub fn finish(&mut self, evm: &Evm, error: Option<&EvmError>) {
+        self.gas_cost = self.gas.saturating_sub(evm.gas.max(0) as u64);
+        self.error = error.map(|e| e.to_string());
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut v = json!({
+            "pc": self.pc,
+            "op": self.op,
+            "gas": format!("0x{:x}", self.gas),
+            "gasCost": format!("0x{:x}", self.gas_cost),
+            "memSize": self.mem_size,
+            "stack": self.stack.iter().map(|s| format!("0x{:x}", s)).collect::<Vec<_>>(),
+            "depth": self.depth,
+            "returnData": format!("0x{}", hex(&self.return_data)),
+            "refund": self.refund,
+            "opName": opcode_name(self.op),
+        });
+        if let Some(err) = &self.error {
+            v["error"] = err.clone().into();
+        }
+        v
+    }
+}
+
+// Collects EIP-3155 steps for every frame, in execution order. A step's line is
+// complete once its `step_end` fires, which for calls is after the child's steps.
+#[derive(Debug, Clone, Default)]
+pub struct Eip3155Tracer {
+    pub steps: Vec<StepTrace>,
+    pending: Vec<usize>,
+}
+
+impl Inspector for Eip3155Tracer {
+    fn step(&mut self, evm: &Evm) {
+        self.pending.push(self.steps.len());
+        // EIP-3155 depth starts at 1
+        self.steps.push(StepTrace::capture(evm, evm.depth + 1));
+    }
+
+    fn step_end(&mut self, evm: &Evm, error: Option<&EvmError>) {
+        if let Some(idx) = self.pending.pop() {
+            self.steps[idx].finish(evm, error);
+        }
+    }
+}
+
+// Final line of an EIP-3155 trace
+pub fn summary(result: &ExecutionResult) -> Value {
+    let mut v = json!({
+        "output": format!("0x{}", hex(result.output())),
+        "gasUsed": format!("0x{:x}", result.gas_used()),
+        "pass": result.is_success(),
+    });
+    match result {
+        ExecutionResult::Revert { .. } => v["error"] = "execution reverted".into(),
+        ExecutionResult::Halt { reason, .. } => v["error"] = reason.to_string().into(),
+        ExecutionResult::Success { .. } => {}
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::EvmConfig;
+
+    #[test]
+    fn traces_each_step() {
+        // PUSH1 1; PUSH1 2; ADD; STOP
+        let mut evm = Evm::new(
+            vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x00],
+            EvmConfig::default(),
+        );
+        let mut lines = Vec::new();
+        while evm.result().is_none() {
+            let mut step = StepTrace::capture(&evm, 1);
+            let res = evm.step();
+            step.finish(&evm, res.as_ref().err());
+            lines.push(step.to_json());
+        }
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2]["opName"], "ADD");
+        assert_eq!(lines[2]["stack"], json!(["0x1", "0x2"]));
+        assert_eq!(lines[2]["gasCost"], "0x3");
+        assert_eq!(lines[3]["pc"], 5);
+        let end = summary(&evm.result().unwrap());
+        assert_eq!(end["gasUsed"], "0x9");
+        assert_eq!(end["pass"], true);
+    }
+}
@@ -0,0 +1,812 @@
+use std::collections::HashSet;
+
+use primitive_types::{H160, H256, U256};
+use thiserror::Error;
+
+use crate::inspector::{Inspector, NoopInspector};
+use crate::machine::{
+    create_address, create_collision, deploy_checks, keccak256, precompile, Account, BlockEnv, Evm,
+    EvmConfig, EvmError, ExecutionResult, LogEntry, World, INITCODE_WORD_GAS, MAX_INITCODE_SIZE,
+};
+use crate::rlp;
+use crate::trie::u256_bytes;
+
+const TX_BASE_GAS: u64 = 21_000;
+const TX_CREATE_GAS: u64 = 32_000;
+const TX_DATA_ZERO_GAS: u64 = 4;
+const TX_DATA_NONZERO_GAS: u64 = 16;
+const TX_ACCESS_LIST_ADDRESS_GAS: u64 = 2_400;
+const TX_ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1_900;
+const MAX_REFUND_QUOTIENT: u64 = 5; // EIP-3529
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxKind {
+    Call(H160),
+    Create,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessListItem {
+    pub address: H160,
+    pub storage_keys: Vec<U256>,
+}
+
+// `v` is the full legacy value (27/28 or EIP-155) for type 0 and the y parity for typed transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub v: u64,
+    pub r: U256,
+    pub s: U256,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    // EIP-2718 type byte: 0 legacy, 1 access list (EIP-2930), 2 dynamic fee (EIP-1559)
+    pub tx_type: u8,
+    pub caller: H160,
+    pub nonce: u64,
+    pub gas_limit: u64,
+    // Legacy gas price, or max_fee_per_gas for EIP-1559 transactions
+    pub gas_price: U256,
+    // Only for EIP-1559 transactions
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub to: TxKind,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+    pub chain_id: Option<U256>,
+    // Only used for encoding; `caller` is trusted as the sender
+    pub signature: Option<Signature>,
+}
+
+impl Default for Transaction {
+    fn default() -> Self {
+        Self {
+            tx_type: 0,
+            caller: H160::zero(),
+            nonce: 0,
+            gas_limit: 10_000_000,
+            gas_price: U256::zero(),
+            max_priority_fee_per_gas: None,
+            to: TxKind::Create,
+            value: U256::zero(),
+            data: Vec::new(),
+            access_list: Vec::new(),
+            chain_id: None,
+            signature: None,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TxError {
+    #[error("nonce mismatch: account has {expected}, transaction has {got}")]
+    NonceMismatch { expected: u64, got: u64 },
+    #[error("insufficient funds: have {have}, need {need}")]
+    InsufficientFunds { have: U256, need: U256 },
+    #[error("intrinsic gas too low: limit {limit}, intrinsic {intrinsic}")]
+    IntrinsicGasTooLow { limit: u64, intrinsic: u64 },
+    #[error("init code size {0} exceeds limit {MAX_INITCODE_SIZE}")]
+    InitCodeTooLarge(usize),
+    #[error("fee cap {fee_cap} below base fee {basefee}")]
+    FeeCapTooLow { fee_cap: U256, basefee: U256 },
+    #[error("priority fee {priority_fee} above fee cap {fee_cap}")]
+    PriorityFeeTooHigh { priority_fee: U256, fee_cap: U256 },
+    #[error("chain id mismatch: block has {expected}, transaction has {got}")]
+    ChainIdMismatch { expected: U256, got: U256 },
+    #[error("sender {0:?} has deployed code")]
+    SenderNotEoa(H160),
+    #[error("unsupported transaction type {0}")]
+    UnsupportedType(u8),
+    #[error("fields do not match transaction type {tx_type}: {reason}")]
+    TypeMismatch { tx_type: u8, reason: &'static str },
+}
+
+#[derive(Debug, Clone)]
+pub struct TxResult {
+    // Gas figures are transaction-level: intrinsic gas included, refund applied
+    pub result: ExecutionResult,
+    // Set for contract-creation transactions, whether or not they succeeded
+    pub created_address: Option<H160>,
+}
+
+impl TxResult {
+    pub fn is_success(&self) -> bool {
+        self.result.is_success()
+    }
+
+    pub fn gas_used(&self) -> u64 {
+        self.result.gas_used()
+    }
+
+    pub fn logs(&self) -> &[LogEntry] {
+        self.result.logs()
+    }
+}
+
+impl Transaction {
+    pub fn is_eip1559(&self) -> bool {
+        self.tx_type == 2
+    }
+
+    // The fields a transaction carries must be the ones its type defines
+    pub fn check_type(&self) -> Result<(), TxError> {
+        let mismatch = |reason| {
+            Err(TxError::TypeMismatch {
+                tx_type: self.tx_type,
+                reason,
+            })
+        };
+        match self.tx_type {
+            0 | 1 if self.max_priority_fee_per_gas.is_some() => {
+                mismatch("unexpected maxPriorityFeePerGas")
+            }
+            0 if !self.access_list.is_empty() => mismatch("unexpected access list"),
+            2 if self.max_priority_fee_per_gas.is_none() => {
+                mismatch("missing maxPriorityFeePerGas")
+            }
+            0..=2 => Ok(()),
+            ty => Err(TxError::UnsupportedType(ty)),
+        }
+    }
+
+    pub fn intrinsic_gas(&self) -> u64 {
+        let mut gas = TX_BASE_GAS;
+        for b in &self.data {
+            gas += if *b == 0 {
+                TX_DATA_ZERO_GAS
+            } else {
+                TX_DATA_NONZERO_GAS
+            };
+        }
+        if self.to == TxKind::Create {
+            gas += TX_CREATE_GAS + INITCODE_WORD_GAS * (self.data.len() as u64).div_ceil(32);
+        }
+        for item in &self.access_list {
+            gas += TX_ACCESS_LIST_ADDRESS_GAS
+                + TX_ACCESS_LIST_STORAGE_KEY_GAS * item.storage_keys.len() as u64;
+        }
+        gas
+    }
+
+    // EIP-2718 envelope: rlp list for legacy, type byte || rlp list otherwise
+    pub fn encode(&self) -> Vec<u8> {
+        let sig = self.signature.unwrap_or(Signature {
+            v: 0,
+            r: U256::zero(),
+            s: U256::zero(),
+        });
+        let mut fields = self.unsigned_fields();
+        fields.push(rlp::encode_u64(sig.v));
+        fields.push(rlp::encode_u256(sig.r));
+        fields.push(rlp::encode_u256(sig.s));
+        self.envelope(rlp::encode_list(&fields))
+    }
+
+    // The hash that is signed: EIP-155 appends [chainId, 0, 0] to protected legacy transactions
+    pub fn signing_hash(&self) -> H256 {
+        let mut fields = self.unsigned_fields();
+        if let (0, Some(id)) = (self.tx_type, self.chain_id) {
+            fields.push(rlp::encode_u256(id));
+            fields.push(rlp::encode_u64(0));
+            fields.push(rlp::encode_u64(0));
+        }
+        keccak256(&self.envelope(rlp::encode_list(&fields)))
+    }
+
+    fn unsigned_fields(&self) -> Vec<Vec<u8>> {
+        let to = match self.to {
+            TxKind::Call(addr) => rlp::encode_bytes(addr.as_bytes()),
+            TxKind::Create => rlp::encode_bytes(&[]),
+        };
+        let access_list = rlp::encode_list(
+            &self
+                .access_list
+                .iter()
+                .map(|item| {
+                    let keys: Vec<Vec<u8>> = item
+                        .storage_keys
+                        .iter()
+                        .map(|k| rlp::encode_bytes(&u256_bytes(*k)))
+                        .collect();
+                    rlp::encode_list(&[
+                        rlp::encode_bytes(item.address.as_bytes()),
+                        rlp::encode_list(&keys),
+                    ])
+                })
+                .collect::<Vec<_>>(),
+        );
+        let chain_id = rlp::encode_u256(self.chain_id.unwrap_or_default());
+        match self.tx_type {
+            0 => vec![
+                rlp::encode_u64(self.nonce),
+                rlp::encode_u256(self.gas_price),
+                rlp::encode_u64(self.gas_limit),
+                to,
+                rlp::encode_u256(self.value),
+                rlp::encode_bytes(&self.data),
+            ],
+            1 => vec![
+                chain_id,
+                rlp::encode_u64(self.nonce),
+                rlp::encode_u256(self.gas_price),
+                rlp::encode_u64(self.gas_limit),
+                to,
+                rlp::encode_u256(self.value),
+                rlp::encode_bytes(&self.data),
+                access_list,
+            ],
+            _ => vec![
+                chain_id,
+                rlp::encode_u64(self.nonce),
+                rlp::encode_u256(self.max_priority_fee_per_gas.unwrap_or_default()),
+                rlp::encode_u256(self.gas_price),
+                rlp::encode_u64(self.gas_limit),
+                to,
+                rlp::encode_u256(self.value),
+                rlp::encode_bytes(&self.data),
+                access_list,
+            ],
+        }
+    }
+
+    fn envelope(&self, payload: Vec<u8>) -> Vec<u8> {
+        match self.tx_type {
+            0 => payload,
+            ty => {
+                let mut out = vec![ty];
+                out.extend(payload);
+                out
+            }
+        }
+    }
+
+    // Sign with `secret`, setting `caller` and `signature`. Legacy transactions are
+    // EIP-155 protected when `chain_id` is set.
+    pub fn sign(&mut self, secret: &[u8]) -> Option<()> {
+        let key = k256::ecdsa::SigningKey::from_slice(secret).ok()?;
+        let (sig, recid) = key
+            .sign_prehash_recoverable(self.signing_hash().as_bytes())
+            .ok()?;
+        let parity = recid.is_y_odd() as u64;
+        let v = match (self.tx_type, self.chain_id) {
+            (0, Some(id)) => parity + 35 + 2 * id.low_u64(),
+            (0, None) => parity + 27,
+            _ => parity,
+        };
+        let (r, s) = sig.split_bytes();
+        self.signature = Some(Signature {
+            v,
+            r: U256::from_big_endian(&r),
+            s: U256::from_big_endian(&s),
+        });
+        self.caller = secret_key_address(secret)?;
+        Some(())
+    }
+
+    // Recover the sender from `signature`. For legacy transactions `chain_id` must
+    // already match the one encoded in `v`.
+    pub fn recover_sender(&self) -> Option<H160> {
+        use k256::ecdsa::{RecoveryId, Signature as EcdsaSignature, VerifyingKey};
+        let sig = self.signature?;
+        let parity = match (self.tx_type, self.chain_id) {
+            (0, Some(id)) => sig.v.checked_sub(35 + 2 * id.low_u64())?,
+            (0, None) => sig.v.checked_sub(27)?,
+            _ => sig.v,
+        };
+        if parity > 1 {
+            return None;
+        }
+        let signature = EcdsaSignature::from_scalars(u256_bytes(sig.r), u256_bytes(sig.s)).ok()?;
+        let recid = RecoveryId::from_byte(parity as u8)?;
+        let key =
+            VerifyingKey::recover_from_prehash(self.signing_hash().as_bytes(), &signature, recid)
+                .ok()?;
+        let point = key.to_encoded_point(false);
+        let hash = keccak256(&point.as_bytes()[1..]);
+        Some(H160::from_slice(&hash.as_bytes()[12..]))
+    }
+
+    pub fn hash(&self) -> H256 {
+        keccak256(&self.encode())
+    }
+
+    pub fn effective_gas_price(&self, basefee: U256) -> Result<U256, TxError> {
+        if self.gas_price < basefee {
+            return Err(TxError::FeeCapTooLow {
+                fee_cap: self.gas_price,
+                basefee,
+            });
+        }
+        match self.max_priority_fee_per_gas {
+            Some(tip) if tip > self.gas_price => Err(TxError::PriorityFeeTooHigh {
+                priority_fee: tip,
+                fee_cap: self.gas_price,
+            }),
+            Some(tip) => Ok(self.gas_price.min(basefee.saturating_add(tip))),
+            None => Ok(self.gas_price),
+        }
+    }
+}
+
+// Address controlled by a secp256k1 secret key: the last 20 bytes of keccak(pubkey)
+pub fn secret_key_address(secret: &[u8]) -> Option<H160> {
+    let key = k256::ecdsa::SigningKey::from_slice(secret).ok()?;
+    let point = key.verifying_key().to_encoded_point(false);
+    let hash = keccak256(&point.as_bytes()[1..]);
+    Some(H160::from_slice(&hash.as_bytes()[12..]))
+}
+
+impl Evm {
+    // Validate and apply a transaction to `world`: buy gas, bump the sender nonce,
+    // execute, refund unused gas and pay the priority fee to the coinbase
+    pub fn transact(
+        world: &mut World,
+        block: &BlockEnv,
+        tx: &Transaction,
+    ) -> Result<TxResult, TxError> {
+        Self::transact_with(world, block, tx, &mut NoopInspector)
+    }
+
+    // Like `transact`, with `insp` observing the execution
+    pub fn transact_with(
+        world: &mut World,
+        block: &BlockEnv,
+        tx: &Transaction,
+        insp: &mut dyn Inspector,
+    ) -> Result<TxResult, TxError> {
+        tx.check_type()?;
+        if let Some(id) = tx.chain_id {
+            if id != block.chain_id {
+                return Err(TxError::ChainIdMismatch {
+                    expected: block.chain_id,
+                    got: id,
+                });
+            }
+        }
+        let gas_price = tx.effective_gas_price(block.basefee)?;
+        let intrinsic = tx.intrinsic_gas();
+        if tx.gas_limit < intrinsic {
+            return Err(TxError::IntrinsicGasTooLow {
+                limit: tx.gas_limit,
+                intrinsic,
+            });
+        }
+        // EIP-3860
+        if tx.to == TxKind::Create && tx.data.len() > MAX_INITCODE_SIZE {
+            return Err(TxError::InitCodeTooLarge(tx.data.len()));
+        }
+
+        let empty = Account::default();
+        let sender = world.accounts.get(&tx.caller).unwrap_or(&empty);
+        if !sender.code.is_empty() {
+            return Err(TxError::SenderNotEoa(tx.caller));
+        }
+        if sender.nonce != tx.nonce {
+            return Err(TxError::NonceMismatch {
+                expected: sender.nonce,
+                got: tx.nonce,
+            });
+        }
+        let max_cost = U256::from(tx.gas_limit)
+            .checked_mul(tx.gas_price)
+            .and_then(|c| c.checked_add(tx.value))
+            .unwrap_or(U256::MAX);
+        if sender.balance < max_cost {
+            return Err(TxError::InsufficientFunds {
+                have: sender.balance,
+                need: max_cost,
+            });
+        }
+        let sender = world.accounts.entry(tx.caller).or_default();
+        sender.balance -= U256::from(tx.gas_limit) * gas_price;
+        sender.nonce += 1;
+
+        // Execution failures roll back to here; the gas purchase and nonce bump stay.
+        let snapshot = world.clone();
+        let (address, code, calldata, created_address) = match tx.to {
+            TxKind::Call(to) => {
+                let code = world
+                    .accounts
+                    .get(&to)
+                    .map(|a| a.code.clone())
+                    .unwrap_or_default();
+                (to, code, tx.data.clone(), None)
+            }
+            TxKind::Create => {
+                let created = create_address(tx.caller, tx.nonce);
+                (created, tx.data.clone(), Vec::new(), Some(created))
+            }
+        };
+
+        let exec_gas = tx.gas_limit - intrinsic;
+        // Accounts touched by execution, kept only if it succeeds
+        let mut touched = HashSet::new();
+        let collision = created_address.is_some_and(|a| create_collision(world, a));
+        let outcome = if collision {
+            ExecutionResult::Halt {
+                reason: EvmError::CreateCollision,
+                gas_used: exec_gas,
+            }
+        } else {
+            world.accounts.entry(tx.caller).or_default().balance -= tx.value;
+            let recipient = world.accounts.entry(address).or_default();
+            recipient.balance += tx.value;
+            if created_address.is_some() {
+                recipient.nonce = 1;
+            }
+
+            let precompiled = match tx.to {
+                TxKind::Call(to) => precompile(to, &tx.data, exec_gas),
+                TxKind::Create => None,
+            };
+            touched.insert(address);
+            if let Some(result) = precompiled {
+                result
+            } else {
+                let mut evm = Evm::new(
+                    code,
+                    EvmConfig {
+                        gas_limit: exec_gas as i128,
+                        calldata,
+                        address: Some(address),
+                        caller: Some(tx.caller),
+                        origin: Some(tx.caller),
+                        value: tx.value,
+                        gas_price,
+                        block: block.clone(),
+                        world: Some(std::mem::take(world)),
+                    },
+                );
+                let result = evm.run_with(insp);
+                if result.is_success() {
+                    *world = evm.world.take().unwrap_or_default();
+                    touched.extend(evm.touched.drain());
+                }
+                result
+            }
+        };
+
+        let outcome = match created_address {
+            Some(created) => {
+                let outcome = deploy_checks(outcome, exec_gas);
+                if outcome.is_success() {
+                    world.accounts.entry(created).or_default().code = outcome.output().to_vec();
+                }
+                outcome
+            }
+            None => outcome,
+        };
+        if !outcome.is_success() {
+            *world = snapshot;
+            touched.clear();
+        }
+
+        // Re-express the frame outcome in transaction terms: intrinsic gas included,
+        // refund capped and subtracted.
+        let gas_used = intrinsic + outcome.gas_used();
+        let result = match outcome {
+            ExecutionResult::Success {
+                reason,
+                gas_refunded,
+                output,
+                logs,
+                ..
+            } => {
+                let gas_refunded = gas_refunded.min(gas_used / MAX_REFUND_QUOTIENT);
+                ExecutionResult::Success {
+                    reason,
+                    gas_used: gas_used - gas_refunded,
+                    gas_refunded,
+                    output,
+                    logs,
+                }
+            }
+            ExecutionResult::Revert { output, .. } => ExecutionResult::Revert { gas_used, output },
+            ExecutionResult::Halt { reason, .. } => ExecutionResult::Halt { reason, gas_used },
+        };
+        let gas_used = result.gas_used();
+
+        world.accounts.entry(tx.caller).or_default().balance +=
+            U256::from(tx.gas_limit - gas_used) * gas_price;
+        world.accounts.entry(block.coinbase).or_default().balance +=
+            U256::from(gas_used) * (gas_price - block.basefee);
+        // EIP-161: touched accounts left empty are removed from state
+        touched.extend([tx.caller, block.coinbase]);
+        for addr in touched {
+            if world.accounts.get(&addr).is_some_and(Account::is_empty) {
+                world.accounts.remove(&addr);
+            }
+        }
+
+        Ok(TxResult {
+            result,
+            created_address,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn funded_world(addr: H160, balance: u64) -> World {
+        let mut world = World::default();
+        world.accounts.insert(
+            addr,
+            Account {
+                balance: U256::from(balance),
+                ..Account::default()
+            },
+        );
+        world
+    }
+
+    #[test]
+    fn value_transfer_charges_fees() {
+        let sender = H160::from_low_u64_be(0xaa);
+        let to = H160::from_low_u64_be(0xbb);
+        let block = BlockEnv {
+            coinbase: H160::from_low_u64_be(0xcc),
+            basefee: U256::from(7),
+            ..BlockEnv::default()
+        };
+        let mut world = funded_world(sender, 1_000_000);
+        let tx = Transaction {
+            tx_type: 2,
+            caller: sender,
+            gas_limit: 30_000,
+            gas_price: U256::from(10),
+            max_priority_fee_per_gas: Some(U256::from(2)),
+            to: TxKind::Call(to),
+            value: U256::from(1_000),
+            ..Transaction::default()
+        };
+        let res = Evm::transact(&mut world, &block, &tx).unwrap();
+        assert!(res.is_success());
+        assert_eq!(res.gas_used(), 21_000);
+        // effective price = min(10, 7 + 2) = 9
+        assert_eq!(
+            world.accounts[&sender].balance,
+            U256::from(1_000_000 - 1_000 - 21_000 * 9)
+        );
+        assert_eq!(world.accounts[&sender].nonce, 1);
+        assert_eq!(world.accounts[&to].balance, U256::from(1_000));
+        assert_eq!(
+            world.accounts[&block.coinbase].balance,
+            U256::from(21_000 * 2)
+        );
+    }
+
+    #[test]
+    fn encodes_eip155_example() {
+        // Example transaction from EIP-155
+        let tx = Transaction {
+            nonce: 9,
+            gas_limit: 21_000,
+            gas_price: U256::from(20_000_000_000u64),
+            to: TxKind::Call(H160::repeat_byte(0x35)),
+            value: U256::from(1_000_000_000_000_000_000u64),
+            signature: Some(Signature {
+                v: 37,
+                r: U256::from_str_radix(
+                    "28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+                    16,
+                )
+                .unwrap(),
+                s: U256::from_str_radix(
+                    "67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+                    16,
+                )
+                .unwrap(),
+            }),
+            ..Transaction::default()
+        };
+        assert_eq!(
+            hex::encode(tx.encode()),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f\
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+    }
+
+    #[test]
+    fn signs_and_recovers_eip155_example() {
+        let mut tx = Transaction {
+            nonce: 9,
+            gas_limit: 21_000,
+            gas_price: U256::from(20_000_000_000u64),
+            to: TxKind::Call(H160::repeat_byte(0x35)),
+            value: U256::from(1_000_000_000_000_000_000u64),
+            chain_id: Some(U256::one()),
+            ..Transaction::default()
+        };
+        assert_eq!(
+            hex::encode(tx.signing_hash()),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+        tx.sign(&[0x46; 32]).unwrap();
+        let sig = tx.signature.unwrap();
+        assert_eq!(sig.v, 37);
+        assert_eq!(
+            sig.r,
+            U256::from_str_radix(
+                "28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+                16
+            )
+            .unwrap()
+        );
+        let sender =
+            H160::from_slice(&hex::decode("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").unwrap());
+        assert_eq!(tx.caller, sender);
+        assert_eq!(tx.recover_sender(), Some(sender));
+    }
+
+    #[test]
+    fn typed_transaction_keeps_its_type() {
+        // EIP-2930 with an empty access list is still type 1, not legacy
+        let tx = Transaction {
+            tx_type: 1,
+            chain_id: Some(U256::one()),
+            to: TxKind::Call(H160::repeat_byte(0x35)),
+            ..Transaction::default()
+        };
+        assert_eq!(tx.encode()[0], 1);
+        assert!(tx.check_type().is_ok());
+        let mut legacy = tx.clone();
+        legacy.tx_type = 0;
+        assert_ne!(legacy.signing_hash(), tx.signing_hash());
+
+        let bad = Transaction {
+            max_priority_fee_per_gas: Some(U256::one()),
+            ..tx
+        };
+        let err = Evm::transact(&mut World::default(), &BlockEnv::default(), &bad).unwrap_err();
+        assert_eq!(
+            err,
+            TxError::TypeMismatch {
+                tx_type: 1,
+                reason: "unexpected maxPriorityFeePerGas"
+            }
+        );
+        assert_eq!(
+            Transaction {
+                tx_type: 3,
+                ..Transaction::default()
+            }
+            .check_type(),
+            Err(TxError::UnsupportedType(3))
+        );
+    }
+
+    #[test]
+    fn removes_only_touched_empty_accounts() {
+        let sender = H160::from_low_u64_be(0xaa);
+        let (callee, untouched) = (H160::from_low_u64_be(0xbb), H160::from_low_u64_be(0xcc));
+        let mut world = funded_world(sender, 1_000_000);
+        world.accounts.insert(callee, Account::default());
+        world.accounts.insert(untouched, Account::default());
+        let tx = Transaction {
+            caller: sender,
+            gas_limit: 21_000,
+            to: TxKind::Call(callee),
+            ..Transaction::default()
+        };
+        assert!(Evm::transact(&mut world, &BlockEnv::default(), &tx)
+            .unwrap()
+            .is_success());
+        assert!(!world.accounts.contains_key(&callee));
+        assert!(world.accounts.contains_key(&untouched));
+    }
+
+    #[test]
+    fn precompile_call_charges_intrinsic_and_precompile_gas() {
+        let sender = H160::from_low_u64_be(0xaa);
+        let mut world = funded_world(sender, 1_000_000);
+        let tx = Transaction {
+            caller: sender,
+            gas_limit: 30_000,
+            gas_price: U256::one(),
+            to: TxKind::Call(H160::from_low_u64_be(4)),
+            data: vec![1; 33],
+            ..Transaction::default()
+        };
+        let res = Evm::transact(&mut world, &BlockEnv::default(), &tx).unwrap();
+        assert_eq!(res.result.output(), &[1; 33]);
+        // intrinsic 21000 + 33 * 16, identity 15 + 3 * 2 words
+        let used = 21_000 + 33 * 16 + 15 + 6;
+        assert_eq!(res.gas_used(), used);
+        assert_eq!(
+            world.accounts[&sender].balance,
+            U256::from(1_000_000 - used)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_nonce_without_touching_state() {
+        let sender = H160::from_low_u64_be(0xaa);
+        let mut world = funded_world(sender, 1_000_000);
+        let tx = Transaction {
+            caller: sender,
+            nonce: 3,
+            gas_limit: 21_000,
+            to: TxKind::Call(H160::zero()),
+            ..Transaction::default()
+        };
+        let err = Evm::transact(&mut world, &BlockEnv::default(), &tx).unwrap_err();
+        assert_eq!(
+            err,
+            TxError::NonceMismatch {
+                expected: 0,
+                got: 3
+            }
+        );
+        assert_eq!(world.accounts[&sender].nonce, 0);
+    }
+
+    #[test]
+    fn create_deploys_returned_code() {
+        let sender = H160::from_low_u64_be(0xaa);
+        let mut world = funded_world(sender, 0);
+        // PUSH1 1; PUSH1 0; RETURN -> deploys a single STOP byte
+        let tx = Transaction {
+            caller: sender,
+            gas_limit: 100_000,
+            data: vec![0x60, 0x01, 0x60, 0x00, 0xf3],
+            ..Transaction::default()
+        };
+        let res = Evm::transact(&mut world, &BlockEnv::default(), &tx).unwrap();
+        assert!(res.is_success());
+        let created = res.created_address.unwrap();
+        assert_eq!(created, create_address(sender, 0));
+        assert_eq!(world.accounts[&created].code, vec![0x00]);
+        assert_eq!(world.accounts[&created].nonce, 1);
+        assert_eq!(world.accounts[&sender].nonce, 1);
+    }
+
+    #[test]
+    fn rejects_oversized_init_code() {
+        let sender = H160::from_low_u64_be(0xaa);
+        let mut world = funded_world(sender, 10_000_000);
+        let tx = Transaction {
+            caller: sender,
+            gas_limit: 1_000_000,
+            data: vec![0; MAX_INITCODE_SIZE + 1],
+            ..Transaction::default()
+        };
+        let err = Evm::transact(&mut world, &BlockEnv::default(), &tx).unwrap_err();
+        assert_eq!(err, TxError::InitCodeTooLarge(MAX_INITCODE_SIZE + 1));
+        assert_eq!(world.accounts[&sender].nonce, 0);
+    }
+
+    #[test]
+    fn revert_undoes_value_but_keeps_fee() {
+        let sender = H160::from_low_u64_be(0xaa);
+        let to = H160::from_low_u64_be(0xbb);
+        let mut world = funded_world(sender, 1_000_000);
+        // PUSH1 0; PUSH1 0; REVERT
+        world.accounts.insert(
+            to,
+            Account {
+                code: vec![0x60, 0x00, 0x60, 0x00, 0xfd],
+                ..Account::default()
+            },
+        );
+        let tx = Transaction {
+            caller: sender,
+            gas_limit: 50_000,
+            gas_price: U256::one(),
+            to: TxKind::Call(to),
+            value: U256::from(500),
+            ..Transaction::default()
+        };
+        let res = Evm::transact(&mut world, &BlockEnv::default(), &tx).unwrap();
+        assert!(!res.is_success());
+        assert_eq!(world.accounts[&to].balance, U256::zero());
+        assert_eq!(
+            world.accounts[&sender].balance,
+            U256::from(1_000_000 - res.gas_used())
+        );
+        assert_eq!(world.accounts[&sender].nonce, 1);
+    }
+}
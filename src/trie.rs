@@ -0,0 +1,474 @@
+// Hexary Merkle Patricia Trie (yellow paper appendix D) plus the state and
+// storage tries built from `World`. The trie keeps its key/value pairs in a
+// sorted map and rebuilds the node structure when a root is requested.
+
+use std::collections::{BTreeMap, HashMap};
+
+use primitive_types::{H160, H256, U256};
+use thiserror::Error;
+
+use crate::machine::{keccak256, Account, World};
+use crate::rlp::{self, RlpError, RlpItem};
+
+// keccak256(rlp(""))
+pub const EMPTY_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProofError {
+    #[error("proof is missing node {0:?}")]
+    MissingNode(H256),
+    #[error("malformed proof node")]
+    MalformedNode,
+    #[error("invalid rlp in proof: {0}")]
+    Rlp(#[from] RlpError),
+    #[error("account {0:?} does not match its proof")]
+    AccountMismatch(H160),
+    #[error("storage slot 0x{0:x} does not match its proof")]
+    StorageMismatch(U256),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Trie {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf {
+        path: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Extension {
+        path: Vec<u8>,
+        child: Box<Node>,
+    },
+    Branch {
+        children: Vec<Option<Node>>,
+        value: Option<Vec<u8>>,
+    },
+}
+
+impl Trie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // An empty value deletes the key, as in the yellow paper.
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) {
+        if value.is_empty() {
+            self.entries.remove(key);
+        } else {
+            self.entries.insert(key.to_vec(), value);
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(|v| v.as_slice())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn root_hash(&self) -> H256 {
+        match self.build() {
+            Some(root) => keccak256(&root.encode()),
+            None => EMPTY_ROOT,
+        }
+    }
+
+    // Encoded nodes on the path from the root towards `key`; nodes embedded in
+    // their parent (shorter than 32 bytes) are not listed separately.
+    pub fn proof(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let Some(root) = self.build() else {
+            return Vec::new();
+        };
+        let nibbles = to_nibbles(key);
+        let mut out = vec![root.encode()];
+        let mut node = &root;
+        let mut depth = 0;
+        loop {
+            let next = match node {
+                Node::Leaf { .. } => break,
+                Node::Extension { path, child } => {
+                    if !nibbles[depth..].starts_with(path) {
+                        break;
+                    }
+                    depth += path.len();
+                    child.as_ref()
+                }
+                Node::Branch { children, .. } => {
+                    match nibbles
+                        .get(depth)
+                        .and_then(|n| children[*n as usize].as_ref())
+                    {
+                        Some(child) => {
+                            depth += 1;
+                            child
+                        }
+                        None => break,
+                    }
+                }
+            };
+            let enc = next.encode();
+            if enc.len() >= 32 {
+                out.push(enc);
+            }
+            node = next;
+        }
+        out
+    }
+
+    fn build(&self) -> Option<Node> {
+        let items: Vec<(Vec<u8>, &[u8])> = self
+            .entries
+            .iter()
+            .map(|(k, v)| (to_nibbles(k), v.as_slice()))
+            .collect();
+        build_node(&items, 0)
+    }
+}
+
+// `items` must be sorted by key; byte order and nibble order agree.
+fn build_node(items: &[(Vec<u8>, &[u8])], depth: usize) -> Option<Node> {
+    match items {
+        [] => return None,
+        [(key, value)] => {
+            return Some(Node::Leaf {
+                path: key[depth..].to_vec(),
+                value: value.to_vec(),
+            })
+        }
+        _ => {}
+    }
+    let first = &items[0].0[depth..];
+    let last = &items[items.len() - 1].0[depth..];
+    let shared = first
+        .iter()
+        .zip(last.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if shared > 0 {
+        return Some(Node::Extension {
+            path: first[..shared].to_vec(),
+            child: Box::new(build_node(items, depth + shared)?),
+        });
+    }
+    let mut value = None;
+    let mut rest = items;
+    if items[0].0.len() == depth {
+        value = Some(items[0].1.to_vec());
+        rest = &items[1..];
+    }
+    let mut children = Vec::with_capacity(16);
+    for nibble in 0..16u8 {
+        let start = rest.partition_point(|(k, _)| k[depth] < nibble);
+        let end = rest.partition_point(|(k, _)| k[depth] <= nibble);
+        children.push(build_node(&rest[start..end], depth + 1));
+    }
+    Some(Node::Branch { children, value })
+}
+
+impl Node {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Node::Leaf { path, value } => rlp::encode_list(&[
+                rlp::encode_bytes(&hex_prefix(path, true)),
+                rlp::encode_bytes(value),
+            ]),
+            Node::Extension { path, child } => rlp::encode_list(&[
+                rlp::encode_bytes(&hex_prefix(path, false)),
+                child.reference(),
+            ]),
+            Node::Branch { children, value } => {
+                let mut items: Vec<Vec<u8>> = children
+                    .iter()
+                    .map(|c| match c {
+                        Some(node) => node.reference(),
+                        None => rlp::encode_bytes(&[]),
+                    })
+                    .collect();
+                items.push(rlp::encode_bytes(value.as_deref().unwrap_or(&[])));
+                rlp::encode_list(&items)
+            }
+        }
+    }
+
+    // Nodes shorter than 32 bytes are embedded in their parent; others by hash.
+    fn reference(&self) -> Vec<u8> {
+        let enc = self.encode();
+        if enc.len() < 32 {
+            enc
+        } else {
+            rlp::encode_bytes(keccak256(&enc).as_bytes())
+        }
+    }
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | path[0]);
+        &path[1..]
+    } else {
+        out.push(flag << 4);
+        path
+    };
+    for pair in rest.chunks(2) {
+        out.push((pair[0] << 4) | pair[1]);
+    }
+    out
+}
+
+// Walk `proof` from `root` along `key`. Ok(None) is a valid proof of absence.
+pub fn verify_proof(
+    root: H256,
+    key: &[u8],
+    proof: &[Vec<u8>],
+) -> Result<Option<Vec<u8>>, ProofError> {
+    if root == EMPTY_ROOT && proof.is_empty() {
+        return Ok(None);
+    }
+    let nodes: HashMap<H256, &[u8]> = proof.iter().map(|n| (keccak256(n), n.as_slice())).collect();
+    let lookup = |hash: H256| -> Result<RlpItem, ProofError> {
+        let raw = nodes.get(&hash).ok_or(ProofError::MissingNode(hash))?;
+        Ok(rlp::decode(raw)?)
+    };
+    let resolve = |child: &RlpItem| -> Result<Option<RlpItem>, ProofError> {
+        match child {
+            RlpItem::Bytes(b) if b.is_empty() => Ok(None),
+            RlpItem::Bytes(b) if b.len() == 32 => lookup(H256::from_slice(b)).map(Some),
+            RlpItem::List(_) => Ok(Some(child.clone())),
+            _ => Err(ProofError::MalformedNode),
+        }
+    };
+
+    let nibbles = to_nibbles(key);
+    let mut depth = 0;
+    let mut item = lookup(root)?;
+    loop {
+        let RlpItem::List(fields) = &item else {
+            return Err(ProofError::MalformedNode);
+        };
+        let next = match fields.as_slice() {
+            [children @ .., RlpItem::Bytes(value)] if children.len() == 16 => {
+                let Some(n) = nibbles.get(depth) else {
+                    return Ok((!value.is_empty()).then(|| value.clone()));
+                };
+                depth += 1;
+                resolve(&children[*n as usize])?
+            }
+            [RlpItem::Bytes(encoded_path), rest] => {
+                let (path, leaf) = decode_hex_prefix(encoded_path)?;
+                if leaf {
+                    let RlpItem::Bytes(value) = rest else {
+                        return Err(ProofError::MalformedNode);
+                    };
+                    return Ok((nibbles[depth..] == path[..]).then(|| value.clone()));
+                }
+                if !nibbles[depth..].starts_with(&path) {
+                    return Ok(None);
+                }
+                depth += path.len();
+                Some(resolve(rest)?.ok_or(ProofError::MalformedNode)?)
+            }
+            _ => return Err(ProofError::MalformedNode),
+        };
+        match next {
+            Some(node) => item = node,
+            None => return Ok(None),
+        }
+    }
+}
+
+fn decode_hex_prefix(encoded: &[u8]) -> Result<(Vec<u8>, bool), ProofError> {
+    let first = *encoded.first().ok_or(ProofError::MalformedNode)?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(ProofError::MalformedNode);
+    }
+    let mut path = Vec::with_capacity(encoded.len() * 2);
+    if flag & 1 == 1 {
+        path.push(first & 0x0f);
+    }
+    path.extend(to_nibbles(&encoded[1..]));
+    Ok((path, flag & 2 == 2))
+}
+
+// Root of a trie keyed by rlp(index), as used for transactions and receipts.
+pub fn ordered_trie_root(items: &[Vec<u8>]) -> H256 {
+    let mut trie = Trie::new();
+    for (i, item) in items.iter().enumerate() {
+        trie.insert(&rlp::encode_u64(i as u64), item.clone());
+    }
+    trie.root_hash()
+}
+
+impl Account {
+    pub fn code_hash(&self) -> H256 {
+        keccak256(&self.code)
+    }
+
+    // Secure trie: keccak256(slot) -> rlp(value); zero slots are absent.
+    pub fn storage_trie(&self) -> Trie {
+        let mut trie = Trie::new();
+        for (slot, value) in &self.storage {
+            if value.is_zero() {
+                continue;
+            }
+            trie.insert(
+                keccak256(&u256_bytes(*slot)).as_bytes(),
+                rlp::encode_u256(*value),
+            );
+        }
+        trie
+    }
+
+    pub fn storage_root(&self) -> H256 {
+        self.storage_trie().root_hash()
+    }
+
+    // rlp([nonce, balance, storageRoot, codeHash])
+    pub fn rlp_encode(&self) -> Vec<u8> {
+        rlp::encode_list(&[
+            rlp::encode_u64(self.nonce),
+            rlp::encode_u256(self.balance),
+            rlp::encode_bytes(self.storage_root().as_bytes()),
+            rlp::encode_bytes(self.code_hash().as_bytes()),
+        ])
+    }
+}
+
+impl World {
+    // Secure trie: keccak256(address) -> rlp(account)
+    pub fn state_trie(&self) -> Trie {
+        let mut trie = Trie::new();
+        for (addr, acc) in &self.accounts {
+            trie.insert(keccak256(addr.as_bytes()).as_bytes(), acc.rlp_encode());
+        }
+        trie
+    }
+
+    pub fn state_root(&self) -> H256 {
+        self.state_trie().root_hash()
+    }
+}
+
+pub(crate) fn u256_bytes(v: U256) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    v.to_big_endian(&mut buf);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root_of(pairs: &[(&str, &str)]) -> H256 {
+        let mut trie = Trie::new();
+        for (k, v) in pairs {
+            trie.insert(k.as_bytes(), v.as_bytes().to_vec());
+        }
+        trie.root_hash()
+    }
+
+    #[test]
+    fn empty_root() {
+        assert_eq!(Trie::new().root_hash(), EMPTY_ROOT);
+        assert_eq!(keccak256(&rlp::encode_bytes(&[])), EMPTY_ROOT);
+        assert_eq!(World::default().state_root(), EMPTY_ROOT);
+        assert_eq!(ordered_trie_root(&[]), EMPTY_ROOT);
+    }
+
+    #[test]
+    fn matches_ethereum_trie_vectors() {
+        // ethereum/tests TrieTests/trieanyorder.json "dogs" and "puppy"
+        assert_eq!(
+            format!(
+                "{:?}",
+                root_of(&[
+                    ("doe", "reindeer"),
+                    ("dog", "puppy"),
+                    ("dogglesworth", "cat")
+                ])
+            ),
+            "0x8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                root_of(&[
+                    ("do", "verb"),
+                    ("horse", "stallion"),
+                    ("doge", "coin"),
+                    ("dog", "puppy")
+                ])
+            ),
+            "0x5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+        );
+    }
+
+    #[test]
+    fn proofs_verify_presence_and_absence() {
+        let mut trie = Trie::new();
+        for i in 0..200u64 {
+            let key = keccak256(&i.to_be_bytes());
+            trie.insert(key.as_bytes(), rlp::encode_u64(i + 1));
+        }
+        let root = trie.root_hash();
+        let present = keccak256(&42u64.to_be_bytes());
+        let proof = trie.proof(present.as_bytes());
+        assert_eq!(
+            verify_proof(root, present.as_bytes(), &proof).unwrap(),
+            Some(rlp::encode_u64(43))
+        );
+        let absent = keccak256(b"missing");
+        let proof = trie.proof(absent.as_bytes());
+        assert_eq!(verify_proof(root, absent.as_bytes(), &proof).unwrap(), None);
+        // Dropping a node must be detected.
+        let proof = trie.proof(present.as_bytes());
+        assert!(matches!(
+            verify_proof(root, present.as_bytes(), &proof[..proof.len() - 1]),
+            Err(ProofError::MissingNode(_))
+        ));
+    }
+
+    #[test]
+    fn proofs_through_embedded_nodes() {
+        let mut trie = Trie::new();
+        for (k, v) in [
+            ("do", "verb"),
+            ("dog", "puppy"),
+            ("doge", "coin"),
+            ("horse", "stallion"),
+        ] {
+            trie.insert(k.as_bytes(), v.as_bytes().to_vec());
+        }
+        let root = trie.root_hash();
+        for (k, v) in [("do", Some("verb")), ("doge", Some("coin")), ("dot", None)] {
+            let proof = trie.proof(k.as_bytes());
+            assert_eq!(
+                verify_proof(root, k.as_bytes(), &proof).unwrap(),
+                v.map(|v| v.as_bytes().to_vec()),
+                "key {k}"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_storage_does_not_change_root() {
+        let mut acc = Account::default();
+        acc.storage.insert(U256::one(), U256::zero());
+        assert_eq!(acc.storage_root(), EMPTY_ROOT);
+        acc.storage.insert(U256::one(), U256::from(2));
+        assert_ne!(acc.storage_root(), EMPTY_ROOT);
+    }
+}
@@ -0,0 +1,265 @@
+// Breakpoints and watchpoints for tools that drive the interpreter. Conditions
+// are checked before each instruction, so a watched write is caught before it
+// happens and its operands can still be changed.
+
+use primitive_types::{H160, U256};
+
+use crate::inspector::{Inspector, NoopInspector};
+use crate::machine::{Evm, ExecutionResult};
+use crate::opcodes::{
+    CALL, CALLCODE, CALLDATACOPY, CODECOPY, DELEGATECALL, EXTCODECOPY, MSTORE, MSTORE8,
+    RETURNDATACOPY, SSTORE, STATICCALL,
+};
+use crate::trie::u256_bytes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Pc(usize),
+    Opcode(u8),
+    // SSTORE to `slot` while running against `address`
+    StorageWrite { address: H160, slot: U256 },
+    // Any instruction writing memory that overlaps [offset, offset + len)
+    MemoryWrite { offset: usize, len: usize },
+    // CALL/CALLCODE/DELEGATECALL/STATICCALL to this address
+    CallTo(H160),
+}
+
+impl Condition {
+    // Whether the instruction about to run at `evm.pc` meets the condition
+    pub fn matches(&self, evm: &Evm) -> bool {
+        let op = evm.code.get(evm.pc).copied().unwrap_or(0);
+        let arg = |i: usize| evm.stack.len().checked_sub(i + 1).map(|j| evm.stack[j]);
+        match *self {
+            Condition::Pc(pc) => evm.pc == pc,
+            Condition::Opcode(o) => op == o,
+            Condition::StorageWrite { address, slot } => {
+                op == SSTORE && evm.address == Some(address) && arg(0) == Some(slot)
+            }
+            Condition::MemoryWrite { offset, len } => {
+                memory_write(evm).is_some_and(|(start, size)| {
+                    size > 0
+                        && len > 0
+                        && start < offset.saturating_add(len)
+                        && offset < start + size
+                })
+            }
+            Condition::CallTo(to) => {
+                matches!(op, CALL | CALLCODE | DELEGATECALL | STATICCALL)
+                    && arg(1).is_some_and(|a| H160::from_slice(&u256_bytes(a)[12..]) == to)
+            }
+        }
+    }
+}
+
+// Memory range the instruction at `evm.pc` writes, as (offset, size)
+fn memory_write(evm: &Evm) -> Option<(usize, usize)> {
+    let arg = |i: usize| {
+        let j = evm.stack.len().checked_sub(i + 1)?;
+        Some(evm.stack[j].min(U256::from(usize::MAX / 2)).as_usize())
+    };
+    match evm.code.get(evm.pc).copied()? {
+        MSTORE => Some((arg(0)?, 32)),
+        MSTORE8 => Some((arg(0)?, 1)),
+        CALLDATACOPY | CODECOPY | RETURNDATACOPY => Some((arg(0)?, arg(2)?)),
+        EXTCODECOPY => Some((arg(1)?, arg(3)?)),
+        // The output buffer
+        CALL | CALLCODE => Some((arg(5)?, arg(6)?)),
+        DELEGATECALL | STATICCALL => Some((arg(4)?, arg(5)?)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit {
+    // Id returned by `Watchpoints::add`
+    pub id: usize,
+    pub depth: usize,
+    pub pc: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints {
+    // Indexed by id; removed conditions leave a None so ids stay stable
+    conditions: Vec<Option<Condition>>,
+    // The last `run_until` paused, so the next one first runs that instruction
+    paused: bool,
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, condition: Condition) -> usize {
+        self.conditions.push(Some(condition));
+        self.conditions.len() - 1
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Condition> {
+        self.conditions.get_mut(id)?.take()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Condition)> + '_ {
+        self.conditions
+            .iter()
+            .enumerate()
+            .filter_map(|(id, c)| Some((id, (*c)?)))
+    }
+
+    // The first condition met before the instruction at `evm.pc`
+    pub fn check(&self, evm: &Evm) -> Option<Hit> {
+        self.iter()
+            .find(|(_, c)| c.matches(evm))
+            .map(|(id, _)| Hit {
+                id,
+                depth: evm.depth,
+                pc: evm.pc,
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RunState {
+    // Stopped before the instruction at `hit.pc`; call `run_until` again to resume
+    Paused(Hit),
+    Finished(ExecutionResult),
+}
+
+impl Evm {
+    // Runs this frame until a watchpoint matches one of its instructions and
+    // hands control back. Calls it makes run to completion inside one step;
+    // use a `Watcher` to stop inside them.
+    pub fn run_until(&mut self, watch: &mut Watchpoints) -> RunState {
+        self.run_until_with(watch, &mut NoopInspector)
+    }
+
+    pub fn run_until_with(
+        &mut self,
+        watch: &mut Watchpoints,
+        insp: &mut dyn Inspector,
+    ) -> RunState {
+        let mut resumed = std::mem::take(&mut watch.paused);
+        let run = self.run_until_pause(insp, |evm| {
+            if std::mem::take(&mut resumed) {
+                return None;
+            }
+            watch.check(evm)
+        });
+        match run {
+            Ok(result) => RunState::Finished(result),
+            Err(hit) => {
+                watch.paused = true;
+                RunState::Paused(hit)
+            }
+        }
+    }
+}
+
+// Inspector calling `on_hit` with write access to the frame, at any depth,
+// whenever a watchpoint matches
+pub struct Watcher<'a, F> {
+    pub watch: &'a Watchpoints,
+    on_hit: F,
+}
+
+impl<'a, F: FnMut(&mut Evm, Hit)> Watcher<'a, F> {
+    pub fn new(watch: &'a Watchpoints, on_hit: F) -> Self {
+        Self { watch, on_hit }
+    }
+}
+
+impl<F: FnMut(&mut Evm, Hit)> Inspector for Watcher<'_, F> {
+    fn interrupt(&mut self, evm: &mut Evm) {
+        if let Some(hit) = self.watch.check(evm) {
+            (self.on_hit)(evm, hit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{EvmError, World};
+    use crate::testutil::{call_callee, callee_world, evm, CALLEE, CONTRACT};
+
+    #[test]
+    fn pauses_and_resumes_with_modified_state() {
+        // MSTORE(0, 1); SSTORE(1, 7); SSTORE(2, 8); STOP
+        let code = vec![
+            0x60, 0x01, 0x60, 0x00, 0x52, 0x60, 0x07, 0x60, 0x01, 0x55, 0x60, 0x08, 0x60, 0x02,
+            0x55, 0x00,
+        ];
+        let mut evm = evm(code, Some(World::default()));
+        let mut watch = Watchpoints::default();
+        let mem = watch.add(Condition::MemoryWrite { offset: 31, len: 1 });
+        let slot = watch.add(Condition::StorageWrite {
+            address: CONTRACT,
+            slot: U256::from(1),
+        });
+        let pc = watch.add(Condition::Pc(0x0e));
+        let RunState::Paused(hit) = evm.run_until(&mut watch) else {
+            panic!("expected a pause")
+        };
+        assert_eq!((hit.id, hit.pc), (mem, 4));
+        let RunState::Paused(hit) = evm.run_until(&mut watch) else {
+            panic!("expected a pause")
+        };
+        assert_eq!((hit.id, hit.pc), (slot, 9));
+        // Store 0x2a instead of 7
+        let top = evm.stack.len() - 2;
+        evm.stack[top] = U256::from(0x2a);
+        assert_eq!(watch.remove(pc), Some(Condition::Pc(0x0e)));
+        let RunState::Finished(result) = evm.run_until(&mut watch) else {
+            panic!("expected the run to finish")
+        };
+        assert!(result.is_success());
+        let storage = &evm.world.as_ref().unwrap().accounts[&CONTRACT].storage;
+        assert_eq!(storage[&U256::from(1)], U256::from(0x2a));
+        assert_eq!(storage[&U256::from(2)], U256::from(8));
+    }
+
+    #[test]
+    fn stays_finished_after_an_exceptional_halt() {
+        // PUSH1 1; INVALID; PUSH1 2
+        let mut evm = evm(vec![0x60, 0x01, 0xfe, 0x60, 0x02], None);
+        let mut watch = Watchpoints::default();
+        watch.add(Condition::Pc(2));
+        assert!(matches!(evm.run_until(&mut watch), RunState::Paused(_)));
+        // The failed instruction is not run, or watched, again
+        for _ in 0..2 {
+            let RunState::Finished(result) = evm.run_until(&mut watch) else {
+                panic!("expected the run to finish")
+            };
+            assert!(matches!(
+                result,
+                ExecutionResult::Halt {
+                    reason: EvmError::InvalidOpcode(0xfe, 2),
+                    ..
+                }
+            ));
+        }
+        assert_eq!(evm.pc, 2);
+    }
+
+    #[test]
+    fn watcher_stops_inside_child_frames() {
+        let world = callee_world("PUSH1 7 PUSH1 1 SSTORE STOP");
+        let mut evm = evm(call_callee(0, "STOP"), Some(world));
+        let mut watch = Watchpoints::default();
+        let call = watch.add(Condition::CallTo(CALLEE));
+        let store = watch.add(Condition::StorageWrite {
+            address: CALLEE,
+            slot: U256::from(1),
+        });
+        let mut hits = Vec::new();
+        let mut watcher = Watcher::new(&watch, |evm: &mut Evm, hit: Hit| {
+            hits.push(hit);
+            if hit.id == store {
+                let top = evm.stack.len() - 2;
+                evm.stack[top] = U256::from(9);
+            }
+        });
+        assert!(evm.run_with(&mut watcher).is_success());
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].id, hits[0].depth), (call, 0));
+        assert_eq!((hits[1].id, hits[1].depth, hits[1].pc), (store, 1, 4));
+        let storage = &evm.world.as_ref().unwrap().accounts[&CALLEE].storage;
+        assert_eq!(storage[&U256::from(1)], U256::from(9));
+    }
+}
@@ -37,6 +37,124 @@ fn evm_disasm_basic() {
     assert!(stdout.contains("0000: STOP"), "stdout={stdout}");
 }
 
+#[test]
+fn evm_disasm_json_and_effects() {
+    // PUSH1 0; JUMPDEST; RETURN; then data 0xdead
+    let out = Command::new(evm_bin())
+        .args(["disasm", "0x60005bf3dead", "--json", "--effects"])
+        .output()
+        .expect("run evm disasm --json");
+    assert!(out.status.success());
+    let v: serde_json::Value = serde_json::from_slice(&out.stdout).expect("json output");
+    let ins = v["instructions"].as_array().unwrap();
+    assert_eq!(ins.len(), 3);
+    assert_eq!(ins[0]["mnemonic"], "PUSH1");
+    assert_eq!(ins[0]["immediate"], "0x00");
+    assert_eq!(ins[2]["pops"], 2);
+    assert_eq!(ins[2]["dynamicGas"], true);
+    assert_eq!(v["data"]["offset"], 4);
+    assert_eq!(v["data"]["bytes"], "0xdead");
+
+    let out = Command::new(evm_bin())
+        .args(["disasm", "0x60005bf3dead", "--effects"])
+        .output()
+        .expect("run evm disasm --effects");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("; 0 -> 1, gas 3"), "stdout={stdout}");
+    assert!(stdout.contains("0004: 0xdead ; data"), "stdout={stdout}");
+}
+
+#[test]
+fn evm_cfg_dot_and_json() {
+    // PUSH1 0; CALLDATALOAD; PUSH1 6; JUMPI; JUMPDEST; STOP
+    let out = Command::new(evm_bin())
+        .args(["cfg", "0x6000356006575b00"])
+        .output()
+        .expect("run evm cfg");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("digraph cfg {"), "stdout={stdout}");
+    assert!(stdout.contains("b0000 -> b0006;"), "stdout={stdout}");
+    assert!(
+        stdout.contains("b0000 -> b0006 [color=darkgreen label=\"taken\"];"),
+        "stdout={stdout}"
+    );
+
+    let out = Command::new(evm_bin())
+        .args(["cfg", "0x6000356006575b00", "--json"])
+        .output()
+        .expect("run evm cfg --json");
+    assert!(out.status.success());
+    let v: serde_json::Value = serde_json::from_slice(&out.stdout).expect("cfg json");
+    assert_eq!(v["blocks"].as_array().unwrap().len(), 2);
+    assert_eq!(v["edges"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn evm_analyze_reports_findings() {
+    // JUMPI to a JUMPDEST whose ADD underflows; the rest is dead
+    let out = Command::new(evm_bin())
+        .args(["analyze", "0x6000356006575b01005b00"])
+        .output()
+        .expect("run evm analyze");
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("blocks: 3"), "stdout={stdout}");
+    assert!(
+        stdout.contains("0007: stack underflow: needs 2 items, has 0 on some path"),
+        "stdout={stdout}"
+    );
+    assert!(
+        stdout.contains("0009: unreachable code up to 000b"),
+        "stdout={stdout}"
+    );
+
+    // PUSH1 1; PUSH1 2; ADD; STOP
+    let out = Command::new(evm_bin())
+        .args(["analyze", "0x600160020100", "--json"])
+        .output()
+        .expect("run evm analyze --json");
+    assert!(out.status.success());
+    let v: serde_json::Value = serde_json::from_slice(&out.stdout).expect("analysis json");
+    assert_eq!(v["findings"].as_array().unwrap().len(), 0);
+    assert_eq!(v["blocks"][0]["maxGrowth"], 2);
+}
+
+#[test]
+fn evm_selectors_from_world_with_db() {
+    // PUSH4 sel; PUSH1 0; CALLDATALOAD; EQ; PUSH2 0x000f; JUMPI; STOP; STOP; JUMPDEST; STOP
+    let world = write_temp_text(
+        "selectors_world",
+        r#"{"accounts": {"0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa": {
+            "code": "0x63a9059cbb6000351461000f5700005b00"
+        }}}"#,
+    );
+    let db = write_temp_text("selectors_db", "0xa9059cbb transfer(address,uint256)\n");
+    let out = Command::new(evm_bin())
+        .args([
+            "selectors",
+            "--world",
+            world.to_str().unwrap(),
+            "--address",
+            "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "--db",
+            db.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run evm selectors");
+    assert!(
+        out.status.success(),
+        "stderr={}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("selectors: 1"), "stdout={stdout}");
+    assert!(
+        stdout.contains("0xa9059cbb entry=0x000f transfer(address,uint256)"),
+        "stdout={stdout}"
+    );
+}
+
 #[test]
 fn evm_run_simple_add() {
     // PUSH1 0x01; PUSH1 0x01; ADD; STOP
@@ -66,6 +184,38 @@ fn evm_run_with_code_from_file() {
     assert!(stdout.contains("top: 0x2"), "stdout={stdout}");
 }
 
+#[test]
+fn evm_asm_and_run_easm_file() {
+    let src = "PUSH1 0x03\nJUMPDEST @loop ; count down\nPUSH1 1 SUB DUP1 PUSH @loop JUMPI\nSTOP\n";
+    let path = std::env::temp_dir().join(format!("evm_loop_{}.easm", std::process::id()));
+    fs::write(&path, src).expect("write easm file");
+    let path = path.display().to_string();
+
+    let out = Command::new(evm_bin())
+        .args(["asm", &path])
+        .output()
+        .expect("run evm asm");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(stdout.trim(), "0x60035b6001038060025700");
+
+    let out = Command::new(evm_bin())
+        .args(["run", &path])
+        .output()
+        .expect("run evm run with easm");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("halted: STOP"), "stdout={stdout}");
+    assert!(stdout.contains("stack size: 1"), "stdout={stdout}");
+
+    let out = Command::new(evm_bin())
+        .args(["asm", "PUSH @missing"])
+        .output()
+        .expect("run evm asm");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("undefined label @missing"));
+}
+
 #[test]
 fn evm_run_dump_stack() {
     let out = Command::new(evm_bin())
@@ -246,6 +396,30 @@ fn evm_run_world_dump_to_file() {
     assert!(v.get("accounts").is_some());
 }
 
+#[test]
+fn evm_run_dump_world_includes_state_root() {
+    let world_json = r#"{ "accounts": {} }"#;
+    let world_path = write_temp_text("evm_world_root", world_json);
+    let out = Command::new(evm_bin())
+        .args([
+            "run",
+            "0x00",
+            "--world",
+            world_path.to_str().unwrap(),
+            "--dump-world",
+        ])
+        .output()
+        .expect("run evm run dump-world state root");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains(
+            "\"stateRoot\": \"0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421\""
+        ),
+        "stdout={stdout}"
+    );
+}
+
 #[test]
 fn evm_run_logs_prints_count() {
     // Build code to write 1 byte to memory and LOG0 that byte.
@@ -328,3 +502,592 @@ fn evm_run_binary_simple() {
     assert!(stdout.contains("stack size: 1"));
     assert!(stdout.contains("top: 0x2"));
 }
+
+#[test]
+fn evm_proof_verifies_against_state_root() {
+    let addr = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    let world_json = format!(
+        "{{ \"accounts\": {{ \"{addr}\": {{ \"balance\": \"0x10\", \"code\": \"0x6000\", \"storage\": {{ \"0x1\": \"0x2a\" }} }}, \"0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\": {{ \"balance\": \"0x1\" }} }} }}"
+    );
+    let world_path = write_temp_text("evm_world_proof", &world_json);
+    let out = Command::new(evm_bin())
+        .args([
+            "proof",
+            "--world",
+            world_path.to_str().unwrap(),
+            "--address",
+            addr,
+            "--slot",
+            "0x1",
+            "--slot",
+            "0x2",
+        ])
+        .output()
+        .expect("run evm proof");
+    assert!(out.status.success());
+    let v: serde_json::Value = serde_json::from_slice(&out.stdout).expect("proof json");
+    assert_eq!(v["balance"], "0x10");
+    assert_eq!(v["storageProof"][0]["value"], "0x2a");
+    assert_eq!(v["storageProof"][1]["value"], "0x0");
+    let proof = evm_in_rust::AccountProof::from_json(&v).expect("parse proof");
+    let root = evm_in_rust::hexutil::parse_h256(v["stateRoot"].as_str().unwrap()).unwrap();
+    proof.verify(root).expect("proof verifies");
+}
+
+#[test]
+fn evm_statetest_reports_summary() {
+    // Sender is the well-known test key 0x45a9..f2d8; the transaction is rejected
+    // (gas limit below intrinsic gas) so the expected root is the pre-state root.
+    let fixture = r#"{
+  "rejected": {
+    "env": { "currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba", "currentGasLimit": "0x0f4240",
+             "currentNumber": "0x01", "currentTimestamp": "0x03e8", "currentBaseFee": "0x0a" },
+    "pre": {},
+    "transaction": { "data": ["0x"], "gasLimit": ["0x01"], "gasPrice": "0x0a", "nonce": "0x00",
+                     "secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+                     "to": "0x095e7baea6a6c7c4c2dfeb977efac326af552d87", "value": ["0x00"] },
+    "post": { "Cancun": [ { "hash": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+                            "logs": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+                            "indexes": { "data": 0, "gas": 0, "value": 0 }, "expectException": "TR_IntrinsicGas" } ] }
+  }
+}"#;
+    let path = write_temp_text("evm_statetest", fixture);
+    let out = Command::new(evm_bin())
+        .args(["statetest", path.to_str().unwrap()])
+        .output()
+        .expect("run evm statetest");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "stdout={stdout}");
+    let v: serde_json::Value = serde_json::from_slice(&out.stdout).expect("statetest json");
+    assert_eq!(v["passed"], 1);
+    assert_eq!(v["failed"], 0);
+    assert_eq!(v["results"][0]["name"], "rejected");
+    assert_eq!(v["results"][0]["fork"], "Cancun");
+
+    let out = Command::new(evm_bin())
+        .args(["statetest", path.to_str().unwrap(), "--fork", "Berlin"])
+        .output()
+        .expect("run evm statetest");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unsupported fork Berlin"));
+}
+
+#[test]
+fn evm_t8n_writes_result_and_alloc() {
+    let dir = std::env::temp_dir().join(format!("evm_t8n_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("alloc.json"),
+        r#"{ "0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b": { "balance": "0x0de0b6b3a7640000", "nonce": "0x0" } }"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("env.json"),
+        r#"{ "currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba", "currentGasLimit": "0x0f4240",
+             "currentNumber": "0x1", "currentTimestamp": "0x3e8", "currentBaseFee": "0xa" }"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("txs.json"),
+        r#"[ { "type": "0x0", "nonce": "0x0", "gas": "0x5208", "gasPrice": "0xa", "value": "0x1",
+               "to": "0x00000000000000000000000000000000000000bb", "input": "0x",
+               "secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8" } ]"#,
+    )
+    .unwrap();
+    let out = Command::new(evm_bin())
+        .current_dir(&dir)
+        .args(["t8n", "--state.fork", "Cancun", "--output.result", "stdout"])
+        .output()
+        .expect("run evm t8n");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(out.status.success(), "stderr={stderr}");
+    let v: serde_json::Value = serde_json::from_slice(&out.stdout).expect("t8n json");
+    assert_eq!(v["result"]["gasUsed"], "0x5208");
+    assert_eq!(v["result"]["receipts"][0]["status"], "0x1");
+    let alloc: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("alloc.json")).unwrap()).unwrap();
+    assert_eq!(
+        alloc["0x00000000000000000000000000000000000000bb"]["balance"],
+        "0x1"
+    );
+
+    let out = Command::new(evm_bin())
+        .current_dir(&dir)
+        .args(["t8n", "--state.fork", "London"])
+        .output()
+        .expect("run evm t8n unimplemented fork");
+    assert_eq!(out.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unsupported fork London"));
+}
+
+#[test]
+fn evm_trace_json_emits_eip3155_lines() {
+    let out = Command::new(evm_bin())
+        .args(["trace", "0x600160020100", "--json"]) // PUSH1 1; PUSH1 2; ADD; STOP
+        .output()
+        .expect("run evm trace --json");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("json line"))
+        .collect();
+    assert_eq!(lines.len(), 5, "stdout={stdout}");
+    assert_eq!(lines[0]["opName"], "PUSH1");
+    assert_eq!(lines[0]["gas"], "0x989680");
+    assert_eq!(lines[2]["stack"], serde_json::json!(["0x1", "0x2"]));
+    assert_eq!(lines[2]["depth"], 1);
+    assert_eq!(lines[4]["gasUsed"], "0x9");
+    assert_eq!(lines[4]["pass"], true);
+}
+
+#[test]
+fn evm_run_call_trace_prints_call_tree() {
+    // Callee emits an empty LOG0 and stops
+    let world_json = r#"{
+        "accounts": {
+            "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb": { "balance": "0x0", "code": "0x60006000a000" }
+        }
+    }"#;
+    let world_path = write_temp_text("evm_call_trace_world", world_json);
+    // CALL 0xbb..bb with gas 0xffff and no args; STOP
+    let code = format!("0x6000600060006000600073{}61fffff100", "bb".repeat(20));
+    let out = Command::new(evm_bin())
+        .args([
+            "run",
+            &code,
+            "--world",
+            world_path.to_str().unwrap(),
+            "--address",
+            "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "--call-trace",
+            "--with-log",
+        ])
+        .output()
+        .expect("run evm run --call-trace");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let tree: serde_json::Value = serde_json::from_str(&stdout).expect("json tree");
+    assert_eq!(tree["type"], "CALL");
+    assert_eq!(tree["to"], "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+    let call = &tree["calls"][0];
+    assert_eq!(call["type"], "CALL");
+    assert_eq!(call["from"], "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+    assert_eq!(call["to"], "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb");
+    assert_eq!(call["gas"], "0xffff");
+    assert_eq!(call["logs"][0]["address"], call["to"]);
+    assert!(call.get("error").is_none());
+}
+
+#[test]
+fn evm_run_prestate_and_diff_mode() {
+    let world_json = r#"{
+        "accounts": {
+            "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa": {
+                "balance": "0x01",
+                "code": "0x",
+                "storage": { "0x01": "0x02", "0x05": "0x06" }
+            }
+        }
+    }"#;
+    let world_path = write_temp_text("evm_prestate_world", world_json);
+    let slot1 = format!("0x{:064x}", 1);
+    // SSTORE(1, 0x2a); STOP
+    let run = |extra: &[&str]| {
+        let mut args = vec![
+            "run",
+            "0x602a600155",
+            "--world",
+            world_path.to_str().unwrap(),
+            "--address",
+            "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "--prestate",
+        ];
+        args.extend_from_slice(extra);
+        let out = Command::new(evm_bin())
+            .args(&args)
+            .output()
+            .expect("run evm run --prestate");
+        assert!(out.status.success());
+        serde_json::from_slice::<serde_json::Value>(&out.stdout).expect("json")
+    };
+    let pre = run(&[]);
+    let acc = &pre["0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"];
+    assert_eq!(acc["balance"], "0x1");
+    // Only the touched slot is reported
+    assert_eq!(acc["storage"].as_object().unwrap().len(), 1);
+    assert_eq!(acc["storage"][&slot1], format!("0x{:064x}", 2));
+
+    let diff = run(&["--diff-mode"]);
+    let post = &diff["post"]["0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"];
+    assert_eq!(post["storage"][&slot1], format!("0x{:064x}", 0x2a));
+    assert!(post.get("balance").is_none());
+    assert_eq!(
+        diff["pre"]["0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"]["storage"][&slot1],
+        format!("0x{:064x}", 2)
+    );
+}
+
+#[test]
+fn evm_profile_ranks_opcodes_and_writes_folded_stacks() {
+    let world_json = r#"{
+        "accounts": {
+            "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb": { "balance": "0x0", "code": "0x602a60015500" }
+        }
+    }"#;
+    let world_path = write_temp_text("evm_profile_world", world_json);
+    let folded_path =
+        std::env::temp_dir().join(format!("evm_profile_{}.folded", std::process::id()));
+    // CALL 0xbb..bb, which does SSTORE(1, 0x2a)
+    let code = format!("0x6000600060006000600073{}61fffff100", "bb".repeat(20));
+    let out = Command::new(evm_bin())
+        .args([
+            "profile",
+            &code,
+            "--world",
+            world_path.to_str().unwrap(),
+            "--address",
+            "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "--folded",
+            folded_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run evm profile");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    // SSTORE is the most expensive opcode, ahead of CALL
+    let first_row = stdout
+        .lines()
+        .skip_while(|l| !l.starts_with("opcode"))
+        .nth(1)
+        .unwrap_or_default();
+    assert!(first_row.starts_with("SSTORE"), "stdout={stdout}");
+    assert!(stdout.contains("  CALL 0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"));
+    let folded = fs::read_to_string(&folded_path).expect("folded file");
+    let total: u64 = folded
+        .lines()
+        .map(|l| l.rsplit(' ').next().unwrap().parse::<u64>().unwrap())
+        .sum();
+    assert!(
+        stdout.contains(&format!("gas used: {total}")),
+        "stdout={stdout}"
+    );
+    assert!(folded.contains(&format!(
+        "CALL 0x{};CALL 0x{};SSTORE 20000",
+        "aa".repeat(20),
+        "bb".repeat(20)
+    )));
+    let _ = fs::remove_file(folded_path);
+}
+
+#[test]
+fn evm_coverage_reports_branches_and_writes_lcov() {
+    // CALLDATASIZE; PUSH1 7; JUMPI; PUSH1 1; STOP; JUMPDEST; PUSH1 2; STOP
+    let code = "0x366007576001005b600200";
+    let source_path = write_temp_text("evm_coverage_src", "a\nb\nc\n");
+    let lcov_path = std::env::temp_dir().join(format!("evm_coverage_{}.info", std::process::id()));
+    let out = Command::new(evm_bin())
+        .args([
+            "coverage",
+            code,
+            "--input",
+            "0x",
+            "--srcmap",
+            "0:1:0;;;;2:1;;4:1",
+            "--source",
+            source_path.to_str().unwrap(),
+            "--lcov",
+            lcov_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run evm coverage");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("instructions 5/8 (62.50%), branches 1/2 (50.00%)"),
+        "stdout={stdout}"
+    );
+    assert!(
+        stdout.contains("missed pcs: 0007 0008 000a"),
+        "stdout={stdout}"
+    );
+    let lcov = fs::read_to_string(&lcov_path).expect("lcov file");
+    assert!(lcov.contains("BRDA:1,3,0,1\nBRDA:1,3,1,0\n"), "lcov={lcov}");
+    assert!(lcov.contains("DA:3,0\n"), "lcov={lcov}");
+
+    // A second input takes the other branch
+    let out = Command::new(evm_bin())
+        .args(["coverage", code, "--input", "0x", "--input", "0x01"])
+        .output()
+        .expect("run evm coverage");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("runs: 2\n"), "stdout={stdout}");
+    assert!(
+        stdout.contains("instructions 8/8 (100.00%), branches 2/2 (100.00%)"),
+        "stdout={stdout}"
+    );
+
+    // Without --input, the run uses the calldata encoded from --sig
+    let out = Command::new(evm_bin())
+        .args(["coverage", code, "--sig", "f()"])
+        .output()
+        .expect("run evm coverage");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("runs: 1\n"), "stdout={stdout}");
+    assert!(
+        stdout.contains("missed pcs: 0004 0006\n"),
+        "stdout={stdout}"
+    );
+    let _ = fs::remove_file(lcov_path);
+}
+
+#[test]
+fn evm_run_maps_failures_to_solidity_source() {
+    let dir = std::env::temp_dir().join(format!("evm_srcmap_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("T.sol"),
+        "contract T {\n    function f() public {\n        revert();\n    }\n}\n",
+    )
+    .unwrap();
+    // PUSH1 0; JUMP (to a non-JUMPDEST)
+    let combined = r#"{
+        "contracts": {"T.sol:T": {"bin-runtime": "600056", "srcmap-runtime": "17:41:0;47:8"}},
+        "sourceList": ["T.sol"]
+    }"#;
+    fs::write(dir.join("combined.json"), combined).unwrap();
+    let solc = dir.join("combined.json");
+    let root = dir.to_str().unwrap();
+    let out = Command::new(evm_bin())
+        .args([
+            "run",
+            "0x600056",
+            "--solc",
+            solc.to_str().unwrap(),
+            "--source-root",
+            root,
+        ])
+        .output()
+        .expect("run evm run --solc");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("invalid jump destination 0\n  --> T.sol:3:9\n   | revert()"),
+        "stderr={stderr}"
+    );
+
+    let out = Command::new(evm_bin())
+        .args([
+            "trace",
+            "0x600056",
+            "--solc",
+            solc.to_str().unwrap(),
+            "--source-root",
+            root,
+        ])
+        .output()
+        .expect("run evm trace --solc");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("T.sol:2:5 function f() public {"),
+        "stdout={stdout}"
+    );
+    assert!(stdout.contains("T.sol:3:9 revert()"), "stdout={stdout}");
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn evm_run_builds_calldata_from_sig_and_decodes_return() {
+    // Return calldataload(4) + calldataload(36)
+    let out = Command::new(evm_bin())
+        .args([
+            "run",
+            "0x6004356024350160005260206000f3",
+            "--sig",
+            "add(uint256,int256)",
+            "--args",
+            "50",
+            "-8",
+            "--ret",
+            "(uint256)",
+        ])
+        .output()
+        .expect("run evm run --sig");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("decoded: (42)"), "stdout={stdout}");
+
+    let out = Command::new(evm_bin())
+        .args([
+            "run",
+            "0x00",
+            "--sig",
+            "add(uint256,uint256)",
+            "--args",
+            "1",
+        ])
+        .output()
+        .expect("run evm run --sig");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("expected 2 arguments, got 1"),
+        "stderr={stderr}"
+    );
+
+    // The tracers print JSON only, so --ret is refused with them
+    let out = Command::new(evm_bin())
+        .args(["run", "0x00", "--call-trace", "--ret", "(uint256)"])
+        .output()
+        .expect("run evm run --call-trace --ret");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("cannot be used with"), "stderr={stderr}");
+}
+
+#[test]
+fn evm_run_refuses_abi_decoding_with_tracers() {
+    for args in [
+        ["--prestate", "--logs-json"],
+        ["--call-trace", "--abi=abi.json"],
+    ] {
+        let out = Command::new(evm_bin())
+            .args(["run", "0x00"])
+            .args(args)
+            .output()
+            .expect("run evm run");
+        assert!(!out.status.success());
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains("cannot be used with"), "stderr={stderr}");
+    }
+}
+
+#[test]
+fn evm_run_decodes_panics_and_custom_errors() {
+    // MSTORE(0, selector); MSTORE(0x20, arg); REVERT(0x1c, 0x24)
+    let revert_with =
+        |selector: &str, arg: &str| format!("0x63{selector}60005260{arg}6020526024601cfd");
+    let out = Command::new(evm_bin())
+        .args(["run", &revert_with("4e487b71", "32")])
+        .output()
+        .expect("run evm run");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("revert: panic 0x32: array index out of bounds"),
+        "stdout={stdout}"
+    );
+
+    let abi = r#"{"abi": [{"type": "error", "name": "TooLow", "inputs": [{"name": "have", "type": "uint256"}]}]}"#;
+    let abi_path = write_temp_text("evm_cli_abi", abi);
+    let error = evm_in_rust::Signature::parse("error TooLow(uint256)").unwrap();
+    let code = revert_with(&evm_in_rust::hexutil::hex(&error.selector()), "07");
+    let out = Command::new(evm_bin())
+        .args(["run", &code, "--abi", abi_path.to_str().unwrap()])
+        .output()
+        .expect("run evm run --abi");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("revert: TooLow(have: 7)"),
+        "stdout={stdout}"
+    );
+
+    let out = Command::new(evm_bin())
+        .args(["trace", &code, "--abi", abi_path.to_str().unwrap()])
+        .output()
+        .expect("run evm trace --abi");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("-- halt: REVERT --"), "stdout={stdout}");
+    assert!(
+        stdout.contains("revert: TooLow(have: 7)"),
+        "stdout={stdout}"
+    );
+
+    let mut child = Command::new(evm_bin())
+        .args(["debug", &code, "--abi", abi_path.to_str().unwrap()])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn evm debug --abi");
+    child.stdin.take().unwrap().write_all(b"c\n").unwrap();
+    let out = child.wait_with_output().expect("run evm debug --abi");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("halted: REVERT"), "stdout={stdout}");
+    assert!(
+        stdout.contains("revert: TooLow(have: 7)"),
+        "stdout={stdout}"
+    );
+}
+
+#[test]
+fn evm_run_decodes_logs_with_abi() {
+    let abi = r#"[{"type": "event", "name": "Transfer", "anonymous": false, "inputs": [
+        {"name": "from", "type": "address", "indexed": true},
+        {"name": "to", "type": "address", "indexed": true},
+        {"name": "value", "type": "uint256", "indexed": false}
+    ]}]"#;
+    let abi_path = write_temp_text("evm_cli_events", abi);
+    // MSTORE(0, 100); LOG3(0, 0x20, topic0, 0xaa, 0xbb)
+    let topic = "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    let code = format!("0x606460005260bb60aa7f{topic}60206000a300");
+    let out = Command::new(evm_bin())
+        .args([
+            "run",
+            &code,
+            "--abi",
+            abi_path.to_str().unwrap(),
+            "--logs-json",
+        ])
+        .output()
+        .expect("run evm run --abi");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let from = format!("0x{}aa", "0".repeat(38));
+    let to = format!("0x{}bb", "0".repeat(38));
+    assert!(
+        stdout.contains(&format!("Transfer(from: {from}, to: {to}, value: 100)")),
+        "stdout={stdout}"
+    );
+    let json: serde_json::Value =
+        serde_json::from_str(&stdout[stdout.find("[\n").expect("logs json")..]).unwrap();
+    assert_eq!(json[0]["signature"], "Transfer(address,address,uint256)");
+    assert_eq!(json[0]["args"][1]["value"], to);
+
+    // Without the ABI the log is printed raw
+    let out = Command::new(evm_bin())
+        .args(["run", &code])
+        .output()
+        .expect("run evm run");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains(&format!("topics=[0x{topic}")),
+        "stdout={stdout}"
+    );
+}
+
+#[test]
+fn evm_debug_runs_scripted_session() {
+    use std::process::Stdio;
+    // PUSH1 1; PUSH1 2; ADD; PUSH1 3; ADD; STOP
+    let mut child = Command::new(evm_bin())
+        .args(["debug", "0x600160020160030100"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn evm debug");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"b op ADD\nc\nstack\nn\nwhere\nd 0\nc\n")
+        .unwrap();
+    let out = child.wait_with_output().expect("run evm debug");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("[0] - 0004: ADD"), "stdout={stdout}");
+    assert!(stdout.contains("[0] 0x2\n[1] 0x1"), "stdout={stdout}");
+    assert!(stdout.contains("[0] - 0005: PUSH1 0x03"), "stdout={stdout}");
+    assert!(stdout.contains("halted: STOP"), "stdout={stdout}");
+    assert!(stdout.contains("gas used: 15"), "stdout={stdout}");
+}